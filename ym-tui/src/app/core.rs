@@ -1,9 +1,295 @@
 //! Основная структура приложения и его жизненный цикл.
 
-use crate::settings;
+use crate::settings::{self, ModelOverrides, Preferences};
 use crossterm::event::EventStream;
+use futures::{Stream, StreamExt};
 use ratatui::DefaultTerminal;
+use std::collections::VecDeque;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::AbortHandle;
 use ym_yagpt::client::GPTClient;
+use ym_yagpt::errors::GPTError;
+use ym_yagpt::models::{AccessData, GPTAnswer, StreamEvent};
+
+use super::history::{self, ChatEntry, History};
+use super::messaging::{self, GREETING, NetworkRetry, Reply, ReplyKind, SystemClipboard};
+use super::search::Search;
+use super::stats::SessionStats;
+use super::ui::{self, RenderedLines};
+
+/// Период обновления интерфейса в отсутствие событий ввода (отсчёты, таймеры, индикатор
+/// ожидания ответа).
+const TICK_RATE: Duration = Duration::from_millis(100);
+
+/// Пауза в наборе, после которой токены поля ввода считаются через API.
+const TOKEN_COUNT_DELAY: Duration = Duration::from_millis(700);
+
+/// Источник очередной итерации главного цикла.
+enum LoopEvent {
+    /// Событие терминала (клавиатура, мышь, изменение размера).
+    Input(Option<std::io::Result<crossterm::event::Event>>),
+    /// Периодический тик.
+    Tick,
+    /// Ответ фонового запроса к модели.
+    Reply(Reply),
+    /// ОС просит процесс завершиться (SIGTERM, закрытие консоли в Windows).
+    Terminate,
+}
+
+/// Подписка на сигнал завершения процесса от ОС: SIGTERM в Unix.
+#[cfg(unix)]
+fn termination_signal() -> std::io::Result<tokio::signal::unix::Signal> {
+    use tokio::signal::unix::{SignalKind, signal};
+    signal(SignalKind::terminate())
+}
+
+/// Подписка на сигнал завершения процесса от ОС: закрытие окна консоли в Windows.
+#[cfg(windows)]
+fn termination_signal() -> std::io::Result<tokio::signal::windows::CtrlClose> {
+    tokio::signal::windows::ctrl_close()
+}
+
+/// Сколько ранее использованных моделей помнит приложение.
+const RECENT_MODELS_LIMIT: usize = 5;
+
+/// Стек ранее использованных моделей (последняя — на вершине).
+#[derive(Debug, Default, Clone)]
+pub struct RecentModels {
+    stack: Vec<String>,
+}
+
+impl RecentModels {
+    /// Запомнить модель, от которой переключились. Повторы поднимаются на вершину стека.
+    pub fn remember(&mut self, model: &str) {
+        self.stack.retain(|m| m != model);
+        self.stack.push(model.to_string());
+        if self.stack.len() > RECENT_MODELS_LIMIT {
+            self.stack.remove(0);
+        }
+    }
+
+    /// Предыдущая использованная модель, если она есть.
+    pub fn previous(&self) -> Option<&str> {
+        self.stack.last().map(String::as_str)
+    }
+}
+
+/// История отправленных сообщений: вызывается в поле ввода стрелками, как в командной оболочке.
+#[derive(Debug, Default, Clone)]
+pub struct InputHistory {
+    entries: Vec<String>,
+    // Запись, выведенная в поле ввода; `None` — в поле черновик пользователя.
+    position: Option<usize>,
+    // Текст поля ввода до начала перехода по истории.
+    draft: String,
+}
+
+impl InputHistory {
+    /// Запомнить отправленный текст. Повтор предыдущей записи не добавляется.
+    ///
+    /// Переход по истории начинается заново.
+    pub fn push(&mut self, text: &str) {
+        if self.entries.last().map(String::as_str) != Some(text) {
+            self.entries.push(text.to_string());
+        }
+        self.position = None;
+        self.draft.clear();
+    }
+
+    /// Запись старше выведенной; `current` — текст поля ввода, на первом шаге он
+    /// сохраняется как черновик. `None`, если старше записей нет.
+    pub fn older(&mut self, current: &str) -> Option<&str> {
+        let position = match self.position {
+            None if self.entries.is_empty() => return None,
+            None => {
+                self.draft = current.to_string();
+                self.entries.len() - 1
+            }
+            Some(0) => return None,
+            Some(position) => position - 1,
+        };
+        self.position = Some(position);
+        Some(&self.entries[position])
+    }
+
+    /// Запись новее выведенной, а после самой новой — сохранённый черновик.
+    /// `None`, если в поле ввода уже черновик.
+    pub fn newer(&mut self) -> Option<&str> {
+        let position = self.position?;
+        if position + 1 < self.entries.len() {
+            self.position = Some(position + 1);
+            Some(&self.entries[position + 1])
+        } else {
+            self.position = None;
+            Some(&self.draft)
+        }
+    }
+}
+
+/// Точный подсчёт токенов поля ввода через API: после паузы в наборе и не больше одного
+/// запроса одновременно.
+#[derive(Debug, Default)]
+pub struct TokenCounter {
+    // Текст поля ввода на последнем тике и момент, с которого он не менялся.
+    seen: Option<(String, Instant)>,
+    // Подсчитанный текст и число токенов; `None` — подсчитать не удалось.
+    counted: Option<(String, Option<u32>)>,
+    // Текст, который сейчас считается, и канал результата.
+    pending: Option<(String, oneshot::Receiver<Option<u32>>)>,
+}
+
+impl TokenCounter {
+    /// Число токенов в `text`, если он уже подсчитан.
+    pub fn tokens(&self, text: &str) -> Option<u32> {
+        match &self.counted {
+            Some((counted, tokens)) if counted == text => *tokens,
+            _ => None,
+        }
+    }
+
+    /// Отметить текст поля ввода на очередном тике. Истинно, если текст пора подсчитать:
+    /// он не менялся [`TOKEN_COUNT_DELAY`], не пуст, не команда и ещё не считался.
+    fn due(&mut self, text: &str, now: Instant) -> bool {
+        let since = match &self.seen {
+            Some((seen, since)) if seen == text => *since,
+            _ => {
+                self.seen = Some((text.to_string(), now));
+                now
+            }
+        };
+        let counted = matches!(&self.counted, Some((counted, _)) if counted == text);
+        !text.trim().is_empty()
+            && !text.starts_with('/')
+            && self.pending.is_none()
+            && !counted
+            && now.duration_since(since) >= TOKEN_COUNT_DELAY
+    }
+
+    /// Забрать результат подсчёта, если он готов.
+    fn poll(&mut self) {
+        let Some((_, receiver)) = &mut self.pending else {
+            return;
+        };
+        let tokens = match receiver.try_recv() {
+            Ok(tokens) => tokens,
+            Err(oneshot::error::TryRecvError::Empty) => return,
+            Err(oneshot::error::TryRecvError::Closed) => None,
+        };
+        if let Some((text, _)) = self.pending.take() {
+            self.counted = Some((text, tokens));
+        }
+    }
+}
+
+/// Состояние соединения с API для индикатора в статус-баре.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionStatus {
+    /// Идёт проверка.
+    #[default]
+    Checking,
+    /// Последний запрос прошёл успешно.
+    Connected,
+    /// Нет сети или API не отвечает.
+    Offline,
+    /// API отклонил ключ.
+    InvalidKey,
+    /// API ответил ошибкой другого рода.
+    Failed,
+}
+
+impl ConnectionStatus {
+    /// Состояние по результату запроса к API.
+    pub fn from_result<T>(result: &Result<T, GPTError>) -> Self {
+        match result {
+            Ok(_) => Self::Connected,
+            Err(GPTError::Network { .. }) => Self::Offline,
+            Err(GPTError::InvalidCredential) => Self::InvalidKey,
+            Err(_) => Self::Failed,
+        }
+    }
+
+    /// Подпись индикатора.
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Checking => "проверка…",
+            Self::Connected => "подключено",
+            Self::Offline => "нет сети",
+            Self::InvalidKey => "ключ неверен",
+            Self::Failed => "ошибка API",
+        }
+    }
+}
+
+/// Канал, по которому фоновые запросы к модели возвращают ответы в главный цикл,
+/// и номер запроса, ответ на который ожидается.
+#[derive(Debug)]
+struct ReplyChannel {
+    sender: mpsc::UnboundedSender<Reply>,
+    receiver: mpsc::UnboundedReceiver<Reply>,
+    /// Номер последнего запущенного запроса.
+    last_id: u64,
+    /// Ожидаемый запрос; `None`, если модель ничего не готовит или запрос отменён.
+    awaited: Option<u64>,
+}
+
+impl Default for ReplyChannel {
+    fn default() -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        Self {
+            sender,
+            receiver,
+            last_id: 0,
+            awaited: None,
+        }
+    }
+}
+
+impl ReplyChannel {
+    /// Зарегистрировать новый запрос и вернуть его номер. Прежний ожидаемый запрос
+    /// перестаёт ожидаться.
+    fn start(&mut self) -> u64 {
+        self.last_id += 1;
+        self.awaited = Some(self.last_id);
+        self.last_id
+    }
+
+    /// Перестать ждать текущий запрос.
+    fn cancel(&mut self) {
+        self.awaited = None;
+    }
+
+    /// Ожидается ли ответ.
+    fn is_waiting(&self) -> bool {
+        self.awaited.is_some()
+    }
+
+    /// Ожидается ли ответ на запрос `id`.
+    fn awaits(&self, id: u64) -> bool {
+        self.awaited == Some(id)
+    }
+
+    /// Принять ответ, если он на ожидаемый запрос.
+    fn accept(&mut self, reply: &Reply) -> bool {
+        if !self.awaits(reply.id) {
+            return false;
+        }
+        self.awaited = None;
+        true
+    }
+}
+
+/// Область интерфейса, которой адресованы нажатия клавиш навигации.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Focus {
+    /// Поле ввода: стрелки двигают курсор.
+    #[default]
+    Input,
+    /// История диалога: стрелки прокручивают сообщения.
+    History,
+}
 
 /// Структура, содержащая данные для рендеринга окна терминала.
 #[derive(Debug, Default)]
@@ -13,43 +299,463 @@ pub struct App {
     // Event stream.
     pub event_stream: EventStream,
     // История сообщений с нейросетью.
-    pub messages: Vec<String>,
+    pub messages: History,
     // Буфер ввода от пользователя.
     pub input_buffer: String,
     // Позиция курсора.
     pub cursor_pos: usize,
     // Контроллер скроллинга.
     pub scroll_offset: u16,
+    // Следовать за новыми сообщениями: ложно, пока пользователь читает историю выше.
+    pub follow_tail: bool,
+    // Высота видимой области истории при последней отрисовке (шаг PageUp/PageDown).
+    pub history_height: u16,
+    // Число экранных строк истории (после переноса) при последней отрисовке.
+    pub history_lines: usize,
+    // Показывать ответы модели исходным текстом, без разбора markdown.
+    pub raw_markdown: bool,
+    // Сообщения, отправленные, пока модель отвечала: уходят по одному после ответа.
+    pub pending_inputs: VecDeque<String>,
+    // Ширина текста в поле ввода при последней отрисовке (перенос строк для Home/End).
+    pub input_width: u16,
     pub gpt_client: GPTClient,
+    // Запланированный повтор запроса после потери сети.
+    pub network_retry: Option<NetworkRetry>,
+    // Ранее использованные модели для быстрого переключения.
+    pub recent_models: RecentModels,
+    // Отправленные сообщения для вызова стрелками в поле ввода.
+    pub input_history: InputHistory,
+    // Фоновая задача, принимающая ответ по частям (`stream` в настройках).
+    pub stream_task: Option<AbortHandle>,
+    // Получена хотя бы часть ответа, который приходит по частям.
+    pub receiving: bool,
+    // Активная область интерфейса.
+    pub focus: Focus,
+    // Поиск по истории диалога.
+    pub search: Search,
+    // Пользовательские настройки интерфейса.
+    pub prefs: Preferences,
+    // Текст повторного сообщения, отправку которого пользователь должен подтвердить.
+    pub pending_duplicate: Option<String>,
+    // Существующий файл, перезапись которого `/save` или `/export` ждёт повтором команды.
+    pub pending_overwrite: Option<PathBuf>,
+    // Открытый запрос подтверждения отправки: текст с оценкой токенов.
+    pub send_confirmation: Option<String>,
+    // Состояние соединения с API.
+    pub connection: ConnectionStatus,
+    // Точное число токенов в поле ввода (`count_tokens` в настройках).
+    pub token_counter: TokenCounter,
+    // Результат фоновой проверки соединения, если она ещё идёт.
+    ping_result: Option<oneshot::Receiver<ConnectionStatus>>,
+    // Ответы фоновых запросов к модели и номер ожидаемого запроса.
+    replies: ReplyChannel,
+    // Счётчик тиков главного цикла: задаёт кадр индикатора ожидания.
+    pub tick: u64,
+    // Статистика текущей сессии.
+    pub stats: SessionStats,
+    // Все варианты последнего ответа, если модель вернула несколько.
+    pub alternatives: Vec<String>,
+    // Системный буфер обмена для копирования ответов.
+    pub clipboard: SystemClipboard,
+    // Отрисованные строки записей истории с прошлых кадров.
+    pub rendered: RenderedLines,
+    // Терминал поддерживает 24-битный цвет (`COLORTERM`, читается при старте).
+    pub truecolor: bool,
 }
 
 impl App {
     /// Создание нового экземпляра [`App`].
     pub fn new() -> Self {
+        let mut messages = vec![ChatEntry::info(GREETING)];
+        // Без данных доступа интерфейс всё равно запускается: можно поправить файл
+        // и выполнить /reload, не перезапуская приложение.
+        let access = match AccessData::load_checked(&settings::access_file_path()) {
+            Ok((access, warnings)) => {
+                messages.extend(warnings.into_iter().map(ChatEntry::system));
+                access
+            }
+            Err(err) => {
+                messages.push(ChatEntry::system(format!(
+                    "Не удалось загрузить данные доступа ({}). Исправьте файл и выполните \
+                     /reload или перезапустите приложение с ключом --init.",
+                    err
+                )));
+                AccessData::default()
+            }
+        };
+        let (prefs, warning) = Preferences::load(&settings::prefs_file_path());
+        messages.extend(warning.map(ChatEntry::system));
+        let client = GPTClient::new()
+            .with_access(access)
+            .with_alternatives(prefs.alternatives.max(1))
+            .with_max_history_exchanges(prefs.max_history_exchanges)
+            .with_data_logging(prefs.data_logging);
+        let gpt_client = prefs.model.apply(client.clone()).unwrap_or_else(|err| {
+            messages.push(ChatEntry::system(format!(
+                "Параметры модели из настроек не применены: {}",
+                err
+            )));
+            client
+        });
+        match history::load_entries(&settings::history_file_path()) {
+            Ok(saved) if !saved.is_empty() => messages.push(ChatEntry::system(format!(
+                "Найден сохранённый диалог ({} сообщ.). Продолжить его: /resume.",
+                saved.len()
+            ))),
+            Ok(_) => {}
+            Err(err) => messages.push(ChatEntry::system(format!(
+                "Сохранённый диалог не загружен, начинаем заново: {}",
+                err
+            ))),
+        }
+
         Self {
             running: true,
             event_stream: EventStream::new(),
-            messages: vec!["YandexGPT готов к диалогу.".to_string()],
+            messages: History::from_entries(messages),
             input_buffer: String::new(),
             cursor_pos: 0,
             scroll_offset: 0,
-            gpt_client: GPTClient::new().load_auth(settings::access_file_path()),
+            follow_tail: true,
+            history_height: 0,
+            history_lines: 0,
+            raw_markdown: false,
+            pending_inputs: VecDeque::new(),
+            input_width: 0,
+            gpt_client,
+            network_retry: None,
+            recent_models: RecentModels::default(),
+            input_history: InputHistory::default(),
+            stream_task: None,
+            receiving: false,
+            focus: Focus::Input,
+            search: Search::default(),
+            prefs,
+            pending_duplicate: None,
+            pending_overwrite: None,
+            send_confirmation: None,
+            connection: ConnectionStatus::default(),
+            token_counter: TokenCounter::default(),
+            ping_result: None,
+            replies: ReplyChannel::default(),
+            tick: 0,
+            stats: SessionStats::default(),
+            alternatives: Vec::new(),
+            clipboard: SystemClipboard::default(),
+            rendered: RenderedLines::default(),
+            truecolor: ui::supports_truecolor(),
+        }
+    }
+
+    /// Включить или выключить компактный интерфейс и запомнить выбор в настройках.
+    pub fn set_compact(&mut self, compact: bool) {
+        self.prefs.compact = compact;
+        self.save_prefs();
+    }
+
+    /// Показать или скрыть время сообщений в истории и запомнить выбор в настройках.
+    pub fn toggle_timestamps(&mut self) {
+        self.prefs.show_timestamps = !self.prefs.show_timestamps;
+        self.save_prefs();
+    }
+
+    /// Переключить вид ответов модели: отрисованный markdown или исходный текст.
+    ///
+    /// Действует до конца сессии; история перерисовывается без повторного запроса.
+    pub fn toggle_raw_markdown(&mut self) {
+        self.raw_markdown = !self.raw_markdown;
+    }
+
+    /// Переопределить параметры модели на время сессии (ключи командной строки).
+    ///
+    /// При ошибке клиент не меняется.
+    pub fn override_model(&mut self, overrides: &ModelOverrides) -> Result<(), String> {
+        self.gpt_client = overrides.apply(self.gpt_client.clone())?;
+        Ok(())
+    }
+
+    /// Сохранить пользовательские настройки. Ошибка записи не прерывает работу.
+    pub fn save_prefs(&mut self) {
+        if let Err(err) = self.prefs.save(&settings::prefs_file_path()) {
+            let notice = format!("Не удалось сохранить настройки: {}", err);
+            super::messaging::add_system_message(self, &notice);
+        }
+    }
+
+    /// Сохранить диалог в файл: только реплики пользователя и ответы модели, без приветствия,
+    /// служебных записей и ошибок.
+    pub fn save_history(&self, path: &Path) -> io::Result<()> {
+        history::save_entries(path, &super::messaging::normalized_history(self))
+    }
+
+    /// Заменить текущий диалог сохранённым в файле. Возвращает число восстановленных записей.
+    ///
+    /// При ошибке чтения текущий диалог не меняется.
+    pub fn load_history(&mut self, path: &Path) -> Result<usize, String> {
+        let entries = history::load_entries(path)?;
+        let count = entries.len();
+        super::messaging::clear_messages(self);
+        for entry in entries {
+            self.messages.push(entry);
+        }
+        Ok(count)
+    }
+
+    /// Перечитать данные доступа и настройки интерфейса без перезапуска.
+    ///
+    /// Применяется всё или ничего: при ошибке в любом из файлов клиент и настройки остаются
+    /// прежними. Возвращает предупреждения о файле доступа.
+    pub fn reload_config(&mut self) -> Result<Vec<String>, String> {
+        let prefs = Preferences::load_checked(&settings::prefs_file_path())?;
+        let warnings = reload_access(&mut self.gpt_client, &settings::access_file_path())
+            .map_err(|err| err.to_string())?;
+        self.gpt_client.gpt_options.alternatives = prefs.alternatives.max(1);
+        self.gpt_client.gpt_options.max_history_exchanges = prefs.max_history_exchanges;
+        let client = self.gpt_client.clone();
+        self.gpt_client = client.with_data_logging(prefs.data_logging);
+        self.prefs = prefs;
+        Ok(warnings)
+    }
+
+    /// Переключиться на профиль доступа `name`: данные доступа клиента заменяются данными
+    /// профиля, модель и параметры генерации сохраняются.
+    ///
+    /// При ошибке клиент и активный профиль остаются прежними. С `--config` переключение
+    /// отклоняется (см. [`settings::check_profile_switch`]). Возвращает предупреждения
+    /// о файле профиля.
+    pub fn switch_profile(&mut self, name: &str) -> Result<Vec<String>, String> {
+        settings::check_profile_switch()?;
+        let path = settings::profile_file_path(name)?;
+        if !path.exists() {
+            return Err(format!(
+                "Профиль {} не найден. Создайте его: ym --profile {} --init",
+                name, name
+            ));
+        }
+        let (access, warnings) = settings::load_named(name)?;
+        self.gpt_client.access = access;
+        settings::set_active_profile(Some(name.to_string()));
+        Ok(warnings)
+    }
+
+    /// Запустить фоновую проверку соединения. Результат забирает [`App::poll_ping`].
+    pub fn start_ping(&mut self) {
+        let client = self.gpt_client.clone();
+        let (sender, receiver) = oneshot::channel();
+        tokio::spawn(async move {
+            let _ = sender.send(ConnectionStatus::from_result(&client.ping().await));
+        });
+
+        self.connection = ConnectionStatus::Checking;
+        self.ping_result = Some(receiver);
+    }
+
+    /// Применить результат фоновой проверки соединения, если он готов.
+    pub fn poll_ping(&mut self) {
+        let Some(receiver) = &mut self.ping_result else {
+            return;
+        };
+        match receiver.try_recv() {
+            Ok(status) => {
+                self.connection = status;
+                self.ping_result = None;
+            }
+            Err(oneshot::error::TryRecvError::Empty) => {}
+            Err(oneshot::error::TryRecvError::Closed) => self.ping_result = None,
+        }
+    }
+
+    /// Забрать готовый подсчёт токенов поля ввода и после паузы в наборе запустить новый.
+    ///
+    /// Ошибка подсчёта не показывается: статус-бар остаётся с приблизительной оценкой.
+    pub fn poll_token_count(&mut self) {
+        self.token_counter.poll();
+        if !self.prefs.count_tokens || !self.gpt_client.access.has_data() {
+            return;
+        }
+        if !self.token_counter.due(&self.input_buffer, Instant::now()) {
+            return;
+        }
+
+        let client = self.gpt_client.clone();
+        let text = self.input_buffer.clone();
+        let (sender, receiver) = oneshot::channel();
+        tokio::spawn(async move {
+            let _ = sender.send(client.count_tokens(&text).await.ok());
+        });
+        self.token_counter.pending = Some((self.input_buffer.clone(), receiver));
+    }
+
+    /// Запустить запрос к модели в фоне. Ответ придёт в главный цикл и будет передан
+    /// в [`messaging::handle_reply`](super::messaging::handle_reply).
+    ///
+    /// Пока запрос выполняется, [`App::is_loading`] истинно.
+    pub fn begin_request<F>(&mut self, request: F)
+    where
+        F: Future<Output = ReplyKind> + Send + 'static,
+    {
+        let id = self.replies.start();
+        spawn_request(
+            id,
+            self.model().to_string(),
+            self.replies.sender.clone(),
+            request,
+        );
+    }
+
+    /// Запустить в фоне запрос ответа по частям. Каждый фрагмент приходит в главный цикл
+    /// отдельным [`ReplyKind::Delta`], а завершение потока — ответом, который построит `finish`
+    /// из итогового ответа модели.
+    pub fn begin_stream<F, S, E>(&mut self, request: F, finish: E)
+    where
+        F: Future<Output = Result<S, GPTError>> + Send + 'static,
+        S: Stream<Item = Result<StreamEvent, GPTError>> + Send + 'static,
+        E: FnOnce(Result<GPTAnswer, GPTError>) -> ReplyKind + Send + 'static,
+    {
+        let id = self.replies.start();
+        self.receiving = false;
+        self.stream_task = Some(spawn_stream(
+            id,
+            self.model().to_string(),
+            self.replies.sender.clone(),
+            request,
+            finish,
+        ));
+    }
+
+    /// Идёт запрос к модели: интерфейс показывает индикатор и не отправляет новые сообщения.
+    pub fn is_loading(&self) -> bool {
+        self.replies.is_waiting()
+    }
+
+    /// Идёт запрос ответа по частям: `Esc` прерывает его.
+    pub fn is_streaming(&self) -> bool {
+        self.is_loading() && self.stream_task.is_some()
+    }
+
+    /// Перестать ждать текущий запрос: его ответ будет отброшен, приём ответа по частям
+    /// останавливается, очередь сообщений сбрасывается.
+    pub fn cancel_request(&mut self) {
+        self.pending_inputs.clear();
+        self.replies.cancel();
+        self.receiving = false;
+        if let Some(task) = self.stream_task.take() {
+            task.abort();
+        }
+    }
+
+    /// Принять фрагмент ответа, если он относится к ожидаемому запросу. Признак загрузки
+    /// сохраняется до конца потока.
+    pub fn accept_delta(&self, reply: &Reply) -> bool {
+        self.replies.awaits(reply.id)
+    }
+
+    /// Принять ответ, если это ответ на ожидаемый запрос, и снять признак загрузки.
+    ///
+    /// Ответы отменённых и устаревших запросов не принимаются.
+    pub fn accept_reply(&mut self, reply: &Reply) -> bool {
+        let accepted = self.replies.accept(reply);
+        if accepted {
+            self.stream_task = None;
+        }
+        accepted
+    }
+
+    /// Обновить состояние соединения по результату запроса к модели.
+    ///
+    /// Результат свежего запроса важнее незавершённой фоновой проверки, поэтому она отменяется.
+    pub fn update_connection<T>(&mut self, result: &Result<T, GPTError>) {
+        self.connection = ConnectionStatus::from_result(result);
+        self.ping_result = None;
+    }
+
+    /// Переключить фокус между полем ввода и историей.
+    pub fn toggle_focus(&mut self) {
+        self.focus = match self.focus {
+            Focus::Input => Focus::History,
+            Focus::History => Focus::Input,
+        };
+    }
+
+    /// Название активной модели.
+    pub fn model(&self) -> &str {
+        &self.gpt_client.gpt_options.model
+    }
+
+    /// Сохранить диалог в Markdown-файл `path`, чтобы поделиться им (`/export md`).
+    ///
+    /// В отличие от `/save`, файл предназначен для чтения, а не для загрузки обратно.
+    pub fn export_markdown(&self, path: &Path) -> io::Result<()> {
+        messaging::write_markdown(
+            path,
+            &self.messages.snapshot(),
+            self.model(),
+            &self.prefs.prefixes(),
+        )
+    }
+
+    /// Переключить клиента на модель `model`, запомнив текущую как предыдущую.
+    pub fn switch_model(&mut self, model: &str) {
+        if model == self.model() {
+            return;
+        }
+        let current = self.model().to_string();
+        self.recent_models.remember(&current);
+        self.gpt_client.gpt_options.model = model.to_string();
+    }
+
+    /// Вернуться к предыдущей модели. Повторный вызов возвращает текущую (как Alt+Tab).
+    ///
+    /// Возвращает `false`, если переключаться не на что.
+    pub fn toggle_model(&mut self) -> bool {
+        match self.recent_models.previous().map(str::to_string) {
+            Some(previous) => {
+                self.switch_model(&previous);
+                true
+            }
+            None => false,
         }
     }
 
     /// Запуск приложения `App` в асинхронном процессе.
     pub async fn run(mut self, mut terminal: DefaultTerminal) -> color_eyre::Result<()> {
-        use crate::app::{events, ui};
+        use crate::app::{events, messaging, ui};
+
+        let mut ticker = tokio::time::interval(TICK_RATE);
+        let mut terminate = termination_signal()?;
 
         self.running = true;
+        self.start_ping();
         while self.running {
             terminal.draw(|frame| ui::draw_interface(&mut self, frame))?;
 
-            // Обработка событий
-            if let Err(e) = events::handle_crossterm_events(&mut self).await {
-                eprintln!("Ошибка обработки событий: {}", e);
+            // Ожидание события ввода, тика, ответа модели или сигнала ОС. Запросы к модели
+            // выполняются в фоне, поэтому интерфейс не замирает в ожидании ответа.
+            let event = tokio::select! {
+                event = self.event_stream.next() => LoopEvent::Input(event),
+                _ = ticker.tick() => LoopEvent::Tick,
+                Some(reply) = self.replies.receiver.recv() => LoopEvent::Reply(reply),
+                _ = terminate.recv() => LoopEvent::Terminate,
+            };
+
+            match event {
+                LoopEvent::Input(event) => {
+                    if let Err(e) = events::handle_crossterm_events(&mut self, event) {
+                        eprintln!("Ошибка обработки событий: {}", e);
+                    }
+                }
+                LoopEvent::Tick => messaging::on_tick(&mut self),
+                LoopEvent::Reply(reply) => messaging::handle_reply(&mut self, reply),
+                // Выход из цикла штатный: терминал восстанавливает вызывающая сторона.
+                LoopEvent::Terminate => self.quit(),
             }
         }
+
+        // Диалог сохраняется при выходе, чтобы в следующий раз его можно было продолжить.
+        // Сообщить об ошибке записи уже негде: интерфейс закрывается.
+        if !messaging::normalized_history(&self).is_empty() {
+            let _ = self.save_history(&settings::history_file_path());
+        }
         Ok(())
     }
 
@@ -58,3 +764,391 @@ impl App {
         self.running = false;
     }
 }
+
+/// Выполнить запрос `id` к модели `model` в фоновой задаче и отправить ответ в `sender`.
+fn spawn_request<F>(id: u64, model: String, sender: mpsc::UnboundedSender<Reply>, request: F)
+where
+    F: Future<Output = ReplyKind> + Send + 'static,
+{
+    tokio::spawn(async move {
+        let started = Instant::now();
+        let kind = request.await;
+        // Приложение могло завершиться, пока шёл запрос: ответ тогда никому не нужен.
+        let _ = sender.send(Reply {
+            id,
+            model,
+            elapsed: started.elapsed(),
+            kind,
+        });
+    });
+}
+
+/// Выполнить потоковый запрос `id` к модели `model` в фоновой задаче.
+///
+/// Фрагменты ответа уходят в `sender` по мере получения, последним — результат `finish`.
+/// Прервать приём можно через возвращённый [`AbortHandle`].
+fn spawn_stream<F, S, E>(
+    id: u64,
+    model: String,
+    sender: mpsc::UnboundedSender<Reply>,
+    request: F,
+    finish: E,
+) -> AbortHandle
+where
+    F: Future<Output = Result<S, GPTError>> + Send + 'static,
+    S: Stream<Item = Result<StreamEvent, GPTError>> + Send + 'static,
+    E: FnOnce(Result<GPTAnswer, GPTError>) -> ReplyKind + Send + 'static,
+{
+    let task = tokio::spawn(async move {
+        let started = Instant::now();
+        let send = |kind| {
+            sender
+                .send(Reply {
+                    id,
+                    model: model.clone(),
+                    elapsed: started.elapsed(),
+                    kind,
+                })
+                .is_ok()
+        };
+
+        let result = async {
+            let mut events = std::pin::pin!(request.await?);
+            while let Some(event) = events.next().await {
+                match event? {
+                    StreamEvent::Delta(delta) => {
+                        // Приложение завершилось: дочитывать ответ незачем.
+                        if !send(ReplyKind::Delta(delta)) {
+                            break;
+                        }
+                    }
+                    StreamEvent::Done(answer) => return Ok(answer),
+                }
+            }
+            Err(GPTError::EmptyResponse)
+        }
+        .await;
+        send(finish(result));
+    });
+    task.abort_handle()
+}
+
+/// Заменить данные доступа клиента на прочитанные из `access_file`.
+///
+/// Модель и прочие параметры генерации сохраняются. При ошибке клиент не изменяется.
+fn reload_access(client: &mut GPTClient, access_file: &Path) -> Result<Vec<String>, GPTError> {
+    let (access, warnings) = AccessData::load_checked(access_file)?;
+    client.access = access;
+    Ok(warnings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// История ввода с записями `entries`, отправленными по порядку.
+    fn input_history(entries: &[&str]) -> InputHistory {
+        let mut history = InputHistory::default();
+        for entry in entries {
+            history.push(entry);
+        }
+        history
+    }
+
+    #[test]
+    fn test_token_counter_waits_for_pause() {
+        let start = Instant::now();
+        let mut counter = TokenCounter::default();
+
+        assert!(!counter.due("При", start));
+        assert!(!counter.due("Привет", start + TOKEN_COUNT_DELAY));
+        assert!(counter.due("Привет", start + TOKEN_COUNT_DELAY * 2));
+        assert!(!counter.due("/model", start));
+        assert!(!counter.due("/model", start + TOKEN_COUNT_DELAY));
+        assert!(!counter.due("  ", start + TOKEN_COUNT_DELAY * 3));
+    }
+
+    #[tokio::test]
+    async fn test_token_counter_keeps_result_for_its_text() {
+        let start = Instant::now();
+        let mut counter = TokenCounter::default();
+        let (sender, receiver) = oneshot::channel();
+        counter.pending = Some(("Привет".to_string(), receiver));
+
+        assert!(!counter.due("Привет", start + TOKEN_COUNT_DELAY));
+        counter.poll();
+        assert_eq!(counter.tokens("Привет"), None);
+
+        sender.send(Some(3)).unwrap();
+        counter.poll();
+        assert_eq!(counter.tokens("Привет"), Some(3));
+        assert_eq!(counter.tokens("Привет!"), None);
+        assert!(!counter.due("Привет", start + TOKEN_COUNT_DELAY * 2));
+    }
+
+    #[test]
+    fn test_input_history_up_and_down() {
+        let mut history = input_history(&["первый", "второй", "третий"]);
+
+        assert_eq!(history.older("черновик"), Some("третий"));
+        assert_eq!(history.older("третий"), Some("второй"));
+        assert_eq!(history.older("второй"), Some("первый"));
+        // Старше первой записи идти некуда.
+        assert_eq!(history.older("первый"), None);
+
+        assert_eq!(history.newer(), Some("второй"));
+        assert_eq!(history.newer(), Some("третий"));
+    }
+
+    #[test]
+    fn test_input_history_past_newest_restores_draft() {
+        let mut history = input_history(&["первый", "второй"]);
+
+        assert_eq!(history.newer(), None);
+        assert_eq!(history.older("недописанный вопрос"), Some("второй"));
+        assert_eq!(history.newer(), Some("недописанный вопрос"));
+        // Черновик уже в поле ввода.
+        assert_eq!(history.newer(), None);
+    }
+
+    #[test]
+    fn test_input_history_skips_consecutive_duplicates() {
+        let mut history = input_history(&["да", "да", "нет", "да"]);
+
+        assert_eq!(history.older(""), Some("да"));
+        assert_eq!(history.older(""), Some("нет"));
+        assert_eq!(history.older(""), Some("да"));
+        assert_eq!(history.older(""), None);
+        assert_eq!(InputHistory::default().older("текст"), None);
+    }
+
+    #[test]
+    fn test_input_history_push_resets_navigation() {
+        let mut history = input_history(&["первый", "второй"]);
+        history.older("");
+        history.older("");
+        history.push("третий");
+
+        assert_eq!(history.older(""), Some("третий"));
+    }
+
+    #[test]
+    fn test_connection_status_from_result() {
+        let ok: Result<(), GPTError> = Ok(());
+        assert_eq!(
+            ConnectionStatus::from_result(&ok),
+            ConnectionStatus::Connected
+        );
+
+        let offline: Result<(), GPTError> = Err(GPTError::Network {
+            description: "timeout".to_string(),
+        });
+        assert_eq!(
+            ConnectionStatus::from_result(&offline),
+            ConnectionStatus::Offline
+        );
+
+        let denied: Result<(), GPTError> = Err(GPTError::InvalidCredential);
+        assert_eq!(
+            ConnectionStatus::from_result(&denied),
+            ConnectionStatus::InvalidKey
+        );
+
+        let failed: Result<(), GPTError> = Err(GPTError::EmptyResponse);
+        assert_eq!(
+            ConnectionStatus::from_result(&failed),
+            ConnectionStatus::Failed
+        );
+    }
+
+    #[test]
+    fn test_reload_access_replaces_credentials_only() {
+        let path = std::env::temp_dir().join("ym-reload-access.json");
+        std::fs::write(&path, r#"{"id_catalog": "b1gnew", "api_key": "new-key"}"#).unwrap();
+
+        let mut client = GPTClient::new()
+            .set_auth("b1gold".to_string(), "old-key".to_string())
+            .with_model("yandexgpt/rc");
+        let warnings = reload_access(&mut client, &path).unwrap();
+
+        assert!(warnings.is_empty());
+        assert_eq!(client.access.id_catalog, "b1gnew");
+        assert_eq!(client.access.api_key, "new-key");
+        assert_eq!(client.gpt_options.model, "yandexgpt/rc");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_reload_access_keeps_client_on_error() {
+        let path = std::env::temp_dir().join("ym-reload-access-broken.json");
+        std::fs::write(&path, r#"{"id_catalog": "b1gnew"}"#).unwrap();
+
+        let mut client = GPTClient::new().set_auth("b1gold".to_string(), "old-key".to_string());
+        assert!(reload_access(&mut client, &path).is_err());
+
+        assert_eq!(client.access.id_catalog, "b1gold");
+        assert_eq!(client.access.api_key, "old-key");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_recent_models_previous_is_last_remembered() {
+        let mut recent = RecentModels::default();
+        assert_eq!(recent.previous(), None);
+
+        recent.remember("yandexgpt/latest");
+        recent.remember("yandexgpt-pro");
+        assert_eq!(recent.previous(), Some("yandexgpt-pro"));
+    }
+
+    #[test]
+    fn test_recent_models_moves_duplicate_to_top() {
+        let mut recent = RecentModels::default();
+        recent.remember("a");
+        recent.remember("b");
+        recent.remember("a");
+        assert_eq!(recent.stack, vec!["b", "a"]);
+    }
+
+    #[test]
+    fn test_recent_models_is_bounded() {
+        let mut recent = RecentModels::default();
+        for i in 0..RECENT_MODELS_LIMIT + 2 {
+            recent.remember(&format!("model-{i}"));
+        }
+        assert_eq!(recent.stack.len(), RECENT_MODELS_LIMIT);
+        assert_eq!(recent.stack[0], "model-2");
+    }
+
+    fn reply(id: u64) -> Reply {
+        Reply {
+            id,
+            model: "yandexgpt/latest".to_string(),
+            elapsed: Duration::from_millis(10),
+            kind: ReplyKind::Continuation(Err(GPTError::EmptyResponse)),
+        }
+    }
+
+    #[test]
+    fn test_reply_channel_transitions() {
+        let mut replies = ReplyChannel::default();
+        assert!(!replies.is_waiting());
+
+        let cancelled = replies.start();
+        replies.cancel();
+        assert!(!replies.is_waiting());
+        let superseded = replies.start();
+        let current = replies.start();
+        assert!(replies.is_waiting());
+
+        // Ответы приходят через канал так же, как из фоновой задачи.
+        for id in [cancelled, superseded, current] {
+            replies.sender.send(reply(id)).unwrap();
+        }
+
+        for _ in 0..2 {
+            let stale = replies.receiver.try_recv().unwrap();
+            assert!(!replies.accept(&stale));
+            assert!(replies.is_waiting());
+        }
+        let fresh = replies.receiver.try_recv().unwrap();
+        assert!(replies.accept(&fresh));
+        assert!(!replies.is_waiting());
+        assert!(!replies.accept(&fresh));
+    }
+
+    #[tokio::test]
+    async fn test_spawned_request_delivers_reply() {
+        let mut replies = ReplyChannel::default();
+        let id = replies.start();
+        spawn_request(
+            id,
+            "yandexgpt/latest".to_string(),
+            replies.sender.clone(),
+            async { ReplyKind::Continuation(Err(GPTError::EmptyResponse)) },
+        );
+
+        let reply = replies.receiver.recv().await.unwrap();
+        assert_eq!(reply.id, id);
+        assert_eq!(reply.model, "yandexgpt/latest");
+        assert!(replies.accept(&reply));
+    }
+
+    #[tokio::test]
+    async fn test_spawned_stream_delivers_deltas_then_end() {
+        let mut replies = ReplyChannel::default();
+        let id = replies.start();
+        let deltas = futures::stream::iter([
+            Ok(StreamEvent::Delta("Пр".to_string())),
+            Ok(StreamEvent::Delta("ивет".to_string())),
+            Err(GPTError::Network {
+                description: "обрыв".to_string(),
+            }),
+            Ok(StreamEvent::Delta("лишнее".to_string())),
+        ]);
+        spawn_stream(
+            id,
+            "yandexgpt/latest".to_string(),
+            replies.sender.clone(),
+            async { Ok(deltas) },
+            |result| ReplyKind::StreamEnd {
+                result,
+                context_len: 2,
+                attempt: 1,
+            },
+        );
+
+        let mut received = Vec::new();
+        loop {
+            let reply = replies.receiver.recv().await.unwrap();
+            assert!(replies.awaits(reply.id));
+            match reply.kind {
+                ReplyKind::Delta(delta) => received.push(delta),
+                ReplyKind::StreamEnd { result, .. } => {
+                    // Поток обрывается на первой ошибке.
+                    assert!(matches!(result, Err(GPTError::Network { .. })));
+                    break;
+                }
+                other => panic!("Неожиданный ответ: {:?}", other),
+            }
+        }
+        assert_eq!(received, ["Пр", "ивет"]);
+    }
+
+    #[tokio::test]
+    async fn test_spawned_stream_ends_with_final_answer() {
+        let mut replies = ReplyChannel::default();
+        let id = replies.start();
+        let answer = GPTAnswer {
+            text: "Привет".to_string(),
+            truncated: true,
+            status: "ALTERNATIVE_STATUS_TRUNCATED_FINAL".to_string(),
+            input_tokens: 10,
+            completion_tokens: 2,
+        };
+        let events = futures::stream::iter([
+            Ok(StreamEvent::Delta("Привет".to_string())),
+            Ok(StreamEvent::Done(answer.clone())),
+        ]);
+        spawn_stream(
+            id,
+            "yandexgpt/latest".to_string(),
+            replies.sender.clone(),
+            async { Ok(events) },
+            |result| ReplyKind::StreamEnd {
+                result,
+                context_len: 2,
+                attempt: 1,
+            },
+        );
+
+        assert!(matches!(
+            replies.receiver.recv().await.unwrap().kind,
+            ReplyKind::Delta(_)
+        ));
+        match replies.receiver.recv().await.unwrap().kind {
+            ReplyKind::StreamEnd { result, .. } => assert_eq!(result.unwrap(), answer),
+            other => panic!("Неожиданный ответ: {:?}", other),
+        }
+    }
+}
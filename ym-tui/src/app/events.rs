@@ -1,22 +1,41 @@
 //! Обработка пользовательского ввода и событий TUI.
 
 use color_eyre::Result;
-use crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
-use futures::{FutureExt, StreamExt};
+use crossterm::event::{
+    Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, MouseEvent, MouseEventKind,
+};
+use std::io;
+use std::ops::Range;
 
 use super::core::App;
-use super::{clear_messages, messaging};
+use super::ui::{input_cursor, input_rows, max_scroll_offset};
+use super::{Focus, clear_messages, messaging};
+use crate::settings::SendOn;
 
-/// Считывание событий и обновление состояния приложения.
-pub async fn handle_crossterm_events(app: &mut App) -> Result<()> {
-    let event = app.event_stream.next().fuse().await;
+/// На сколько строк сдвигает историю один щелчок колеса мыши.
+const WHEEL_LINES: i32 = 3;
 
+/// Шаг изменения температуры сочетаниями `Ctrl+↑` / `Ctrl+↓`.
+const TEMPERATURE_STEP: f32 = 0.1;
+
+/// Что делает нажатие Enter в поле ввода.
+#[derive(Debug, PartialEq, Eq)]
+enum EnterAction {
+    /// Отправить сообщение.
+    Send,
+    /// Вставить перевод строки (`Shift + Enter`, `Alt + Enter`).
+    NewLine,
+}
+
+/// Обработка считанного события и обновление состояния приложения.
+pub fn handle_crossterm_events(app: &mut App, event: Option<io::Result<Event>>) -> Result<()> {
     if let Some(Ok(evt)) = event {
         match evt {
             Event::Key(key) if key.kind == KeyEventKind::Press => {
-                handle_key_event(app, key).await;
+                handle_key_event(app, key);
             }
-            Event::Mouse(_) => {}
+            Event::Paste(text) => paste_text(app, &text),
+            Event::Mouse(mouse) => handle_mouse_event(app, mouse),
             Event::Resize(_, _) => {}
             _ => {}
         }
@@ -26,8 +45,31 @@ pub async fn handle_crossterm_events(app: &mut App) -> Result<()> {
 }
 
 /// Обработка нажатий клавиш.
-pub async fn handle_key_event(app: &mut App, key: KeyEvent) {
+pub fn handle_key_event(app: &mut App, key: KeyEvent) {
+    // Пока открыт запрос подтверждения отправки, клавиши отвечают только на него.
+    if app.send_confirmation.is_some() {
+        // «н» — та же клавиша, что и «y», в русской раскладке.
+        let confirmed = matches!(
+            key.code,
+            KeyCode::Char('y' | 'Y' | 'н' | 'Н') | KeyCode::Enter
+        );
+        messaging::answer_send_confirmation(app, confirmed);
+        return;
+    }
+
+    // Пока набирается строка поиска, клавиши попадают в неё.
+    if app.search.editing {
+        handle_search_key(app, key);
+        return;
+    }
+
     match (key.modifiers, key.code) {
+        // Esc во время получения ответа по частям прерывает его.
+        (_, KeyCode::Esc) if app.is_streaming() => messaging::stop_stream(app),
+
+        // Esc при активном поиске снимает его подсветку.
+        (_, KeyCode::Esc) if app.search.is_active() => app.search.cancel(),
+
         // Выход.
         (_, KeyCode::Esc) | (KeyModifiers::CONTROL, KeyCode::Char('c') | KeyCode::Char('C')) => {
             app.quit()
@@ -38,45 +80,134 @@ pub async fn handle_key_event(app: &mut App, key: KeyEvent) {
             clear_messages(app);
         }
 
+        // Ctrl+U — очистить поле ввода целиком.
+        (KeyModifiers::CONTROL, KeyCode::Char('u') | KeyCode::Char('U')) => {
+            app.input_buffer.clear();
+            app.cursor_pos = 0;
+        }
+
+        // Ctrl+K — удалить текст от курсора до конца строки.
+        (KeyModifiers::CONTROL, KeyCode::Char('k') | KeyCode::Char('K')) => {
+            app.input_buffer = kill_to_line_end(&app.input_buffer, app.cursor_pos);
+        }
+
+        // Ctrl+Z — отменить последний обмен «вопрос — ответ».
+        (KeyModifiers::CONTROL, KeyCode::Char('z') | KeyCode::Char('Z')) => {
+            messaging::undo_last_exchange(app);
+        }
+
+        // Ctrl+O — взять последний ответ модели в поле ввода для доработки.
+        (KeyModifiers::CONTROL, KeyCode::Char('o') | KeyCode::Char('O')) => {
+            messaging::reuse_last_answer(app);
+        }
+
+        // Ctrl+Y — скопировать последний ответ модели в буфер обмена, Alt+Y — без разметки.
+        (KeyModifiers::CONTROL, KeyCode::Char('y') | KeyCode::Char('Y')) => {
+            messaging::copy_last_answer(app, false);
+        }
+        (KeyModifiers::ALT, KeyCode::Char('y') | KeyCode::Char('Y')) => {
+            messaging::copy_last_answer(app, true);
+        }
+
+        // Ctrl+T — показать или скрыть время сообщений.
+        (KeyModifiers::CONTROL, KeyCode::Char('t') | KeyCode::Char('T')) => {
+            app.toggle_timestamps();
+        }
+
+        // Ctrl+S — сохранить диалог, чтобы продолжить его в следующей сессии.
+        (KeyModifiers::CONTROL, KeyCode::Char('s') | KeyCode::Char('S')) => {
+            messaging::save_history(app);
+        }
+
+        // Alt+R — показывать ответы исходным текстом или отрисованным markdown.
+        (KeyModifiers::ALT, KeyCode::Char('r') | KeyCode::Char('R')) => {
+            app.toggle_raw_markdown();
+        }
+
+        // Переключение между текущей и предыдущей моделью.
+        (KeyModifiers::ALT, KeyCode::Char('m') | KeyCode::Char('M')) => {
+            if app.toggle_model() {
+                let notice = format!("Активная модель: {}", app.model());
+                messaging::add_system_message(app, &notice);
+            } else {
+                messaging::add_system_message(app, "Нет предыдущей модели для переключения.");
+            }
+        }
+
+        // Ctrl+↑ / Ctrl+↓ — температура генерации выше или ниже на шаг.
+        (KeyModifiers::CONTROL, KeyCode::Up) => change_temperature(app, TEMPERATURE_STEP),
+        (KeyModifiers::CONTROL, KeyCode::Down) => change_temperature(app, -TEMPERATURE_STEP),
+
+        // Ctrl+F, а в истории и `/` — поиск по истории.
+        (KeyModifiers::CONTROL, KeyCode::Char('f') | KeyCode::Char('F')) => start_search(app),
+        (KeyModifiers::NONE, KeyCode::Char('/')) if app.focus == Focus::History => {
+            start_search(app)
+        }
+
+        // n / N в истории — следующее и предыдущее совпадение поиска.
+        (_, KeyCode::Char(c @ ('n' | 'N')))
+            if app.focus == Focus::History && app.search.is_active() =>
+        {
+            let entries = app.messages.snapshot();
+            app.search.step(&entries, c == 'n');
+        }
+
+        // Переключение фокуса между полем ввода и историей.
+        (_, KeyCode::Tab) => app.toggle_focus(),
+
+        // Прокрутка истории, когда она в фокусе.
+        (_, KeyCode::Up) if app.focus == Focus::History => scroll_history(app, -1),
+        (_, KeyCode::Down) if app.focus == Focus::History => scroll_history(app, 1),
+        (_, KeyCode::Home) if app.focus == Focus::History => scroll_history(app, i32::MIN),
+        (_, KeyCode::End) if app.focus == Focus::History => scroll_history(app, i32::MAX),
+
+        // В поле ввода: на строку выше или ниже, а с крайней строки — по истории ввода.
+        (_, KeyCode::Up) => input_up(app),
+        (_, KeyCode::Down) => input_down(app),
+
+        // Постраничная прокрутка истории — при любом фокусе.
+        (_, KeyCode::PageUp) => scroll_history(app, -page_lines(app)),
+        (_, KeyCode::PageDown) => scroll_history(app, page_lines(app)),
+
         // Отправка сообщения.
-        (_, KeyCode::Enter) => messaging::send_message_to_gpt(app).await,
+        (modifiers, KeyCode::Enter) => match enter_action(app.prefs.send_on, modifiers) {
+            EnterAction::Send => messaging::send_message_to_gpt(app),
+            EnterAction::NewLine => insert_char_at_cursor(app, '\n'),
+        },
+
+        // Многие терминалы передают Ctrl+Enter как Ctrl+J.
+        (KeyModifiers::CONTROL, KeyCode::Char('j')) if app.prefs.send_on == SendOn::CtrlEnter => {
+            messaging::send_message_to_gpt(app)
+        }
 
         // Ctrl+Left — на слово назад.
         (KeyModifiers::CONTROL, KeyCode::Left) => {
-            let chars: Vec<char> = app.input_buffer.chars().collect();
-            app.cursor_pos = app.cursor_pos.saturating_sub(1);
-            while app.cursor_pos > 0 && chars[app.cursor_pos - 1].is_alphanumeric() {
-                app.cursor_pos -= 1;
-            }
+            app.cursor_pos = word_left(&app.input_buffer, app.cursor_pos);
         }
 
         // Ctrl+Right — на слово вперёд.
         (KeyModifiers::CONTROL, KeyCode::Right) => {
-            let chars: Vec<char> = app.input_buffer.chars().collect();
-            while app.cursor_pos < chars.len() && chars[app.cursor_pos].is_alphanumeric() {
-                app.cursor_pos += 1;
-            }
-            if app.cursor_pos < chars.len() {
-                app.cursor_pos += 1;
-            }
+            app.cursor_pos = word_right(&app.input_buffer, app.cursor_pos);
         }
 
         // Движение курсора.
-        (_, KeyCode::Left) => {
-            if app.cursor_pos > 0 {
-                app.cursor_pos -= 1;
-            }
+        (_, KeyCode::Left) if app.cursor_pos > 0 => {
+            app.cursor_pos -= 1;
         }
         (_, KeyCode::Right) => {
-            if app.cursor_pos < app.input_buffer.len() {
-                app.cursor_pos += 1;
-            }
+            app.cursor_pos = char_right(&app.input_buffer, app.cursor_pos);
         }
-        (_, KeyCode::Home) => {
+        (KeyModifiers::CONTROL, KeyCode::Home) => {
             app.cursor_pos = 0;
         }
+        (KeyModifiers::CONTROL, KeyCode::End) => {
+            app.cursor_pos = app.input_buffer.chars().count();
+        }
+        (_, KeyCode::Home) => {
+            app.cursor_pos = visual_line_start(&app.input_buffer, app.cursor_pos, app.input_width);
+        }
         (_, KeyCode::End) => {
-            app.cursor_pos = app.input_buffer.len();
+            app.cursor_pos = visual_line_end(&app.input_buffer, app.cursor_pos, app.input_width);
         }
 
         // Ввод текста.
@@ -98,31 +229,541 @@ pub async fn handle_key_event(app: &mut App, key: KeyEvent) {
     }
 }
 
+/// Начать набор строки поиска по истории; фокус переходит на историю для `n` / `N`.
+fn start_search(app: &mut App) {
+    app.search.start();
+    app.focus = Focus::History;
+}
+
+/// Обработка клавиш во время набора строки поиска.
+///
+/// Enter завершает набор и переходит к последнему совпадению, Esc отменяет поиск.
+fn handle_search_key(app: &mut App, key: KeyEvent) {
+    match key.code {
+        KeyCode::Esc => app.search.cancel(),
+        KeyCode::Enter => {
+            let entries = app.messages.snapshot();
+            app.search.confirm(&entries);
+        }
+        KeyCode::Backspace => {
+            app.search.query.pop();
+        }
+        KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+            app.search.query.push(c);
+        }
+        _ => {}
+    }
+}
+
+/// Обработка событий мыши: колесо прокручивает историю.
+fn handle_mouse_event(app: &mut App, mouse: MouseEvent) {
+    match mouse.kind {
+        MouseEventKind::ScrollUp => scroll_history(app, -WHEEL_LINES),
+        MouseEventKind::ScrollDown => scroll_history(app, WHEEL_LINES),
+        _ => {}
+    }
+}
+
+/// Шаг постраничной прокрутки: видимая высота истории, но не меньше одной строки.
+fn page_lines(app: &App) -> i32 {
+    i32::from(app.history_height.max(1))
+}
+
+/// Определить действие Enter с учётом режима отправки и модификаторов.
+///
+/// `Shift + Enter` и `Alt + Enter` всегда переносят строку.
+fn enter_action(send_on: SendOn, modifiers: KeyModifiers) -> EnterAction {
+    if modifiers.intersects(KeyModifiers::SHIFT | KeyModifiers::ALT) {
+        return EnterAction::NewLine;
+    }
+
+    match send_on {
+        SendOn::Enter => EnterAction::Send,
+        SendOn::CtrlEnter if modifiers.contains(KeyModifiers::CONTROL) => EnterAction::Send,
+        SendOn::CtrlEnter => EnterAction::NewLine,
+    }
+}
+
+/// Изменить температуру генерации на `delta` и сообщить новое значение в истории.
+fn change_temperature(app: &mut App, delta: f32) {
+    let previous = app.gpt_client.gpt_options.temperature;
+    let temperature = step_temperature(previous, delta);
+    app.gpt_client.gpt_options.temperature = temperature;
+
+    let notice = if temperature == previous {
+        format!("Температура уже {:.1} — это предел.", temperature)
+    } else {
+        format!("Температура: {:.1}", temperature)
+    };
+    messaging::add_system_message(app, &notice);
+}
+
+/// Температура после шага `delta` от `current`: округляется до десятых и не выходит
+/// за пределы `0.0..=1.0`.
+fn step_temperature(current: f32, delta: f32) -> f32 {
+    ((current + delta) * 10.0).round().clamp(0.0, 10.0) / 10.0
+}
+
+/// Прокрутить историю на `delta` экранных строк, не выходя за её пределы.
+///
+/// Прокрутка вверх отключает автопрокрутку к новым сообщениям, возврат к последней строке
+/// снова её включает.
+fn scroll_history(app: &mut App, delta: i32) {
+    let max_offset = usize::from(max_scroll_offset(app.history_lines, app.history_height));
+    let offset = scrolled_offset(app.scroll_offset, delta, max_offset);
+    app.scroll_offset = offset;
+
+    if usize::from(offset) >= max_offset {
+        app.follow_tail = true;
+    } else if delta < 0 {
+        app.follow_tail = false;
+    }
+}
+
+/// Смещение истории после сдвига на `delta` строк в пределах `0..=max_offset`.
+fn scrolled_offset(offset: u16, delta: i32, max_offset: usize) -> u16 {
+    let max_offset = max_offset.min(u16::MAX as usize) as i64;
+    (i64::from(offset) + i64::from(delta)).clamp(0, max_offset) as u16
+}
+
+/// Позиция (в символах) начала слова слева от курсора.
+///
+/// Пробелы и знаки препинания перед курсором пропускаются, затем курсор уходит в начало слова.
+fn word_left(buffer: &str, cursor: usize) -> usize {
+    let chars: Vec<char> = buffer.chars().collect();
+    let mut pos = cursor.min(chars.len());
+    while pos > 0 && !chars[pos - 1].is_alphanumeric() {
+        pos -= 1;
+    }
+    while pos > 0 && chars[pos - 1].is_alphanumeric() {
+        pos -= 1;
+    }
+    pos
+}
+
+/// Позиция (в символах) начала следующего слова справа от курсора.
+///
+/// Остаток текущего слова и следующие за ним пробелы и знаки препинания пропускаются.
+fn word_right(buffer: &str, cursor: usize) -> usize {
+    let chars: Vec<char> = buffer.chars().collect();
+    let mut pos = cursor.min(chars.len());
+    while pos < chars.len() && chars[pos].is_alphanumeric() {
+        pos += 1;
+    }
+    while pos < chars.len() && !chars[pos].is_alphanumeric() {
+        pos += 1;
+    }
+    pos
+}
+
+/// Стрелка вверх в поле ввода: на видимую строку выше, а с первой строки — к более старому
+/// сообщению из истории ввода.
+fn input_up(app: &mut App) {
+    if let Some(pos) = row_above(&app.input_buffer, app.cursor_pos, app.input_width) {
+        app.cursor_pos = pos;
+    } else if let Some(text) = app.input_history.older(&app.input_buffer) {
+        app.input_buffer = text.to_string();
+        app.cursor_pos = app.input_buffer.chars().count();
+    }
+}
+
+/// Стрелка вниз в поле ввода: на видимую строку ниже, а с последней строки — к более новому
+/// сообщению из истории ввода или к черновику.
+fn input_down(app: &mut App) {
+    if let Some(pos) = row_below(&app.input_buffer, app.cursor_pos, app.input_width) {
+        app.cursor_pos = pos;
+    } else if let Some(text) = app.input_history.newer() {
+        app.input_buffer = text.to_string();
+        app.cursor_pos = app.input_buffer.chars().count();
+    }
+}
+
+/// Позиция (в символах) в той же колонке на видимую строку выше; `None` на первой строке.
+fn row_above(buffer: &str, cursor: usize, width: u16) -> Option<usize> {
+    let rows = input_rows(buffer, usize::from(width));
+    let (row, col) = input_cursor(&rows, cursor);
+    let above = row.checked_sub(1)?;
+    Some((rows[above].start + col).min(row_last_pos(&rows, above)))
+}
+
+/// Позиция (в символах) в той же колонке на видимую строку ниже; `None` на последней строке.
+fn row_below(buffer: &str, cursor: usize, width: u16) -> Option<usize> {
+    let rows = input_rows(buffer, usize::from(width));
+    let (row, col) = input_cursor(&rows, cursor);
+    let below = row + 1;
+    rows.get(below)?;
+    Some((rows[below].start + col).min(row_last_pos(&rows, below)))
+}
+
+/// Позиция (в символах) начала видимой строки поля ввода шириной `width`, где стоит курсор.
+fn visual_line_start(buffer: &str, cursor: usize, width: u16) -> usize {
+    let rows = input_rows(buffer, usize::from(width));
+    let (row, _) = input_cursor(&rows, cursor);
+    rows[row].start
+}
+
+/// Позиция (в символах) конца видимой строки поля ввода шириной `width`, где стоит курсор.
+fn visual_line_end(buffer: &str, cursor: usize, width: u16) -> usize {
+    let rows = input_rows(buffer, usize::from(width));
+    let (row, _) = input_cursor(&rows, cursor);
+    row_last_pos(&rows, row)
+}
+
+/// Крайняя правая позиция курсора в видимой строке `row`.
+///
+/// Если строка буфера перенесена дальше, это последний символ видимой строки: позиция
+/// за ним уже относится к следующей.
+fn row_last_pos(rows: &[Range<usize>], row: usize) -> usize {
+    let end = rows[row].end;
+    match rows.get(row + 1) {
+        Some(next) if next.start == end && end > rows[row].start => end - 1,
+        _ => end,
+    }
+}
+
+/// Позиция (в символах) на символ правее курсора, но не дальше конца буфера.
+///
+/// Курсор считается в символах, а не в байтах: иначе на кириллице он уходил бы за конец текста.
+fn char_right(buffer: &str, cursor: usize) -> usize {
+    (cursor + 1).min(buffer.chars().count())
+}
+
+/// Позиция (в символах) конца строки буфера, в которой находится курсор.
+fn line_end(buffer: &str, cursor: usize) -> usize {
+    let chars: Vec<char> = buffer.chars().collect();
+    let cursor = cursor.min(chars.len());
+    chars[cursor..]
+        .iter()
+        .position(|&c| c == '\n')
+        .map_or(chars.len(), |newline| cursor + newline)
+}
+
+/// Буфер без текста от курсора до конца текущей строки (перевод строки сохраняется).
+fn kill_to_line_end(buffer: &str, cursor: usize) -> String {
+    let end = line_end(buffer, cursor);
+    buffer
+        .chars()
+        .enumerate()
+        .filter(|(i, _)| *i < cursor || *i >= end)
+        .map(|(_, c)| c)
+        .collect()
+}
+
 /// Вставить символ в позицию курсора.
 fn insert_char_at_cursor(app: &mut App, c: char) {
-    let mut chars: Vec<char> = app.input_buffer.chars().collect();
-    if app.cursor_pos <= chars.len() {
-        chars.insert(app.cursor_pos, c);
-        app.input_buffer = chars.iter().collect();
-        app.cursor_pos += 1;
+    let mut utf8 = [0; 4];
+    (app.input_buffer, app.cursor_pos) =
+        insert_text(&app.input_buffer, app.cursor_pos, c.encode_utf8(&mut utf8));
+}
+
+/// Буфер с `text`, вставленным в позицию `cursor` (в символах), и новая позиция курсора
+/// сразу после вставки. Курсор за концом буфера считается стоящим в конце.
+fn insert_text(buffer: &str, cursor: usize, text: &str) -> (String, usize) {
+    let cursor = cursor.min(buffer.chars().count());
+    let byte_pos = char_to_byte(buffer, cursor);
+
+    let mut result = String::with_capacity(buffer.len() + text.len());
+    result.push_str(&buffer[..byte_pos]);
+    result.push_str(text);
+    result.push_str(&buffer[byte_pos..]);
+    (result, cursor + text.chars().count())
+}
+
+/// Буфер без символа перед курсором (Backspace) и новая позиция курсора.
+fn delete_before(buffer: &str, cursor: usize) -> (String, usize) {
+    let cursor = cursor.min(buffer.chars().count());
+    if cursor == 0 {
+        return (buffer.to_string(), 0);
+    }
+    (remove_char(buffer, cursor - 1), cursor - 1)
+}
+
+/// Буфер без символа на позиции курсора (Delete). Курсор не сдвигается.
+fn delete_at(buffer: &str, cursor: usize) -> String {
+    if cursor >= buffer.chars().count() {
+        return buffer.to_string();
+    }
+    remove_char(buffer, cursor)
+}
+
+/// Буфер без символа с индексом `index` (в символах).
+fn remove_char(buffer: &str, index: usize) -> String {
+    buffer
+        .chars()
+        .enumerate()
+        .filter(|(i, _)| *i != index)
+        .map(|(_, c)| c)
+        .collect()
+}
+
+/// Байтовое смещение символа с индексом `index`; за концом строки — её длина в байтах.
+fn char_to_byte(buffer: &str, index: usize) -> usize {
+    buffer
+        .char_indices()
+        .nth(index)
+        .map_or(buffer.len(), |(i, _)| i)
+}
+
+/// Вставить текст из буфера обмена в позицию курсора.
+///
+/// Текст длиннее `paste_max_chars` из настроек усекается с предупреждением в истории.
+fn paste_text(app: &mut App, text: &str) {
+    let (text, truncated) = limit_paste(text, app.prefs.paste_max_chars);
+    if truncated > 0 {
+        let notice = format!(
+            "Вставка слишком большая: оставлены первые {} символов, отброшено {}.",
+            app.prefs.paste_max_chars, truncated
+        );
+        messaging::add_system_message(app, &notice);
+    }
+
+    (app.input_buffer, app.cursor_pos) = insert_text(&app.input_buffer, app.cursor_pos, &text);
+}
+
+/// Текст вставки с переводами строк `\n`, усечённый до `max_chars` символов,
+/// и число отброшенных символов.
+fn limit_paste(text: &str, max_chars: usize) -> (String, usize) {
+    let text = text.replace("\r\n", "\n").replace('\r', "\n");
+    match text.char_indices().nth(max_chars) {
+        Some((cut, _)) => {
+            let dropped = text[cut..].chars().count();
+            (text[..cut].to_string(), dropped)
+        }
+        None => (text, 0),
     }
 }
 
 /// Удалить символ перед курсором (Backspace).
 fn delete_char_before_cursor(app: &mut App) {
-    if app.cursor_pos > 0 {
-        let mut chars: Vec<char> = app.input_buffer.chars().collect();
-        chars.remove(app.cursor_pos - 1);
-        app.input_buffer = chars.iter().collect();
-        app.cursor_pos -= 1;
-    }
+    (app.input_buffer, app.cursor_pos) = delete_before(&app.input_buffer, app.cursor_pos);
 }
 
 /// Удалить символ на позиции курсора (Delete).
 fn delete_char_at_cursor(app: &mut App) {
-    let mut chars: Vec<char> = app.input_buffer.chars().collect();
-    if app.cursor_pos < chars.len() {
-        chars.remove(app.cursor_pos);
-        app.input_buffer = chars.iter().collect();
+    app.input_buffer = delete_at(&app.input_buffer, app.cursor_pos);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MULTILINE: &str = "первая\nвторая строка\nтретья";
+
+    #[test]
+    fn test_step_temperature_clamps_to_bounds() {
+        assert_eq!(step_temperature(0.7, TEMPERATURE_STEP), 0.8);
+        assert_eq!(step_temperature(0.7, -TEMPERATURE_STEP), 0.6);
+        assert_eq!(step_temperature(1.0, TEMPERATURE_STEP), 1.0);
+        assert_eq!(step_temperature(0.95, TEMPERATURE_STEP), 1.0);
+        assert_eq!(step_temperature(0.0, -TEMPERATURE_STEP), 0.0);
+        assert_eq!(step_temperature(0.04, -TEMPERATURE_STEP), 0.0);
+    }
+
+    #[test]
+    fn test_enter_action_by_mode() {
+        assert_eq!(
+            enter_action(SendOn::Enter, KeyModifiers::NONE),
+            EnterAction::Send
+        );
+        assert_eq!(
+            enter_action(SendOn::CtrlEnter, KeyModifiers::NONE),
+            EnterAction::NewLine
+        );
+        assert_eq!(
+            enter_action(SendOn::CtrlEnter, KeyModifiers::CONTROL),
+            EnterAction::Send
+        );
+    }
+
+    #[test]
+    fn test_shift_or_alt_enter_inserts_newline() {
+        for send_on in [SendOn::Enter, SendOn::CtrlEnter] {
+            for modifiers in [KeyModifiers::SHIFT, KeyModifiers::ALT] {
+                assert_eq!(enter_action(send_on, modifiers), EnterAction::NewLine);
+            }
+        }
+    }
+
+    #[test]
+    fn test_visual_line_bounds_in_wrapped_line() {
+        // При ширине 5 "вторая строка" (7..20) видна как "втора", "я стр", "ока".
+        assert_eq!(visual_line_start(MULTILINE, 9, 5), 7);
+        assert_eq!(visual_line_end(MULTILINE, 9, 5), 11);
+        assert_eq!(visual_line_start(MULTILINE, 14, 5), 12);
+        assert_eq!(visual_line_end(MULTILINE, 14, 5), 16);
+        assert_eq!(visual_line_start(MULTILINE, 18, 5), 17);
+        assert_eq!(visual_line_end(MULTILINE, 18, 5), 20);
+    }
+
+    #[test]
+    fn test_rows_above_and_below_keep_column() {
+        // Курсор на "о" в слове "вторая" (колонка 2).
+        assert_eq!(row_above(MULTILINE, 9, 0), Some(2));
+        assert_eq!(row_below(MULTILINE, 9, 0), Some(23));
+        // Строка выше короче — курсор встаёт в её конец.
+        assert_eq!(row_above(MULTILINE, 19, 0), Some(6));
+        // С крайних строк стрелки уходят в историю ввода.
+        assert_eq!(row_above(MULTILINE, 3, 0), None);
+        assert_eq!(row_below(MULTILINE, 24, 0), None);
+        assert_eq!(row_above("одна строка", 5, 0), None);
+    }
+
+    #[test]
+    fn test_rows_above_and_below_in_wrapped_line() {
+        // При ширине 5 "вторая строка" видна как "втора", "я стр", "ока".
+        assert_eq!(row_below(MULTILINE, 9, 5), Some(14));
+        assert_eq!(row_above(MULTILINE, 14, 5), Some(9));
+        // В перенесённой строке курсор не уходит за её последний символ.
+        assert_eq!(row_above(MULTILINE, 20, 5), Some(15));
+    }
+
+    #[test]
+    fn test_insert_newline_moves_cursor_to_next_line() {
+        let (buffer, cursor) = insert_text("первая", 6, "\n");
+        assert_eq!((buffer.as_str(), cursor), ("первая\n", 7));
+        assert_eq!(visual_line_start(&buffer, cursor, 20), 7);
+        assert_eq!(visual_line_start(&buffer, cursor - 1, 20), 0);
+    }
+
+    #[test]
+    fn test_wheel_scrolling_is_clamped() {
+        // 17 экранных строк в области высотой 10: последняя строка внизу при смещении 7.
+        let max_offset = usize::from(max_scroll_offset(17, 10));
+        let mut offset = 0;
+        for _ in 0..5 {
+            offset = scrolled_offset(offset, WHEEL_LINES, max_offset);
+        }
+        assert_eq!(offset, 7);
+
+        offset = scrolled_offset(offset, -WHEEL_LINES, max_offset);
+        assert_eq!(offset, 4);
+        for _ in 0..5 {
+            offset = scrolled_offset(offset, -WHEEL_LINES, max_offset);
+        }
+        assert_eq!(offset, 0);
+
+        assert_eq!(scrolled_offset(3, i32::MAX, max_offset), 7);
+        assert_eq!(scrolled_offset(3, i32::MIN, max_offset), 0);
+        assert_eq!(scrolled_offset(0, WHEEL_LINES, 0), 0);
+
+        // История короче области не прокручивается.
+        let short = usize::from(max_scroll_offset(4, 10));
+        assert_eq!(scrolled_offset(0, WHEEL_LINES, short), 0);
+        assert_eq!(max_scroll_offset(0, 0), 0);
+    }
+
+    #[test]
+    fn test_line_bounds_single_line() {
+        assert_eq!(visual_line_start("привет", 3, 0), 0);
+        assert_eq!(line_end("привет", 3), 6);
+    }
+
+    #[test]
+    fn test_line_bounds_inside_middle_line() {
+        // Курсор на "о" в слове "вторая": 7 символов первой строки с переводом + 2.
+        let cursor = 9;
+        assert_eq!(visual_line_start(MULTILINE, cursor, 0), 7);
+        assert_eq!(line_end(MULTILINE, cursor), 20);
+    }
+
+    #[test]
+    fn test_line_bounds_at_line_edges() {
+        // Курсор сразу после перевода строки — уже начало следующей строки.
+        assert_eq!(visual_line_start(MULTILINE, 7, 0), 7);
+        // Курсор перед переводом строки — уже конец текущей строки.
+        assert_eq!(line_end(MULTILINE, 6), 6);
+        assert_eq!(visual_line_start(MULTILINE, 6, 0), 0);
+    }
+
+    #[test]
+    fn test_kill_to_line_end() {
+        assert_eq!(kill_to_line_end("привет мир", 6), "привет");
+        assert_eq!(kill_to_line_end("привет мир", 0), "");
+        assert_eq!(kill_to_line_end("привет мир", 10), "привет мир");
+    }
+
+    #[test]
+    fn test_kill_to_line_end_keeps_following_lines() {
+        assert_eq!(kill_to_line_end(MULTILINE, 9), "первая\nвт\nтретья");
+    }
+
+    #[test]
+    fn test_line_bounds_last_line() {
+        let total = MULTILINE.chars().count();
+        assert_eq!(visual_line_start(MULTILINE, total, 0), 21);
+        assert_eq!(line_end(MULTILINE, 22), total);
+    }
+
+    #[test]
+    fn test_cursor_moves_by_chars_in_cyrillic() {
+        let (buffer, cursor) = insert_text("", 0, "привет");
+        assert_eq!(cursor, 6);
+
+        // End и Right считают символы, а не 12 байт.
+        assert_eq!(line_end(&buffer, 0), 6);
+        assert_eq!(char_right(&buffer, 5), 6);
+        assert_eq!(char_right(&buffer, 6), 6);
+        assert_eq!(char_right("👋 мир", 1), 2);
+    }
+
+    #[test]
+    fn test_insert_text_in_middle_of_cyrillic() {
+        assert_eq!(insert_text("приет", 3, "в"), ("привет".to_string(), 4));
+        assert_eq!(insert_text("мир", 0, "Ω"), ("Ωмир".to_string(), 1));
+        assert_eq!(insert_text("мир", 3, "!"), ("мир!".to_string(), 4));
+    }
+
+    #[test]
+    fn test_insert_text_clamps_cursor_past_end() {
+        assert_eq!(insert_text("ок", 10, "!"), ("ок!".to_string(), 3));
+        assert_eq!(insert_text("", 0, "да"), ("да".to_string(), 2));
+    }
+
+    #[test]
+    fn test_delete_before_at_edges() {
+        assert_eq!(delete_before("привет", 0), ("привет".to_string(), 0));
+        assert_eq!(delete_before("привет", 6), ("приве".to_string(), 5));
+        assert_eq!(delete_before("привет", 3), ("првет".to_string(), 2));
+        assert_eq!(delete_before("", 0), (String::new(), 0));
+    }
+
+    #[test]
+    fn test_delete_at_edges() {
+        assert_eq!(delete_at("привет", 0), "ривет");
+        assert_eq!(delete_at("привет", 5), "приве");
+        assert_eq!(delete_at("привет", 6), "привет");
+        assert_eq!(delete_at("", 0), "");
+    }
+
+    #[test]
+    fn test_word_left_with_punctuation() {
+        let text = "Привет, мир! Как дела?";
+        assert_eq!(word_left(text, 22), 17);
+        assert_eq!(word_left(text, 17), 13);
+        // Из начала слова — через запятую и пробел к началу предыдущего.
+        assert_eq!(word_left(text, 8), 0);
+        assert_eq!(word_left(text, 0), 0);
+    }
+
+    #[test]
+    fn test_word_right_with_punctuation() {
+        let text = "Привет, мир! Как дела?";
+        assert_eq!(word_right(text, 0), 8);
+        assert_eq!(word_right(text, 3), 8);
+        assert_eq!(word_right(text, 8), 13);
+        assert_eq!(word_right(text, 17), 22);
+        assert_eq!(word_right(text, 22), 22);
+    }
+
+    #[test]
+    fn test_limit_paste_truncates_by_chars() {
+        assert_eq!(limit_paste("привет", 10), ("привет".to_string(), 0));
+        assert_eq!(limit_paste("привет", 3), ("при".to_string(), 3));
+        assert_eq!(limit_paste("", 0), (String::new(), 0));
+    }
+
+    #[test]
+    fn test_limit_paste_normalizes_line_endings() {
+        assert_eq!(limit_paste("a\r\nb\rc", 100), ("a\nb\nc".to_string(), 0));
     }
 }
@@ -0,0 +1,493 @@
+//! История диалога: типизированные записи и потокобезопасное хранилище для них.
+//!
+//! [`History`] — разделяемый дескриптор: его клон указывает на те же записи. Это позволяет
+//! фоновой задаче (например, автосохранению) снимать снапшот истории, пока интерфейс её дополняет.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use ym_yagpt::ChatMessage;
+
+use crate::settings::Prefixes;
+use crate::utils::tools::write_with_backup;
+
+/// Автор записи в истории диалога.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// Сообщение пользователя.
+    User,
+    /// Ответ языковой модели.
+    Assistant,
+    /// Служебное сообщение приложения.
+    System,
+    /// Информационная строка без префикса (например, приветствие).
+    Info,
+    /// Ошибка запроса к модели.
+    Error,
+}
+
+impl Role {
+    /// Префикс записей этой роли из `prefixes`.
+    pub fn prefix<'a>(self, prefixes: &Prefixes<'a>) -> &'a str {
+        match self {
+            Role::User => prefixes.user,
+            Role::Assistant => prefixes.assistant,
+            Role::System => prefixes.system,
+            Role::Info => prefixes.info,
+            Role::Error => prefixes.error,
+        }
+    }
+}
+
+/// Запись в истории диалога.
+///
+/// Записи равны, только если совпадают все поля, включая время создания и модель; где важно
+/// одно содержимое, роль и текст сравниваются явно.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ChatEntry {
+    pub role: Role,
+    pub text: String,
+    /// Локальное время создания записи. В файлах истории без этого поля — время загрузки.
+    #[serde(default = "Local::now")]
+    pub timestamp: DateTime<Local>,
+    /// Модель, которая дала ответ. Есть только у ответов модели; в старых файлах истории
+    /// поля нет.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+}
+
+impl ChatEntry {
+    pub fn new(role: Role, text: impl Into<String>) -> Self {
+        Self {
+            role,
+            text: text.into(),
+            timestamp: Local::now(),
+            model: None,
+        }
+    }
+
+    /// Отметить запись моделью `model`, давшей ответ.
+    pub fn with_model(mut self, model: impl Into<String>) -> Self {
+        self.model = Some(model.into());
+        self
+    }
+
+    pub fn user(text: impl Into<String>) -> Self {
+        Self::new(Role::User, text)
+    }
+
+    pub fn assistant(text: impl Into<String>) -> Self {
+        Self::new(Role::Assistant, text)
+    }
+
+    pub fn system(text: impl Into<String>) -> Self {
+        Self::new(Role::System, text)
+    }
+
+    pub fn info(text: impl Into<String>) -> Self {
+        Self::new(Role::Info, text)
+    }
+
+    pub fn error(text: impl Into<String>) -> Self {
+        Self::new(Role::Error, text)
+    }
+
+    /// Сообщение для контекста модели. Служебные, информационные записи и ошибки
+    /// в контекст не попадают; текст передаётся без префиксов интерфейса.
+    pub fn to_chat_message(&self) -> Option<ChatMessage> {
+        match self.role {
+            Role::User => Some(ChatMessage::user(self.text.as_str())),
+            Role::Assistant => Some(ChatMessage::assistant(self.text.as_str())),
+            Role::System | Role::Info | Role::Error => None,
+        }
+    }
+
+    /// Строка записи с префиксами по умолчанию (см. [`Prefixes::default`]).
+    pub fn display(&self) -> String {
+        self.display_as(&Prefixes::default())
+    }
+
+    /// Строка записи в том виде, в котором она выводится в интерфейсе.
+    pub fn display_as(&self, prefixes: &Prefixes) -> String {
+        self.head(prefixes, false) + &self.text
+    }
+
+    /// Подпись перед текстом записи: время (при `with_time`), префикс роли и модель ответа,
+    /// например `[ЧЧ:ММ] GPT (yandexgpt-lite): `. Пустая, если выводить нечего.
+    pub fn head(&self, prefixes: &Prefixes, with_time: bool) -> String {
+        let mut head = String::new();
+        if with_time {
+            head.push_str(&format!("[{}] ", self.timestamp.format("%H:%M")));
+        }
+        let prefix = self.role.prefix(prefixes);
+        match (prefix, &self.model) {
+            ("", None) => {}
+            ("", Some(model)) => head.push_str(&format!("({}) ", model)),
+            (prefix, None) => head.push_str(&format!("{}: ", prefix)),
+            (prefix, Some(model)) => head.push_str(&format!("{} ({}): ", prefix, model)),
+        }
+        head
+    }
+}
+
+/// Потокобезопасная история диалога.
+///
+/// Клонирование не копирует записи, а создаёт ещё один дескриптор той же истории.
+/// Для независимой копии используйте [`History::snapshot`].
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    entries: Arc<Mutex<Vec<ChatEntry>>>,
+}
+
+impl History {
+    /// Создать историю с начальными записями.
+    pub fn from_entries(entries: Vec<ChatEntry>) -> Self {
+        Self {
+            entries: Arc::new(Mutex::new(entries)),
+        }
+    }
+
+    /// Захватить записи. Паника в другом потоке не делает историю недоступной.
+    fn lock(&self) -> MutexGuard<'_, Vec<ChatEntry>> {
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Добавить запись в конец истории.
+    pub fn push(&self, entry: ChatEntry) {
+        self.lock().push(entry);
+    }
+
+    /// Дописать `text` к последней записи с ролью `role`. Возвращает `false`, если такой нет.
+    pub fn extend_last(&self, role: Role, text: &str) -> bool {
+        match self
+            .lock()
+            .iter_mut()
+            .rev()
+            .find(|entry| entry.role == role)
+        {
+            Some(entry) => {
+                entry.text.push_str(text);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Заменить текст последней записи с ролью `role`. Возвращает `false`, если такой нет.
+    pub fn replace_last(&self, role: Role, text: &str) -> bool {
+        match self
+            .lock()
+            .iter_mut()
+            .rev()
+            .find(|entry| entry.role == role)
+        {
+            Some(entry) => {
+                entry.text = text.to_string();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Удалить последнюю запись с ролью `role`. Возвращает `false`, если такой нет.
+    pub fn remove_last(&self, role: Role) -> bool {
+        let mut entries = self.lock();
+        match entries.iter().rposition(|entry| entry.role == role) {
+            Some(index) => {
+                entries.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Оставить только первые `len` записей.
+    pub fn truncate(&self, len: usize) {
+        self.lock().truncate(len);
+    }
+
+    /// Удалить все записи.
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    /// Количество записей.
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Независимая копия записей на текущий момент.
+    pub fn snapshot(&self) -> Vec<ChatEntry> {
+        self.lock().clone()
+    }
+}
+
+/// Контекст диалога для модели: реплики пользователя и модели с явными ролями.
+pub fn chat_messages(entries: &[ChatEntry]) -> Vec<ChatMessage> {
+    entries
+        .iter()
+        .filter_map(ChatEntry::to_chat_message)
+        .collect()
+}
+
+/// Роль и текст каждой записи: содержимое для сравнения без времени создания и модели.
+#[cfg(test)]
+pub fn contents(entries: &[ChatEntry]) -> Vec<(Role, &str)> {
+    entries
+        .iter()
+        .map(|entry| (entry.role, entry.text.as_str()))
+        .collect()
+}
+
+/// Сохранить записи в JSON-файл: роль и текст каждой записи, без префиксов интерфейса.
+///
+/// Файл заменяется целиком через временный, прежняя версия остаётся в `<path>.bak`
+/// (см. [`write_with_backup`]).
+pub fn save_entries(path: &Path, entries: &[ChatEntry]) -> io::Result<()> {
+    let json = serde_json::to_string_pretty(entries).map_err(io::Error::other)?;
+    write_with_backup(path, json.as_bytes())
+}
+
+/// Загрузить записи, сохранённые [`save_entries`].
+///
+/// Отсутствующий файл — не ошибка: возвращается пустой список.
+pub fn load_entries(path: &Path) -> Result<Vec<ChatEntry>, String> {
+    match fs::read_to_string(path) {
+        Ok(contents) => serde_json::from_str(&contents)
+            .map_err(|err| format!("{}: ошибка разбора: {}", path.display(), err)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(format!("{} недоступен: {}", path.display(), err)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use std::thread;
+
+    #[test]
+    fn test_chat_messages_keep_roles_and_skip_service_entries() {
+        let entries = vec![
+            ChatEntry::info("YandexGPT готов к диалогу."),
+            ChatEntry::assistant("Чем помочь?"),
+            ChatEntry::user("Привет"),
+            ChatEntry::error("таймаут"),
+            ChatEntry::user("Привет ещё раз"),
+            ChatEntry::system("Ответ обрезан"),
+        ];
+
+        assert_eq!(
+            chat_messages(&entries),
+            [
+                ChatMessage::assistant("Чем помочь?"),
+                ChatMessage::user("Привет"),
+                ChatMessage::user("Привет ещё раз"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_entries_round_trip_through_file() {
+        let entries = vec![
+            ChatEntry::user("Привет"),
+            ChatEntry::assistant("Здравствуйте!\nЧем помочь?"),
+            ChatEntry::user("Код: `a \"b\"`"),
+        ];
+        let path = std::env::temp_dir().join(format!("ym-history-{}.json", std::process::id()));
+
+        save_entries(&path, &entries).unwrap();
+        let loaded = load_entries(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded, entries);
+        assert_eq!(loaded[1].timestamp, entries[1].timestamp);
+    }
+
+    #[test]
+    fn test_entries_are_stored_as_typed_roles() {
+        let json = serde_json::to_string(&[ChatEntry::assistant("Ответ")]).unwrap();
+        assert!(
+            json.starts_with(r#"[{"role":"assistant","text":"Ответ","timestamp":""#),
+            "{}",
+            json
+        );
+
+        // Файлы истории без времени по-прежнему читаются.
+        let old: Vec<ChatEntry> =
+            serde_json::from_str(r#"[{"role":"user","text":"Привет"}]"#).unwrap();
+        assert_eq!(contents(&old), [(Role::User, "Привет")]);
+        assert_eq!(old[0].model, None);
+        assert!(!json.contains("model"));
+
+        let entry = ChatEntry::assistant("Ответ").with_model("yandexgpt/latest");
+        let json = serde_json::to_string(&entry).unwrap();
+        let loaded: ChatEntry = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.model.as_deref(), Some("yandexgpt/latest"));
+    }
+
+    #[test]
+    fn test_head_shows_answer_model() {
+        let entry = ChatEntry::assistant("ответ").with_model("yandexgpt-lite");
+        assert_eq!(entry.head(&Prefixes::default(), false), "(yandexgpt-lite) ");
+        let prefixes = Prefixes {
+            assistant: "GPT",
+            ..Prefixes::default()
+        };
+        assert_eq!(entry.head(&prefixes, false), "GPT (yandexgpt-lite): ");
+    }
+
+    #[test]
+    fn test_head_without_prefix_or_time_is_empty() {
+        let entry = ChatEntry::assistant("ответ");
+        assert_eq!(entry.head(&Prefixes::default(), false), "");
+        assert_eq!(
+            ChatEntry::user("да").head(&Prefixes::default(), false),
+            "Вы: "
+        );
+    }
+
+    #[test]
+    fn test_head_with_time() {
+        let mut entry = ChatEntry::user("Привет");
+        entry.timestamp = Local.with_ymd_and_hms(2025, 3, 1, 9, 5, 0).unwrap();
+        assert_eq!(
+            entry.head(&Prefixes::default(), true) + &entry.text,
+            "[09:05] Вы: Привет"
+        );
+    }
+
+    #[test]
+    fn test_missing_or_corrupt_history_file() {
+        let path = std::env::temp_dir().join(format!("ym-history-bad-{}.json", std::process::id()));
+        assert_eq!(load_entries(&path), Ok(Vec::new()));
+
+        fs::write(&path, "[{\"role\": \"robot\"").unwrap();
+        let loaded = load_entries(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(loaded.unwrap_err().contains("ошибка разбора"));
+    }
+
+    #[test]
+    fn test_clone_shares_entries() {
+        let history = History::default();
+        let handle = history.clone();
+
+        handle.push(ChatEntry::user("привет"));
+        assert_eq!(history.len(), 1);
+        assert_eq!(history.snapshot()[0].text, "привет");
+    }
+
+    #[test]
+    fn test_snapshot_is_independent_copy() {
+        let history = History::from_entries(vec![ChatEntry::info("старт")]);
+        let snapshot = history.snapshot();
+
+        history.clear();
+        assert_eq!(history.len(), 0);
+        assert_eq!(snapshot.len(), 1);
+    }
+
+    #[test]
+    fn test_concurrent_push_and_snapshot() {
+        const WRITES: usize = 1000;
+        let history = History::default();
+
+        let writer = {
+            let history = history.clone();
+            thread::spawn(move || {
+                for i in 0..WRITES {
+                    history.push(ChatEntry::user(i.to_string()));
+                }
+            })
+        };
+
+        // Снапшоты, снятые во время записи, всегда согласованы: это префикс итоговой истории.
+        let mut last_len = 0;
+        while !writer.is_finished() {
+            let snapshot = history.snapshot();
+            assert!(snapshot.len() >= last_len);
+            for (i, entry) in snapshot.iter().enumerate() {
+                assert_eq!(entry.text, i.to_string());
+            }
+            last_len = snapshot.len();
+        }
+        writer.join().unwrap();
+
+        assert_eq!(history.len(), WRITES);
+    }
+
+    #[test]
+    fn test_extend_last_appends_to_matching_role() {
+        let history = History::from_entries(vec![
+            ChatEntry::assistant("Первая"),
+            ChatEntry::assistant("Начало"),
+            ChatEntry::system("Ответ обрезан"),
+        ]);
+
+        assert!(history.extend_last(Role::Assistant, " и конец"));
+        assert_eq!(history.snapshot()[1].text, "Начало и конец");
+        assert_eq!(history.snapshot()[0].text, "Первая");
+        assert!(!history.extend_last(Role::User, "?"));
+    }
+
+    #[test]
+    fn test_remove_last_removes_matching_role() {
+        let history = History::from_entries(vec![
+            ChatEntry::assistant("Первая"),
+            ChatEntry::assistant("Частичный ответ"),
+            ChatEntry::system("Повтор через 5 с"),
+        ]);
+
+        assert!(history.remove_last(Role::Assistant));
+        assert_eq!(
+            contents(&history.snapshot()),
+            [
+                (Role::Assistant, "Первая"),
+                (Role::System, "Повтор через 5 с"),
+            ]
+        );
+        assert!(!history.remove_last(Role::User));
+    }
+
+    #[test]
+    fn test_display_prefixes() {
+        assert_eq!(ChatEntry::user("да").display(), "Вы: да");
+        assert_eq!(ChatEntry::assistant("ответ").display(), "ответ");
+        assert_eq!(ChatEntry::system("готово").display(), "Система: готово");
+    }
+
+    #[test]
+    fn test_display_as_custom_prefixes() {
+        let prefixes = Prefixes {
+            user: "Аня",
+            assistant: "GPT",
+            system: "",
+            info: "i",
+            error: "!",
+        };
+
+        assert_eq!(ChatEntry::user("да").display_as(&prefixes), "Аня: да");
+        assert_eq!(
+            ChatEntry::assistant("ответ").display_as(&prefixes),
+            "GPT: ответ"
+        );
+        assert_eq!(ChatEntry::system("готово").display_as(&prefixes), "готово");
+        assert_eq!(ChatEntry::info("привет").display_as(&prefixes), "i: привет");
+        assert_eq!(ChatEntry::error("сбой").display_as(&prefixes), "!: сбой");
+    }
+
+    #[test]
+    fn test_display_default_prefixes() {
+        assert_eq!(
+            ChatEntry::error("сбой").display(),
+            "Ошибка ответа модели: сбой"
+        );
+        assert_eq!(ChatEntry::info("привет").display(), "привет");
+    }
+}
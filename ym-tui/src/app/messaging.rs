@@ -1,47 +1,2036 @@
 //! Работа с сообщениями и взаимодействие с YandexGPT API.
 
-use super::core::App;
+use std::collections::VecDeque;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
-/// Отправить сообщение нейросети и обработать полученный результат.
-pub async fn send_message_to_gpt(app: &mut App) {
-    if !app.input_buffer.trim().is_empty() {
-        // Добавляем сообщение пользователя в историю
-        app.messages.push(format!("Вы: {}", app.input_buffer));
+use pulldown_cmark::{Event, Options, Parser, html};
+use ym_yagpt::errors::GPTError;
+use ym_yagpt::models::{
+    ChatMessage, GPTAnswer, GPTOptions, ModelInfo, estimate_tokens, model_token_limit,
+    recent_exchanges,
+};
+
+use super::core::{App, Focus};
+use super::history::{ChatEntry, History, Role, chat_messages};
+use super::ui::max_scroll_offset;
+use crate::settings::{self, Prefixes};
+use crate::utils::markdown::strip_markdown;
+use crate::utils::tools::collapse_blank_lines;
+
+/// Приветствие, с которого начинается каждый диалог.
+pub const GREETING: &str = "YandexGPT готов к диалогу.";
+
+/// Пауза перед автоматическим повтором запроса при потере сети.
+const NETWORK_RETRY_DELAY: Duration = Duration::from_secs(5);
+/// Сколько раз повторять запрос, прежде чем сдаться.
+const NETWORK_RETRY_ATTEMPTS: u32 = 3;
+/// Просьба продолжить обрезанный ответ, отправляемая по `/continue`.
+const CONTINUE_PROMPT: &str = "Продолжай с того места, где остановился, без повторов.";
+/// Сколько символов системного промпта показывает `/system` без `full`.
+const SYSTEM_PROMPT_PREVIEW: usize = 200;
+
+/// Встроенные стили HTML-экспорта: у каждой роли свой цвет, код — в моноширинных блоках.
+const HTML_STYLE: &str = "\
+body { font-family: sans-serif; max-width: 860px; margin: 2em auto; color: #222; }
+.entry { margin: 1em 0; padding: 0.6em 1em; border-left: 4px solid #ccc; }
+.entry .role { font-weight: bold; margin-bottom: 0.3em; }
+.entry .plain { white-space: pre-wrap; margin: 0; }
+.user { border-color: #2a7ae2; } .user .role { color: #2a7ae2; }
+.assistant { border-color: #2e9e4f; } .assistant .role { color: #2e9e4f; }
+.system { border-color: #999; color: #555; }
+.info { border-color: #ddd; color: #555; }
+.error { border-color: #d33; } .error .role { color: #d33; }
+pre { background: #f4f4f4; padding: 0.6em; overflow-x: auto; }
+code { font-family: monospace; }
+";
+
+/// Отложенный повтор запроса после потери соединения или превышения лимита запросов.
+#[derive(Debug, Clone)]
+pub struct NetworkRetry {
+    /// Причина повтора для статус-бара.
+    pub reason: &'static str,
+    /// Момент, когда запрос будет отправлен повторно.
+    pub deadline: Instant,
+    /// Номер предстоящей попытки (начиная с 1).
+    pub attempt: u32,
+    /// Длина истории, которая уходила в контекст неудачного запроса.
+    context_len: usize,
+}
+
+impl NetworkRetry {
+    /// Сколько целых секунд осталось до повтора (с округлением вверх).
+    pub fn seconds_left(&self) -> u64 {
+        let left = self.deadline.saturating_duration_since(Instant::now());
+        left.as_millis().div_ceil(1000) as u64
+    }
+}
 
-        let gpt_answer = app
-            .gpt_client
-            .chat_with_gpt(&app.messages)
-            .await
-            .unwrap_or_else(|err| format!("Ошибка ответа модели: {err}"));
+/// Ответ фонового запроса к модели (см. [`App::begin_request`]).
+#[derive(Debug)]
+pub struct Reply {
+    /// Номер запроса: ответы отменённых запросов отбрасываются.
+    pub id: u64,
+    /// Модель, которой был адресован запрос.
+    pub model: String,
+    /// Время ожидания ответа.
+    pub elapsed: Duration,
+    pub kind: ReplyKind,
+}
+
+/// Вид фонового запроса и его результат.
+#[derive(Debug)]
+pub enum ReplyKind {
+    /// Ответ на диалог из первых `context_len` записей истории; `attempt` — номер попытки
+    /// для повтора при потере сети.
+    Answer {
+        result: Result<Vec<GPTAnswer>, GPTError>,
+        context_len: usize,
+        attempt: u32,
+    },
+    /// Продолжение последнего ответа (`/continue`).
+    Continuation(Result<GPTAnswer, GPTError>),
+    /// Очередной фрагмент ответа, получаемого по частям.
+    Delta(String),
+    /// Конец ответа, получаемого по частям, на диалог из первых `context_len` записей:
+    /// итоговый ответ модели, если поток завершился штатно.
+    StreamEnd {
+        result: Result<GPTAnswer, GPTError>,
+        context_len: usize,
+        attempt: u32,
+    },
+}
+
+/// Команда приложения, введённая в поле ввода через `/`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// `/compact` — переключить компактный интерфейс.
+    Compact,
+    /// `/stats` — показать статистику сессии.
+    Stats,
+    /// `/system` и `/system full` — показать системные инструкции (сокращённо или целиком).
+    ShowSystem { full: bool },
+    /// `/system clear` — удалить системные инструкции.
+    ClearSystem,
+    /// `/export html <файл>` — сохранить диалог в HTML.
+    ExportHtml { path: String },
+    /// `/export md <файл>` — сохранить диалог в Markdown.
+    ExportMarkdown { path: String },
+    /// `/switch-model <модель> [ask]` — сменить модель и при `ask` переспросить последний вопрос.
+    SwitchModel { model: String, ask: bool },
+    /// `/reuse` — скопировать последний ответ модели в поле ввода.
+    Reuse,
+    /// `/copy [plain]` — скопировать последний ответ модели в буфер обмена; `plain` — без
+    /// markdown-разметки.
+    Copy { plain: bool },
+    /// `/reload` — перечитать файлы доступа и настроек.
+    Reload,
+    /// `/continue` — продолжить последний ответ модели, обрезанный по лимиту токенов.
+    Continue,
+    /// `/undo` — удалить последний вопрос и всё, что пришло после него.
+    Undo,
+    /// `/pick N` — заменить последний ответ его вариантом с номером `N` (с единицы).
+    Pick(usize),
+    /// `/resume` — продолжить диалог, сохранённый в прошлой сессии.
+    Resume,
+    /// `/temp <значение>` — задать температуру генерации.
+    Temperature(f32),
+    /// `/clear` — очистить историю диалога.
+    Clear,
+    /// `/models` — показать известные модели.
+    Models,
+    /// `/profile` — показать профили доступа; `/profile switch <имя>` — переключиться на профиль.
+    Profile(Option<String>),
+    /// `/save <файл>` — сохранить диалог в JSON-файл.
+    Save { path: String },
+    /// Команда с таким именем не существует.
+    Unknown(String),
+    /// Известная команда с неверными аргументами; `usage` — её правильный вид.
+    Malformed { usage: &'static str },
+}
+
+/// Команды приложения: вид и назначение. Из этого списка строится подсказка
+/// при неизвестной или неверно набранной команде.
+const COMMANDS: &[(&str, &str)] = &[
+    ("/model <модель>", "сменить модель"),
+    ("/models", "список известных моделей"),
+    (
+        "/switch-model <модель> [ask]",
+        "сменить модель и при ask переспросить последний вопрос",
+    ),
+    ("/temp <0..1>", "задать температуру"),
+    ("/clear", "очистить историю"),
+    ("/save <файл.json>", "сохранить диалог в файл"),
+    ("/resume", "продолжить диалог прошлой сессии"),
+    (
+        "/export html | md <файл>",
+        "сохранить диалог в HTML или Markdown",
+    ),
+    ("/system [full | clear]", "системные инструкции"),
+    ("/continue", "продолжить обрезанный ответ"),
+    ("/pick N", "выбрать вариант ответа N"),
+    ("/reuse", "взять последний ответ в поле ввода"),
+    (
+        "/copy [plain]",
+        "скопировать последний ответ (plain — без разметки)",
+    ),
+    ("/undo", "отменить последний обмен"),
+    ("/reload", "перечитать файлы доступа и настроек"),
+    ("/profile [switch <имя>]", "профили доступа"),
+    ("/stats", "статистика сессии"),
+    ("/compact", "компактный интерфейс"),
+];
+
+/// Правильный вид команды `name` из [`COMMANDS`]; `None`, если такой команды нет.
+fn command_usage(name: &str) -> Option<&'static str> {
+    COMMANDS.iter().map(|&(usage, _)| usage).find(|usage| {
+        usage
+            .strip_prefix('/')
+            .and_then(|rest| rest.split_whitespace().next())
+            == Some(name)
+    })
+}
+
+/// Список команд приложения для системного сообщения.
+fn describe_commands() -> String {
+    let lines: Vec<String> = COMMANDS
+        .iter()
+        .map(|(usage, purpose)| format!("{} — {}", usage, purpose))
+        .collect();
+    format!("Доступные команды:\n{}", lines.join("\n"))
+}
+
+/// Список моделей для системного сообщения; `current` помечается.
+fn describe_models(models: &[ModelInfo], current: &str) -> String {
+    let lines: Vec<String> = models
+        .iter()
+        .map(|model| {
+            let mut line = format!("{} — {}", model.name, model.description);
+            if let Some(limit) = model.context_limit {
+                line.push_str(&format!(", контекст {} токенов", limit));
+            }
+            if model.name == current {
+                line.push_str(" (текущая)");
+            }
+            line
+        })
+        .collect();
+    format!("Модели (/model <модель>):\n{}", lines.join("\n"))
+}
+
+/// Активный профиль доступа и список сохранённых для системного сообщения.
+fn describe_profiles(active: Option<&str>, names: &[String]) -> String {
+    let current = match active {
+        Some(name) => format!("Профиль: {}.", name),
+        None => format!("Профиль не выбран, используется {}.", settings::ACCESS_FILE),
+    };
+    if names.is_empty() {
+        format!("{} Сохранённых профилей нет.", current)
+    } else {
+        format!("{} Профили: {}.", current, names.join(", "))
+    }
+}
+
+/// Распознать команду в начале ввода. Текст без `/` — не команда.
+///
+/// Неизвестное имя даёт [`Command::Unknown`], известная команда с неверными аргументами —
+/// [`Command::Malformed`]: такой ввод не отправляется модели.
+pub fn parse_command(input: &str) -> Option<Command> {
+    let rest = input.trim().strip_prefix('/')?;
+    if rest.starts_with('/') {
+        // `//` — экранирование: сообщение модели, начинающееся с `/` (см. [`unescape_message`]).
+        return None;
+    }
+    let mut words = rest.split_whitespace();
+    let name = words.next()?;
+
+    let Some(usage) = command_usage(name) else {
+        return Some(Command::Unknown(name.to_string()));
+    };
+    Some(parse_arguments(name, words).unwrap_or(Command::Malformed { usage }))
+}
+
+/// Разобрать аргументы известной команды `name`. `None` — аргументы неверны.
+fn parse_arguments<'a>(name: &str, mut words: impl Iterator<Item = &'a str>) -> Option<Command> {
+    match name {
+        "compact" => Some(Command::Compact),
+        "stats" => Some(Command::Stats),
+        "system" => match words.next() {
+            None => Some(Command::ShowSystem { full: false }),
+            Some("full") => Some(Command::ShowSystem { full: true }),
+            Some("clear") => Some(Command::ClearSystem),
+            Some(_) => None,
+        },
+        "export" => {
+            let format = words.next();
+            let path = words.collect::<Vec<_>>().join(" ");
+            if path.is_empty() {
+                return None;
+            }
+            match format {
+                Some("html") => Some(Command::ExportHtml { path }),
+                Some("md") => Some(Command::ExportMarkdown { path }),
+                _ => None,
+            }
+        }
+        "reuse" => Some(Command::Reuse),
+        "copy" => {
+            let plain = match words.next() {
+                None => false,
+                Some("plain") => true,
+                Some(_) => return None,
+            };
+            words.next().is_none().then_some(Command::Copy { plain })
+        }
+        "reload" => Some(Command::Reload),
+        "continue" => Some(Command::Continue),
+        "undo" => Some(Command::Undo),
+        "resume" => Some(Command::Resume),
+        "pick" => {
+            let number = words.next()?.parse().ok().filter(|n| *n > 0)?;
+            words.next().is_none().then_some(Command::Pick(number))
+        }
+        "switch-model" => {
+            let model = words.next()?.to_string();
+            let ask = match words.next() {
+                None => false,
+                Some("ask") => true,
+                Some(_) => return None,
+            };
+            words
+                .next()
+                .is_none()
+                .then_some(Command::SwitchModel { model, ask })
+        }
+        "model" => {
+            let model = words.next()?.to_string();
+            words
+                .next()
+                .is_none()
+                .then_some(Command::SwitchModel { model, ask: false })
+        }
+        "temp" => {
+            let value = words.next()?.replace(',', ".").parse().ok()?;
+            let temperature = Command::Temperature(value);
+            words.next().is_none().then_some(temperature)
+        }
+        "clear" => words.next().is_none().then_some(Command::Clear),
+        "models" => words.next().is_none().then_some(Command::Models),
+        "profile" => match words.next() {
+            None => Some(Command::Profile(None)),
+            Some("switch") => {
+                let switch = Command::Profile(Some(words.next()?.to_string()));
+                words.next().is_none().then_some(switch)
+            }
+            Some(_) => None,
+        },
+        "save" => {
+            let path = words.collect::<Vec<_>>().join(" ");
+            (!path.is_empty()).then_some(Command::Save { path })
+        }
+        _ => None,
+    }
+}
+
+/// Выполнить команду приложения.
+fn execute_command(app: &mut App, command: Command) {
+    match command {
+        Command::Compact => {
+            let compact = !app.prefs.compact;
+            app.set_compact(compact);
+        }
+        Command::Stats => {
+            let summary = app.stats.summary();
+            add_system_message(app, &summary);
+        }
+        Command::ShowSystem { full } => {
+            let prompts: Vec<String> = app
+                .gpt_client
+                .gpt_options
+                .system_messages()
+                .cloned()
+                .collect();
+            let notice = describe_system_prompts(&prompts, full);
+            add_system_message(app, &notice);
+        }
+        Command::ClearSystem => {
+            app.gpt_client.gpt_options.system_prompt = None;
+            app.gpt_client.gpt_options.system_prompts.clear();
+            add_system_message(app, "Системные инструкции удалены.");
+        }
+        Command::ExportHtml { path } => export_dialog(app, &path, export_html),
+        Command::ExportMarkdown { path } => export_dialog(app, &path, App::export_markdown),
+        Command::SwitchModel { model, ask } => {
+            let previous = app.model().to_string();
+            app.switch_model(&model);
+            add_system_message(
+                app,
+                &format!("Модель переключена: {} → {}", previous, model),
+            );
+            if ask {
+                reask_last_question(app);
+            }
+        }
+        Command::Reuse => reuse_last_answer(app),
+        Command::Copy { plain } => copy_last_answer(app, plain),
+        Command::Continue => continue_answer(app),
+        Command::Undo => undo_last_exchange(app),
+        Command::Pick(number) => pick_alternative(app, number),
+        Command::Resume => resume_history(app),
+        Command::Temperature(temperature) => {
+            let overrides = settings::ModelOverrides {
+                temperature: Some(temperature),
+                ..Default::default()
+            };
+            let notice = match app.override_model(&overrides) {
+                Ok(()) => format!("Температура: {:.1}", temperature),
+                Err(err) => format!("Температура не изменена: {}", err),
+            };
+            add_system_message(app, &notice);
+        }
+        Command::Clear => clear_messages(app),
+        Command::Models => {
+            let notice = describe_models(&app.gpt_client.list_models(), app.model());
+            add_system_message(app, &notice);
+        }
+        Command::Save { path } => {
+            if let Some(path) = output_path(app, &path) {
+                save_history_to(app, &path);
+            }
+        }
+        Command::Unknown(name) => {
+            let notice = format!("Неизвестная команда /{}. {}", name, describe_commands());
+            add_system_message(app, &notice);
+        }
+        Command::Malformed { usage } => {
+            add_system_message(app, &format!("Формат команды: {}", usage));
+        }
+        Command::Profile(None) => {
+            let notice = describe_profiles(
+                settings::active_profile().as_deref(),
+                &settings::profile_names(),
+            );
+            add_system_message(app, &notice);
+        }
+        Command::Profile(Some(name)) => match app.switch_profile(&name) {
+            Ok(warnings) => {
+                app.start_ping();
+                add_system_message(app, &format!("Профиль переключён: {}", name));
+                for warning in warnings {
+                    add_system_message(app, &warning);
+                }
+            }
+            Err(err) => add_system_message(app, &err),
+        },
+        Command::Reload => match app.reload_config() {
+            Ok(warnings) => {
+                app.start_ping();
+                add_system_message(app, "Конфигурация перечитана.");
+                for warning in warnings {
+                    add_system_message(app, &warning);
+                }
+            }
+            Err(err) => add_system_message(
+                app,
+                &format!("Конфигурация не перечитана, используется прежняя: {}", err),
+            ),
+        },
+    }
+}
+
+/// Запросить продолжение последнего ответа модели и дописать его к этому ответу.
+///
+/// В контекст уходит история до последнего ответа включительно и [`CONTINUE_PROMPT`];
+/// сама просьба в историю не попадает.
+fn continue_answer(app: &mut App) {
+    if is_busy(app) {
+        return;
+    }
+    let history = app.messages.snapshot();
+    let Some(index) = history
+        .iter()
+        .rposition(|entry| entry.role == Role::Assistant)
+    else {
+        add_system_message(app, "Нет ответа модели, который можно продолжить.");
+        return;
+    };
+
+    let mut context = chat_messages(&history[..=index]);
+    context.push(ChatMessage::user(CONTINUE_PROMPT));
+
+    let client = app.gpt_client.clone();
+    app.begin_request(async move {
+        ReplyKind::Continuation(client.chat_with_gpt_detailed(&context).await)
+    });
+}
 
-        // Добавляем ответ GPT в историю
-        app.messages.push(gpt_answer);
+/// Дописать к последнему ответу полученное продолжение.
+fn apply_continuation(
+    app: &mut App,
+    model: &str,
+    elapsed: Duration,
+    result: Result<GPTAnswer, GPTError>,
+) {
+    app.update_connection(&result);
 
-        // Очищаем буфер ввода и сбрасываем курсор
+    match result {
+        Ok(answer) => {
+            app.stats.record_answer(model, elapsed);
+            app.stats
+                .record_tokens(answer.input_tokens, answer.completion_tokens);
+            let text = collapse_blank_lines(&answer.text, app.prefs.blank_lines_threshold);
+            let previous = app
+                .messages
+                .snapshot()
+                .into_iter()
+                .rev()
+                .find(|entry| entry.role == Role::Assistant)
+                .map(|entry| entry.text)
+                .unwrap_or_default();
+            let separator = continuation_separator(&previous, &text);
+            app.messages
+                .extend_last(Role::Assistant, &format!("{}{}", separator, text));
+            if answer.truncated {
+                report_truncated(app);
+            }
+        }
+        Err(err) => app.messages.push(ChatEntry::error(err.to_string())),
+    }
+
+    update_scroll_offset(app);
+}
+
+/// Разделитель между обрезанным ответом `previous` и его продолжением `next`.
+///
+/// Пробел или перевод строки на стыке сохраняются как есть; продолжение, которое начинается
+/// со знака препинания, приклеивается к ответу. Блок markdown (заголовок, список, таблица,
+/// код) начинается с новой строки, остальное отделяется пробелом.
+fn continuation_separator(previous: &str, next: &str) -> &'static str {
+    let (Some(last), Some(first)) = (previous.chars().last(), next.chars().next()) else {
+        return "";
+    };
+    if last.is_whitespace() || first.is_whitespace() || ".,;:!?)]}»…".contains(first) {
+        return "";
+    }
+    let block = ["#", "- ", "* ", "|", "```", "> "]
+        .iter()
+        .any(|marker| next.starts_with(marker));
+    if block { "\n" } else { " " }
+}
+
+/// Сообщить, что ответ обрезан по лимиту токенов и его можно продолжить.
+fn report_truncated(app: &mut App) {
+    add_system_message(
+        app,
+        "Ответ обрезан по лимиту токенов. Команда /continue запросит продолжение.",
+    );
+}
+
+/// Скопировать последний ответ модели в поле ввода, поставив курсор в конец текста.
+///
+/// Текущее содержимое поля ввода заменяется, фокус переходит на поле ввода.
+pub fn reuse_last_answer(app: &mut App) {
+    let history = app.messages.snapshot();
+    match last_answer(&history) {
+        Some(answer) => {
+            app.input_buffer = answer.to_string();
+            app.cursor_pos = app.input_buffer.chars().count();
+            app.focus = Focus::Input;
+        }
+        None => add_system_message(app, "Нет ответа модели, который можно взять в работу."),
+    }
+}
+
+/// Буфер обмена, в который можно положить текст.
+///
+/// Выделен в трейт, чтобы логику копирования можно было проверить без системного буфера.
+pub trait Clipboard {
+    /// Поместить текст в буфер обмена.
+    fn set_text(&mut self, text: &str) -> Result<(), String>;
+}
+
+/// Системный буфер обмена. Подключается при первом копировании: в терминале без графической
+/// сессии (например, по SSH) он недоступен, и это не должно мешать запуску.
+#[derive(Default)]
+pub struct SystemClipboard {
+    inner: Option<arboard::Clipboard>,
+}
+
+impl std::fmt::Debug for SystemClipboard {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SystemClipboard")
+            .field("connected", &self.inner.is_some())
+            .finish()
+    }
+}
+
+impl Clipboard for SystemClipboard {
+    fn set_text(&mut self, text: &str) -> Result<(), String> {
+        let mut clipboard = match self.inner.take() {
+            Some(clipboard) => clipboard,
+            None => arboard::Clipboard::new().map_err(|err| err.to_string())?,
+        };
+        let result = clipboard.set_text(text).map_err(|err| err.to_string());
+        // В X11 содержимое буфера живёт, пока жив его владелец, поэтому он сохраняется.
+        self.inner = Some(clipboard);
+        result
+    }
+}
+
+/// Скопировать последний ответ модели в системный буфер обмена; `plain` — без разметки.
+pub fn copy_last_answer(app: &mut App, plain: bool) {
+    let history = app.messages.snapshot();
+    let notice = copy_answer_to(&history, &mut app.clipboard, plain);
+    add_system_message(app, &notice);
+}
+
+/// Скопировать последний ответ модели из `history` в `clipboard`.
+///
+/// При `plain` markdown-разметка удаляется: копируется текст в том виде, в каком он
+/// показан в истории. Возвращает сообщение для пользователя о результате.
+fn copy_answer_to(history: &[ChatEntry], clipboard: &mut dyn Clipboard, plain: bool) -> String {
+    let Some(answer) = last_answer(history) else {
+        return "Нет ответа модели, который можно скопировать.".to_string();
+    };
+    let (text, kind) = if plain {
+        (strip_markdown(answer), " без разметки")
+    } else {
+        (answer.to_string(), "")
+    };
+    match clipboard.set_text(&text) {
+        Ok(()) => format!(
+            "Ответ модели скопирован в буфер обмена{} ({} симв.).",
+            kind,
+            text.chars().count()
+        ),
+        Err(err) => format!("Буфер обмена недоступен: {}", err),
+    }
+}
+
+/// Текст последнего ответа модели в истории.
+fn last_answer(history: &[ChatEntry]) -> Option<&str> {
+    history
+        .iter()
+        .rev()
+        .find(|entry| entry.role == Role::Assistant)
+        .map(|entry| entry.text.as_str())
+}
+
+/// Повторно отправить текущей модели последний вопрос пользователя вместе с предшествующим
+/// ему контекстом. Новый ответ добавляется в конец истории, прежние ответы остаются.
+fn reask_last_question(app: &mut App) {
+    if is_busy(app) {
+        return;
+    }
+    let history = app.messages.snapshot();
+    let Some(index) = last_user_index(&history) else {
+        add_system_message(app, "Нет вопроса, который можно переспросить.");
+        return;
+    };
+
+    let context_len = index + 1;
+    let tokens: usize = history[..context_len]
+        .iter()
+        .map(|entry| estimate_tokens(&entry.display()))
+        .sum();
+    add_system_message(
+        app,
+        &format!(
+            "Переспрашиваю {}: в контексте {} сообщений, ~{} токенов.",
+            app.model(),
+            context_len,
+            tokens
+        ),
+    );
+
+    app.network_retry = None;
+    request_answer(app, context_len, 1);
+}
+
+/// Сохранить диалог для продолжения в следующей сессии (см. [`App::save_history`]).
+pub fn save_history(app: &mut App) {
+    save_history_to(app, &settings::history_file_path());
+}
+
+/// Сохранить диалог в файл `path` (`/save`).
+fn save_history_to(app: &mut App, path: &Path) {
+    if normalized_history(app).is_empty() {
+        add_system_message(app, "В диалоге пока нет сообщений для сохранения.");
+        return;
+    }
+    let notice = match app.save_history(path) {
+        Ok(()) => format!("Диалог сохранён в {}", path.display()),
+        Err(err) => format!("Не удалось сохранить диалог: {}", err),
+    };
+    add_system_message(app, &notice);
+}
+
+/// Заменить текущий диалог сохранённым (см. [`App::load_history`]).
+fn resume_history(app: &mut App) {
+    match app.load_history(&settings::history_file_path()) {
+        Ok(0) => add_system_message(app, "Сохранённого диалога нет."),
+        Ok(count) => {
+            update_scroll_offset(app);
+            add_system_message(app, &format!("Диалог восстановлен ({} сообщ.).", count));
+        }
+        Err(err) => add_system_message(app, &format!("Не удалось восстановить диалог: {}", err)),
+    }
+}
+
+/// Удалить из истории последний вопрос пользователя вместе с ответом и служебными записями
+/// после него. Удалённое больше не попадает в контекст модели.
+pub fn undo_last_exchange(app: &mut App) {
+    match remove_last_exchange(&app.messages) {
+        Some(removed) => {
+            // Отложенный повтор, ожидаемый ответ и варианты относились к удалённым записям.
+            app.cancel_request();
+            app.network_retry = None;
+            app.alternatives.clear();
+            app.pending_duplicate = None;
+            let notice = format!("Последний обмен удалён из диалога (записей: {}).", removed);
+            add_system_message(app, &notice);
+            update_scroll_offset(app);
+        }
+        None => add_system_message(app, "Нет вопроса, который можно отменить."),
+    }
+}
+
+/// Обрезать историю перед последним сообщением пользователя. Возвращает число удалённых записей.
+fn remove_last_exchange(messages: &History) -> Option<usize> {
+    let history = messages.snapshot();
+    let index = last_user_index(&history)?;
+    messages.truncate(index);
+    Some(history.len() - index)
+}
+
+/// Заменить последний ответ модели вариантом с номером `number` из [`App::alternatives`].
+fn pick_alternative(app: &mut App, number: usize) {
+    let Some(text) = app.alternatives.get(number - 1).cloned() else {
+        let notice = match app.alternatives.len() {
+            0 => "Для последнего ответа нет других вариантов.".to_string(),
+            count => format!(
+                "Нет варианта {}: доступны варианты с 1 по {}.",
+                number, count
+            ),
+        };
+        add_system_message(app, &notice);
+        return;
+    };
+
+    app.messages.replace_last(Role::Assistant, &text);
+    add_system_message(app, &format!("Ответ заменён вариантом {}.", number));
+    update_scroll_offset(app);
+}
+
+/// Описание дополнительных вариантов ответа для истории: все варианты, кроме первого,
+/// который уже показан как ответ.
+fn describe_alternatives(alternatives: &[String]) -> String {
+    let mut lines = vec![format!(
+        "Модель предложила вариантов: {}. Показан 1-й, выбрать другой — /pick N.",
+        alternatives.len()
+    )];
+    for (i, text) in alternatives.iter().enumerate().skip(1) {
+        lines.push(format!("— Вариант {} —\n{}", i + 1, text));
+    }
+    lines.join("\n")
+}
+
+/// Предупреждение, если модель вернула меньше вариантов ответа, чем `requested`.
+///
+/// Параметр `num_alternatives` не описан в документации API: сервер, не знающий его, молча
+/// присылает один вариант.
+fn missing_alternatives(requested: u32, received: usize) -> Option<String> {
+    (received < requested as usize).then(|| {
+        format!(
+            "Запрошено вариантов ответа: {}, получено: {}. Модель может не поддерживать \
+             несколько вариантов.",
+            requested, received
+        )
+    })
+}
+
+/// Индекс последнего сообщения пользователя в истории.
+fn last_user_index(history: &[ChatEntry]) -> Option<usize> {
+    history.iter().rposition(|entry| entry.role == Role::User)
+}
+
+/// История диалога, подготовленная к сохранению и экспорту.
+///
+/// Остаются только сообщения пользователя и ответы модели: приветствие, служебные записи
+/// и ошибки отбрасываются, пробелы в конце текста обрезаются.
+pub fn normalized_history(app: &App) -> Vec<ChatEntry> {
+    normalize_entries(&app.messages.snapshot())
+}
+
+/// Нормализация записей для [`normalized_history`].
+fn normalize_entries(entries: &[ChatEntry]) -> Vec<ChatEntry> {
+    entries
+        .iter()
+        .filter(|entry| matches!(entry.role, Role::User | Role::Assistant))
+        .map(|entry| ChatEntry {
+            text: entry.text.trim_end().to_string(),
+            ..entry.clone()
+        })
+        .filter(|entry| !entry.text.is_empty())
+        .collect()
+}
+
+/// Сохранить диалог в файл `path` функцией `export` и сообщить результат в истории.
+fn export_dialog(app: &mut App, path: &str, export: fn(&App, &Path) -> io::Result<()>) {
+    let Some(path) = output_path(app, path) else {
+        return;
+    };
+    let notice = match export(app, &path) {
+        Ok(()) => format!("Диалог сохранён в {}", path.display()),
+        Err(err) => format!("Не удалось сохранить диалог: {}", err),
+    };
+    add_system_message(app, &notice);
+}
+
+/// Путь файла для `/save` и `/export` (см. [`settings::resolve_output_path`]).
+///
+/// `None`, если путь неверен или файл уже существует и перезапись ещё не подтверждена:
+/// причина сообщается в истории.
+fn output_path(app: &mut App, raw: &str) -> Option<PathBuf> {
+    let path = match settings::resolve_output_path(raw) {
+        Ok(path) => path,
+        Err(err) => {
+            add_system_message(app, &format!("Не удалось сохранить диалог: {}", err));
+            return None;
+        }
+    };
+    if confirm_overwrite(&mut app.pending_overwrite, &path) {
+        return Some(path);
+    }
+    let notice = format!(
+        "Файл {} уже существует. Повторите команду, чтобы перезаписать его.",
+        path.display()
+    );
+    add_system_message(app, &notice);
+    None
+}
+
+/// Можно ли записать файл `path`: его нет или перезапись подтверждена повтором команды.
+///
+/// `pending` хранит путь, ожидающий подтверждения; первая попытка записать в существующий
+/// файл запоминает его и возвращает `false`.
+fn confirm_overwrite(pending: &mut Option<PathBuf>, path: &Path) -> bool {
+    if !path.exists() || pending.as_deref() == Some(path) {
+        *pending = None;
+        return true;
+    }
+    *pending = Some(path.to_path_buf());
+    false
+}
+
+/// Записать в файл `path` диалог `entries` Markdown-документом (см. [`App::export_markdown`]).
+///
+/// Записи нормализуются так же, как в [`normalized_history`]: приветствие, служебные
+/// записи и ошибки в файл не попадают.
+pub fn write_markdown(
+    path: &Path,
+    entries: &[ChatEntry],
+    model: &str,
+    prefixes: &Prefixes,
+) -> io::Result<()> {
+    let markdown = render_dialog_markdown(&normalize_entries(entries), model, prefixes);
+    fs::write(path, markdown)
+}
+
+/// Markdown-документ с записями диалога.
+///
+/// Каждая запись начинается с подписи жирным (`**Вы:**`); ответы без префикса в настройках
+/// подписываются моделью, давшей ответ, или `GPT`, если модель неизвестна. Текст записей
+/// вставляется как есть, поэтому блоки кода в ответах сохраняются.
+fn render_dialog_markdown(entries: &[ChatEntry], model: &str, prefixes: &Prefixes) -> String {
+    let mut markdown = format!("# Диалог с {}\n", model);
+    for entry in entries {
+        let label = match entry.role.prefix(prefixes) {
+            "" if entry.role == Role::Assistant => entry.model.as_deref().unwrap_or("GPT"),
+            prefix => prefix,
+        };
+        if label.is_empty() {
+            markdown.push_str(&format!("\n{}\n", entry.text));
+        } else {
+            markdown.push_str(&format!("\n**{}:**\n\n{}\n", label, entry.text));
+        }
+    }
+    markdown
+}
+
+/// Сохранить историю диалога в самодостаточный HTML-файл.
+pub fn export_html(app: &App, path: &Path) -> io::Result<()> {
+    let html = render_html(&normalized_history(app), app.model(), &app.prefs.prefixes());
+    fs::write(path, html)
+}
+
+/// HTML-документ с записями диалога.
+///
+/// Ответы модели переводятся из Markdown в HTML, остальные записи выводятся как есть.
+/// Весь текст экранируется, включая HTML-вставки внутри Markdown. Записи подписываются
+/// префиксами из настроек, ответы без префикса — моделью, давшей ответ (для старых записей —
+/// текущей моделью `model`).
+fn render_html(entries: &[ChatEntry], model: &str, prefixes: &Prefixes) -> String {
+    let mut body = String::new();
+    for entry in entries {
+        let class = match entry.role {
+            Role::User => "user",
+            Role::Assistant => "assistant",
+            Role::System => "system",
+            Role::Info => "info",
+            Role::Error => "error",
+        };
+        let label = match entry.role.prefix(prefixes) {
+            "" if entry.role == Role::Assistant => entry.model.as_deref().unwrap_or(model),
+            prefix => prefix,
+        };
+        let content = match entry.role {
+            Role::Assistant => markdown_to_html(&entry.text),
+            _ => format!("<p class=\"plain\">{}</p>\n", escape_html(&entry.text)),
+        };
+
+        body.push_str(&format!("<div class=\"entry {}\">\n", class));
+        if !label.is_empty() {
+            body.push_str(&format!(
+                "<div class=\"role\">{}</div>\n",
+                escape_html(label)
+            ));
+        }
+        body.push_str(&content);
+        body.push_str("</div>\n");
+    }
+
+    format!(
+        "<!DOCTYPE html>\n<html lang=\"ru\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>Диалог с {model}</title>\n<style>\n{style}</style>\n</head>\n\
+         <body>\n<h1>Диалог с {model}</h1>\n{body}</body>\n</html>\n",
+        model = escape_html(model),
+        style = HTML_STYLE,
+        body = body,
+    )
+}
+
+/// Перевести Markdown в HTML, выводя встроенный в него HTML как обычный текст.
+fn markdown_to_html(text: &str) -> String {
+    let options = Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH;
+    let events = Parser::new_ext(text, options).map(|event| match event {
+        Event::Html(raw) | Event::InlineHtml(raw) => Event::Text(raw),
+        other => other,
+    });
+
+    let mut output = String::new();
+    html::push_html(&mut output, events);
+    output
+}
+
+/// Экранировать спецсимволы HTML.
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+/// Описание системных инструкций для показа пользователю.
+///
+/// Без `full` длинные инструкции сокращаются до [`SYSTEM_PROMPT_PREVIEW`] символов.
+fn describe_system_prompts(prompts: &[String], full: bool) -> String {
+    if prompts.is_empty() {
+        return "Системные инструкции не заданы.".to_string();
+    }
+
+    let mut truncated = false;
+    let mut lines = vec!["Системные инструкции:".to_string()];
+    for (i, prompt) in prompts.iter().enumerate() {
+        let text = if !full && prompt.chars().count() > SYSTEM_PROMPT_PREVIEW {
+            truncated = true;
+            let preview: String = prompt.chars().take(SYSTEM_PROMPT_PREVIEW).collect();
+            format!("{}…", preview)
+        } else {
+            prompt.clone()
+        };
+        lines.push(format!("{}. {}", i + 1, text));
+    }
+
+    if truncated {
+        lines.push("Полный текст: /system full".to_string());
+    }
+    lines.join("\n")
+}
+
+/// Отправить сообщение нейросети и обработать полученный результат.
+///
+/// Если ввод — команда приложения (см. [`parse_command`]), она выполняется без обращения к модели.
+pub fn send_message_to_gpt(app: &mut App) {
+    if let Some(command) = parse_command(&app.input_buffer) {
+        app.input_history.push(&app.input_buffer);
         app.input_buffer.clear();
         app.cursor_pos = 0;
+        execute_command(app, command);
+        return;
+    }
+
+    if !app.input_buffer.trim().is_empty() {
+        if app.prefs.confirm_duplicate && !confirm_duplicate(app) {
+            return;
+        }
+        if app.prefs.confirm_before_send {
+            app.send_confirmation = Some(send_estimate(
+                &app.gpt_client.gpt_options,
+                &app.messages.snapshot(),
+                &app.input_buffer,
+            ));
+            return;
+        }
+
+        dispatch_input(app);
+    }
+}
+
+/// Ответ пользователя на запрос подтверждения отправки (`confirm_before_send`).
+pub fn answer_send_confirmation(app: &mut App, confirmed: bool) {
+    if app.send_confirmation.take().is_some() && confirmed {
+        dispatch_input(app);
+    }
+}
+
+/// Идёт ли запрос к модели. Если да, пользователь получает подсказку подождать.
+fn is_busy(app: &mut App) -> bool {
+    if app.is_loading() {
+        add_system_message(
+            app,
+            "Модель ещё отвечает на предыдущий запрос, дождитесь ответа.",
+        );
+    }
+    app.is_loading()
+}
+
+/// Текст подтверждения отправки `input` с оценкой расхода токенов.
+///
+/// Контекст считается так же, как его соберёт клиент: системные инструкции и последние
+/// обмены истории `entries` вместе с новым сообщением.
+fn send_estimate(options: &GPTOptions, entries: &[ChatEntry], input: &str) -> String {
+    let mut context = chat_messages(entries);
+    context.push(ChatMessage::user(input));
+    let system: usize = options
+        .system_messages()
+        .map(|prompt| estimate_tokens(prompt))
+        .sum();
+    let dialog: usize = recent_exchanges(&context, options.max_history_exchanges)
+        .iter()
+        .map(|message| estimate_tokens(&message.text))
+        .sum();
+    let reply = options.chat_max_tokens.unwrap_or(options.max_tokens);
+
+    format!(
+        "Отправить запрос к {}?\nКонтекст: ~{} токенов, ответ: до {} токенов.\n\
+         [y] отправить   [n] отмена",
+        options.model,
+        system + dialog,
+        reply
+    )
+}
 
-        // Автоматическая прокрутка к новым сообщениям.
-        update_scroll_offset(app);
+/// Поставить ввод в очередь и отправить, если модель свободна.
+///
+/// Пока модель отвечает, сообщение ждёт в очереди и уходит после ответа: в историю оно
+/// попадает только при отправке, поэтому контекст не перемешивается.
+fn dispatch_input(app: &mut App) {
+    let text = std::mem::take(&mut app.input_buffer);
+    app.cursor_pos = 0;
+    app.input_history.push(&text);
+    app.pending_inputs.push_back(unescape_message(text));
+
+    // Новый запрос отменяет ожидающий повтор предыдущего.
+    app.network_retry = None;
+    send_queued(app);
+}
+
+/// Текст сообщения модели без экранирующего `/`: `//etc/hosts` отправляется как `/etc/hosts`.
+fn unescape_message(text: String) -> String {
+    let start = text.len() - text.trim_start().len();
+    if text[start..].starts_with("//") {
+        let mut text = text;
+        text.remove(start);
+        text
+    } else {
+        text
+    }
+}
+
+/// Отправить следующее сообщение из очереди, если модель не занята запросом или повтором.
+fn send_queued(app: &mut App) {
+    if app.is_loading() || app.network_retry.is_some() {
+        return;
+    }
+    if let Some(context_len) = push_next_queued(&app.messages, &mut app.pending_inputs) {
+        app.stats.record_user_message();
+        request_answer(app, context_len, 1);
+    }
+}
+
+/// Перенести первое сообщение очереди в историю. Возвращает длину контекста запроса.
+fn push_next_queued(messages: &History, queue: &mut VecDeque<String>) -> Option<usize> {
+    let text = queue.pop_front()?;
+    messages.push(ChatEntry::user(text));
+    Some(messages.len())
+}
+
+/// Проверить, можно ли отправлять текущий ввод, если он повторяет предыдущее сообщение.
+///
+/// Первая попытка отправить повтор только предупреждает пользователя, повторный Enter
+/// с тем же текстом подтверждает отправку.
+fn confirm_duplicate(app: &mut App) -> bool {
+    let text = app.input_buffer.clone();
+    if !repeats_last_user_message(&app.messages.snapshot(), &text)
+        || app.pending_duplicate.as_deref() == Some(text.as_str())
+    {
+        app.pending_duplicate = None;
+        return true;
+    }
+
+    app.pending_duplicate = Some(text);
+    add_system_message(
+        app,
+        "Сообщение совпадает с предыдущим. Нажмите Enter ещё раз, чтобы всё равно отправить.",
+    );
+    false
+}
+
+/// Совпадает ли `text` с последним сообщением пользователя (без учёта пробелов по краям).
+fn repeats_last_user_message(history: &[ChatEntry], text: &str) -> bool {
+    history
+        .iter()
+        .rev()
+        .find(|entry| entry.role == Role::User)
+        .is_some_and(|entry| entry.text.trim() == text.trim())
+}
+
+/// Запросить в фоне ответ модели по первым `context_len` сообщениям истории.
+///
+/// Результат обработает [`handle_reply`].
+fn request_answer(app: &mut App, context_len: usize, attempt: u32) {
+    let history = app.messages.snapshot();
+    let context = chat_messages(&history[..context_len.min(history.len())]);
+
+    let options = &app.gpt_client.gpt_options;
+    let reserve = options.chat_max_tokens.unwrap_or(options.max_tokens);
+    let sent = recent_exchanges(&context, options.max_history_exchanges);
+    if let Some((tokens, limit)) = context_overflow(sent, &options.model, reserve) {
+        let notice = format!(
+            "Диалог (~{} токенов) вместе с ответом не помещается в лимит модели ({}): \
+             старые сообщения не будут отправлены.",
+            tokens, limit
+        );
+        add_system_message(app, &notice);
+    }
+
+    let client = app.gpt_client.clone();
+    // Несколько вариантов ответа приходят только целиком.
+    if app.prefs.stream && client.gpt_options.alternatives <= 1 {
+        app.begin_stream(
+            async move { client.chat_with_gpt_stream(&context).await },
+            move |result| ReplyKind::StreamEnd {
+                result,
+                context_len,
+                attempt,
+            },
+        );
+        return;
+    }
+
+    app.begin_request(async move {
+        ReplyKind::Answer {
+            result: client.chat_with_gpt_all(&context).await,
+            context_len,
+            attempt,
+        }
+    });
+}
+
+/// Обработать ответ фонового запроса к модели. Ответы отменённых запросов отбрасываются.
+pub fn handle_reply(app: &mut App, reply: Reply) {
+    if let ReplyKind::Delta(delta) = &reply.kind {
+        if app.accept_delta(&reply) {
+            append_delta(&app.messages, &mut app.receiving, &reply.model, delta);
+            update_scroll_offset(app);
+        }
+        return;
+    }
+    if !app.accept_reply(&reply) {
+        return;
+    }
+
+    match reply.kind {
+        ReplyKind::Answer {
+            result,
+            context_len,
+            attempt,
+        } => apply_answer(
+            app,
+            &reply.model,
+            reply.elapsed,
+            result,
+            context_len,
+            attempt,
+        ),
+        ReplyKind::Continuation(result) => {
+            apply_continuation(app, &reply.model, reply.elapsed, result)
+        }
+        ReplyKind::StreamEnd {
+            result,
+            context_len,
+            attempt,
+        } => finish_stream(
+            app,
+            &reply.model,
+            reply.elapsed,
+            result,
+            context_len,
+            attempt,
+        ),
+        ReplyKind::Delta(_) => {}
+    }
+
+    send_queued(app);
+}
+
+/// Дописать фрагмент ответа, получаемого по частям: первый фрагмент открывает новую
+/// запись модели `model`, следующие дописываются к ней. `receiving` отмечает, что запись
+/// открыта.
+fn append_delta(messages: &History, receiving: &mut bool, model: &str, delta: &str) {
+    if *receiving {
+        messages.extend_last(Role::Assistant, delta);
+    } else {
+        messages.push(ChatEntry::assistant(delta).with_model(model));
+        *receiving = true;
+    }
+}
+
+/// Завершить ответ, полученный по частям.
+///
+/// Итоговый ответ обрабатывается как у обычного запроса (см. [`apply_answer`]): учитываются
+/// токены, обрезанный ответ предлагается продолжить, при потере сети запрос повторяется.
+/// Частично показанный текст при этом заменяется итоговым или снимается перед повтором.
+/// Обрыв без повтора оставляет полученную часть в истории с пометкой.
+fn finish_stream(
+    app: &mut App,
+    model: &str,
+    elapsed: Duration,
+    result: Result<GPTAnswer, GPTError>,
+    context_len: usize,
+    attempt: u32,
+) {
+    if std::mem::take(&mut app.receiving) {
+        if let Err(err) = &result
+            && !will_retry(err, attempt)
+        {
+            app.update_connection(&result);
+            app.alternatives.clear();
+            app.messages.push(ChatEntry::error(err.to_string()));
+            add_system_message(app, "Ответ получен не полностью.");
+            update_scroll_offset(app);
+            return;
+        }
+        app.messages.remove_last(Role::Assistant);
+    }
+
+    let result = result.map(|answer| vec![answer]);
+    apply_answer(app, model, elapsed, result, context_len, attempt);
+}
+
+/// Повторит ли [`apply_answer`] запрос, завершившийся ошибкой `err` на попытке `attempt`.
+fn will_retry(err: &GPTError, attempt: u32) -> bool {
+    matches!(err, GPTError::Network { .. } | GPTError::RateLimited { .. })
+        && attempt < NETWORK_RETRY_ATTEMPTS
+}
+
+/// Прервать получение ответа по частям (`Esc`). Полученная часть остаётся в истории
+/// с пометкой.
+pub fn stop_stream(app: &mut App) {
+    let received = app.receiving;
+    let queued = app.pending_inputs.len();
+    app.cancel_request();
+    if received {
+        add_system_message(app, "Получение ответа прервано: ответ неполный.");
+    } else {
+        add_system_message(app, "Запрос к модели отменён.");
+    }
+    if queued > 0 {
+        let notice = format!(
+            "Сообщения из очереди не отправлены ({}): их можно вернуть в поле ввода стрелкой ↑.",
+            queued
+        );
+        add_system_message(app, &notice);
+    }
+    update_scroll_offset(app);
+}
+
+/// Добавить ответ модели в историю.
+///
+/// При потере сети ответ не добавляется в историю: вместо этого планируется повтор
+/// через [`NETWORK_RETRY_DELAY`], который выполнит [`on_tick`]. При превышении лимита
+/// запросов повтор планируется так же, но через паузу, названную сервером.
+fn apply_answer(
+    app: &mut App,
+    model: &str,
+    elapsed: Duration,
+    result: Result<Vec<GPTAnswer>, GPTError>,
+    context_len: usize,
+    attempt: u32,
+) {
+    app.update_connection(&result);
+    app.alternatives.clear();
+
+    match result {
+        Ok(answers) => {
+            app.stats.record_answer(model, elapsed);
+            // Расход общий для всех вариантов ответа: учитывается один раз.
+            app.stats
+                .record_tokens(answers[0].input_tokens, answers[0].completion_tokens);
+            let threshold = app.prefs.blank_lines_threshold;
+            let texts: Vec<String> = answers
+                .iter()
+                .map(|answer| collapse_blank_lines(&answer.text, threshold))
+                .collect();
+
+            app.messages
+                .push(ChatEntry::assistant(texts[0].as_str()).with_model(model));
+            if answers[0].truncated {
+                report_truncated(app);
+            }
+            if texts.len() > 1 {
+                add_system_message(app, &describe_alternatives(&texts));
+            }
+            let requested = app.gpt_client.gpt_options.alternatives;
+            if let Some(notice) = missing_alternatives(requested, texts.len()) {
+                add_system_message(app, &notice);
+            }
+            if texts.len() > 1 {
+                app.alternatives = texts;
+            }
+        }
+        Err(err) => match err {
+            GPTError::Network { .. } if attempt < NETWORK_RETRY_ATTEMPTS => {
+                let retry = NetworkRetry {
+                    reason: "Нет сети",
+                    deadline: Instant::now() + NETWORK_RETRY_DELAY,
+                    attempt: attempt + 1,
+                    context_len,
+                };
+                schedule_retry(app, "Нет доступа к сети", retry);
+            }
+            GPTError::Network { .. } => add_system_message(
+                app,
+                "Соединение не восстановлено. Проверьте сеть и отправьте сообщение снова.",
+            ),
+            GPTError::RateLimited { retry_after } if attempt < NETWORK_RETRY_ATTEMPTS => {
+                let retry = NetworkRetry {
+                    reason: "Лимит запросов",
+                    deadline: Instant::now() + retry_after.unwrap_or(NETWORK_RETRY_DELAY),
+                    attempt: attempt + 1,
+                    context_len,
+                };
+                schedule_retry(app, "Превышен лимит запросов к API", retry);
+            }
+            GPTError::ContextLengthExceeded { .. } => {
+                app.messages.push(ChatEntry::error(err.to_string()));
+                add_system_message(
+                    app,
+                    "Очистите историю (Ctrl+R или /clear) или уменьшите max_history_exchanges \
+                     в настройках.",
+                );
+            }
+            _ => app.messages.push(ChatEntry::error(err.to_string())),
+        },
+    }
+
+    // Автоматическая прокрутка к новым сообщениям.
+    update_scroll_offset(app);
+}
+
+/// Запланировать повтор запроса и сообщить о нём в истории.
+fn schedule_retry(app: &mut App, cause: &str, retry: NetworkRetry) {
+    add_system_message(
+        app,
+        &format!(
+            "{}. Повтор через {} с (попытка {} из {}).",
+            cause,
+            retry.seconds_left(),
+            retry.attempt,
+            NETWORK_RETRY_ATTEMPTS
+        ),
+    );
+    app.network_retry = Some(retry);
+}
+
+/// Оценка размера контекста и лимит модели, если контекст вместе с `reserve` токенами ответа
+/// превышает лимит. Для моделей без известного лимита — `None`.
+fn context_overflow(context: &[ChatMessage], model: &str, reserve: i64) -> Option<(usize, i64)> {
+    let limit = model_token_limit(model)?;
+    let tokens: usize = context
+        .iter()
+        .map(|message| estimate_tokens(&message.text))
+        .sum();
+    (tokens as i64 + reserve > limit).then_some((tokens, limit))
+}
+
+/// Периодическая обработка между событиями ввода: результат проверки соединения и повтор
+/// запроса по истечении отсчёта.
+pub fn on_tick(app: &mut App) {
+    app.tick = app.tick.wrapping_add(1);
+    app.poll_ping();
+    app.poll_token_count();
+
+    let due = !app.is_loading()
+        && app
+            .network_retry
+            .as_ref()
+            .is_some_and(|retry| Instant::now() >= retry.deadline);
+
+    if due && let Some(retry) = app.network_retry.take() {
+        request_answer(app, retry.context_len, retry.attempt);
     }
 }
 
 /// Обновить смещение скролла для показа новых сообщений.
+///
+/// Не действует, пока пользователь читает историю выше (см. `App::follow_tail`). Число
+/// экранных строк известно с прошлой отрисовки; с учётом новых записей смещение уточняет
+/// следующая отрисовка.
 fn update_scroll_offset(app: &mut App) {
-    const VISIBLE_LINES: usize = 20;
-    if app.messages.len() > VISIBLE_LINES {
-        app.scroll_offset = (app.messages.len() - VISIBLE_LINES) as u16;
+    if app.follow_tail {
+        app.scroll_offset = max_scroll_offset(app.history_lines, app.history_height);
     }
 }
 
 /// Добавить системное сообщение в историю.
-pub fn _add_system_message(app: &mut App, message: &str) {
-    app.messages.push(format!("Система: {}", message));
+pub fn add_system_message(app: &mut App, message: &str) {
+    app.messages.push(ChatEntry::system(message));
 }
 
 /// Очистить историю сообщений.
 pub fn clear_messages(app: &mut App) {
     app.messages.clear();
-    app.messages.push("YandexGPT готов к диалогу.".to_string());
+    app.messages.push(ChatEntry::info(GREETING));
     app.scroll_offset = 0;
+    app.follow_tail = true;
+    app.search.cancel();
+    app.cancel_request();
+    app.network_retry = None;
+    app.alternatives.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::history::contents;
+
+    #[test]
+    fn test_parse_command() {
+        assert_eq!(parse_command("/compact"), Some(Command::Compact));
+        assert_eq!(parse_command("/stats"), Some(Command::Stats));
+        assert_eq!(parse_command("/reload"), Some(Command::Reload));
+        assert_eq!(parse_command("/continue"), Some(Command::Continue));
+        assert_eq!(parse_command("  /compact  "), Some(Command::Compact));
+        assert_eq!(parse_command("compact"), None);
+        assert_eq!(parse_command("/"), None);
+        assert_eq!(
+            parse_command("/unknown"),
+            Some(Command::Unknown("unknown".to_string()))
+        );
+    }
+
+    /// Ожидаемый результат разбора неверно набранной команды.
+    fn malformed(usage: &'static str) -> Option<Command> {
+        Some(Command::Malformed { usage })
+    }
+
+    #[test]
+    fn test_parse_model_and_temp_commands() {
+        assert_eq!(
+            parse_command("/model yandexgpt-pro"),
+            Some(Command::SwitchModel {
+                model: "yandexgpt-pro".to_string(),
+                ask: false
+            })
+        );
+        assert_eq!(parse_command("/model"), malformed("/model <модель>"));
+        assert_eq!(parse_command("/model a b"), malformed("/model <модель>"));
+
+        assert_eq!(parse_command("/temp 0.2"), Some(Command::Temperature(0.2)));
+        assert_eq!(parse_command("/temp 0,5"), Some(Command::Temperature(0.5)));
+        // Диапазон проверяется при выполнении, разбор принимает любое число.
+        assert_eq!(parse_command("/temp 3"), Some(Command::Temperature(3.0)));
+        assert_eq!(parse_command("/temp"), malformed("/temp <0..1>"));
+        assert_eq!(parse_command("/temp тепло"), malformed("/temp <0..1>"));
+    }
+
+    #[test]
+    fn test_parse_models_command() {
+        assert_eq!(parse_command("/models"), Some(Command::Models));
+        assert_eq!(parse_command("/models all"), malformed("/models"));
+    }
+
+    #[test]
+    fn test_parse_profile_command() {
+        assert_eq!(parse_command("/profile"), Some(Command::Profile(None)));
+        assert_eq!(
+            parse_command("/profile switch work"),
+            Some(Command::Profile(Some("work".to_string())))
+        );
+        let usage = malformed("/profile [switch <имя>]");
+        assert_eq!(parse_command("/profile switch"), usage);
+        assert_eq!(parse_command("/profile work"), usage);
+    }
+
+    #[test]
+    fn test_parse_copy_command() {
+        assert_eq!(parse_command("/copy"), Some(Command::Copy { plain: false }));
+        assert_eq!(
+            parse_command("/copy plain"),
+            Some(Command::Copy { plain: true })
+        );
+        assert_eq!(parse_command("/copy all"), malformed("/copy [plain]"));
+    }
+
+    #[test]
+    fn test_parse_clear_and_save_commands() {
+        assert_eq!(parse_command("/clear"), Some(Command::Clear));
+        assert_eq!(parse_command("/clear all"), malformed("/clear"));
+        assert_eq!(
+            parse_command("/save мой диалог.json"),
+            Some(Command::Save {
+                path: "мой диалог.json".to_string()
+            })
+        );
+        assert_eq!(parse_command("/save"), malformed("/save <файл.json>"));
+    }
+
+    #[test]
+    fn test_double_slash_escapes_a_message() {
+        assert_eq!(parse_command("//etc/hosts — что это?"), None);
+        assert_eq!(parse_command("  //clear"), None);
+        assert_eq!(
+            unescape_message("//etc/hosts — что это?".to_string()),
+            "/etc/hosts — что это?"
+        );
+        assert_eq!(unescape_message("  //clear".to_string()), "  /clear");
+        assert_eq!(unescape_message("a // b".to_string()), "a // b");
+        assert_eq!(unescape_message("/".to_string()), "/");
+    }
+
+    #[test]
+    fn test_overwrite_needs_repeated_command() {
+        let existing = std::env::temp_dir().join(format!("ym-overwrite-{}.md", std::process::id()));
+        fs::write(&existing, "старый диалог").unwrap();
+        let missing = std::env::temp_dir().join("ym-overwrite-missing.md");
+        let mut pending = None;
+
+        assert!(confirm_overwrite(&mut pending, &missing));
+        assert!(!confirm_overwrite(&mut pending, &existing));
+        assert_eq!(pending.as_deref(), Some(existing.as_path()));
+        assert!(confirm_overwrite(&mut pending, &existing));
+        assert_eq!(pending, None);
+        // Подтверждение действует один раз.
+        assert!(!confirm_overwrite(&mut pending, &existing));
+        fs::remove_file(&existing).unwrap();
+    }
+
+    #[test]
+    fn test_every_listed_command_is_parsed() {
+        for (usage, _) in COMMANDS {
+            let name = usage[1..].split_whitespace().next().unwrap();
+            assert_eq!(command_usage(name), Some(*usage));
+            assert!(
+                !matches!(parse_command(usage), Some(Command::Unknown(_)) | None),
+                "{}",
+                usage
+            );
+        }
+        assert!(describe_commands().contains("/temp <0..1> — задать температуру"));
+    }
+
+    #[test]
+    fn test_describe_profiles() {
+        let names = ["home".to_string(), "work".to_string()];
+        assert_eq!(
+            describe_profiles(Some("work"), &names),
+            "Профиль: work. Профили: home, work."
+        );
+        assert_eq!(
+            describe_profiles(None, &[]),
+            "Профиль не выбран, используется access.json. Сохранённых профилей нет."
+        );
+    }
+
+    #[test]
+    fn test_describe_models_marks_current() {
+        let models = [
+            ModelInfo::new("yandexgpt/latest", "Pro"),
+            ModelInfo::new("llama/latest", "Llama"),
+        ];
+        let text = describe_models(&models, "llama/latest");
+
+        assert!(text.contains("yandexgpt/latest — Pro, контекст 32768 токенов\n"));
+        assert!(text.ends_with("llama/latest — Llama (текущая)"));
+    }
+
+    #[test]
+    fn test_parse_system_command() {
+        assert_eq!(
+            parse_command("/system"),
+            Some(Command::ShowSystem { full: false })
+        );
+        assert_eq!(
+            parse_command("/system full"),
+            Some(Command::ShowSystem { full: true })
+        );
+        assert_eq!(parse_command("/system clear"), Some(Command::ClearSystem));
+        assert_eq!(
+            parse_command("/system nonsense"),
+            malformed("/system [full | clear]")
+        );
+    }
+
+    #[test]
+    fn test_parse_export_command() {
+        assert_eq!(
+            parse_command("/export html dialog.html"),
+            Some(Command::ExportHtml {
+                path: "dialog.html".to_string()
+            })
+        );
+        assert_eq!(
+            parse_command("/export md мой диалог.md"),
+            Some(Command::ExportMarkdown {
+                path: "мой диалог.md".to_string()
+            })
+        );
+        let usage = "/export html | md <файл>";
+        assert_eq!(parse_command("/export html"), malformed(usage));
+        assert_eq!(parse_command("/export pdf dialog.pdf"), malformed(usage));
+    }
+
+    #[test]
+    fn test_parse_switch_model_command() {
+        assert_eq!(
+            parse_command("/switch-model yandexgpt/rc"),
+            Some(Command::SwitchModel {
+                model: "yandexgpt/rc".to_string(),
+                ask: false
+            })
+        );
+        assert_eq!(
+            parse_command("/switch-model yandexgpt/rc ask"),
+            Some(Command::SwitchModel {
+                model: "yandexgpt/rc".to_string(),
+                ask: true
+            })
+        );
+        let usage = "/switch-model <модель> [ask]";
+        assert_eq!(parse_command("/switch-model"), malformed(usage));
+        assert_eq!(
+            parse_command("/switch-model yandexgpt/rc later"),
+            malformed(usage)
+        );
+    }
+
+    #[test]
+    fn test_last_answer() {
+        let history = vec![
+            ChatEntry::info(GREETING),
+            ChatEntry::user("Составь план"),
+            ChatEntry::assistant("1. Шаг"),
+            ChatEntry::error("Сервис недоступен"),
+        ];
+        assert_eq!(parse_command("/reuse"), Some(Command::Reuse));
+        assert_eq!(last_answer(&history), Some("1. Шаг"));
+        assert_eq!(last_answer(&history[..2]), None);
+    }
+
+    /// Буфер обмена в памяти; `None` имитирует недоступный системный буфер.
+    struct MemoryClipboard(Option<String>);
+
+    impl Clipboard for MemoryClipboard {
+        fn set_text(&mut self, text: &str) -> Result<(), String> {
+            match &mut self.0 {
+                Some(content) => {
+                    *content = text.to_string();
+                    Ok(())
+                }
+                None => Err("нет дисплея".to_string()),
+            }
+        }
+    }
+
+    #[test]
+    fn test_copy_answer_to_clipboard() {
+        let history = vec![
+            ChatEntry::user("Привет"),
+            ChatEntry::assistant("Первый"),
+            ChatEntry::user("Ещё"),
+            ChatEntry::assistant("Второй"),
+            ChatEntry::system("Справка"),
+        ];
+        let mut clipboard = MemoryClipboard(Some(String::new()));
+
+        let notice = copy_answer_to(&history, &mut clipboard, false);
+        assert_eq!(clipboard.0.as_deref(), Some("Второй"));
+        assert!(notice.contains("скопирован"));
+
+        let notice = copy_answer_to(&history[..1], &mut clipboard, false);
+        assert_eq!(notice, "Нет ответа модели, который можно скопировать.");
+        assert_eq!(clipboard.0.as_deref(), Some("Второй"));
+    }
+
+    #[test]
+    fn test_copy_answer_without_markup() {
+        let history = vec![ChatEntry::assistant(
+            "Запустите `cargo test`:\n```\ncargo test\n```",
+        )];
+        let mut clipboard = MemoryClipboard(Some(String::new()));
+
+        let notice = copy_answer_to(&history, &mut clipboard, true);
+        assert_eq!(
+            clipboard.0.as_deref(),
+            Some("Запустите cargo test:\ncargo test")
+        );
+        assert_eq!(
+            notice,
+            "Ответ модели скопирован в буфер обмена без разметки (32 симв.)."
+        );
+    }
+
+    #[test]
+    fn test_copy_answer_reports_unavailable_clipboard() {
+        let history = vec![ChatEntry::assistant("Ответ")];
+        let notice = copy_answer_to(&history, &mut MemoryClipboard(None), false);
+        assert_eq!(notice, "Буфер обмена недоступен: нет дисплея");
+    }
+
+    #[test]
+    fn test_continuation_separator() {
+        assert_eq!(continuation_separator("Первая часть", "вторая часть"), " ");
+        assert_eq!(continuation_separator("Конец фразы.", "Новая фраза"), " ");
+        assert_eq!(continuation_separator("Список:", "- пункт"), "\n");
+        assert_eq!(continuation_separator("Код:", "```rust"), "\n");
+        // Пробел или перевод строки на стыке уже есть.
+        assert_eq!(continuation_separator("Абзац\n", "Дальше"), "");
+        assert_eq!(continuation_separator("Слово", " дальше"), "");
+        assert_eq!(continuation_separator("Слово", ", дальше"), "");
+        assert_eq!(continuation_separator("", "Начало"), "");
+        assert_eq!(continuation_separator("Ответ", ""), "");
+    }
+
+    #[test]
+    fn test_context_overflow() {
+        let context = vec![ChatMessage::user("я".repeat(3000)); 8];
+
+        assert_eq!(
+            context_overflow(&context, "yandexgpt-lite/latest", 2000),
+            Some((8000, 8192))
+        );
+        assert_eq!(context_overflow(&context, "yandexgpt/latest", 2000), None);
+        assert_eq!(context_overflow(&context, "unknown/latest", 2000), None);
+    }
+
+    #[test]
+    fn test_remove_last_exchange_updates_context() {
+        let messages = History::from_entries(vec![
+            ChatEntry::info(GREETING),
+            ChatEntry::user("Первый вопрос"),
+            ChatEntry::assistant("Первый ответ"),
+            ChatEntry::user("Неудачный вопрос"),
+            ChatEntry::assistant("Неудачный ответ"),
+            ChatEntry::system("Ответ обрезан"),
+        ]);
+
+        assert_eq!(parse_command("/undo"), Some(Command::Undo));
+        assert_eq!(remove_last_exchange(&messages), Some(3));
+
+        assert_eq!(
+            chat_messages(&messages.snapshot()),
+            [
+                ChatMessage::user("Первый вопрос"),
+                ChatMessage::assistant("Первый ответ"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_remove_last_exchange_without_questions() {
+        let messages = History::from_entries(vec![ChatEntry::info(GREETING)]);
+
+        assert_eq!(remove_last_exchange(&messages), None);
+        assert_eq!(messages.len(), 1);
+    }
+
+    #[test]
+    fn test_parse_pick_command() {
+        assert_eq!(parse_command("/pick 2"), Some(Command::Pick(2)));
+        assert_eq!(parse_command("/pick 0"), malformed("/pick N"));
+        assert_eq!(parse_command("/pick"), malformed("/pick N"));
+        assert_eq!(parse_command("/pick два"), malformed("/pick N"));
+    }
+
+    #[test]
+    fn test_parse_resume_command() {
+        assert_eq!(parse_command("/resume"), Some(Command::Resume));
+    }
+
+    #[test]
+    fn test_missing_alternatives_are_reported() {
+        assert_eq!(missing_alternatives(1, 1), None);
+        assert_eq!(missing_alternatives(3, 3), None);
+        let notice = missing_alternatives(3, 1).unwrap();
+        assert!(notice.contains("Запрошено вариантов ответа: 3, получено: 1"));
+    }
+
+    #[test]
+    fn test_describe_alternatives_skips_shown_answer() {
+        let texts = vec!["Первый".to_string(), "Второй".to_string()];
+        let notice = describe_alternatives(&texts);
+
+        assert!(notice.contains("вариантов: 2"));
+        assert!(notice.contains("— Вариант 2 —\nВторой"));
+        assert!(!notice.contains("Первый"));
+    }
+
+    #[test]
+    fn test_last_user_index() {
+        let history = vec![
+            ChatEntry::info(GREETING),
+            ChatEntry::user("Вопрос"),
+            ChatEntry::assistant("Ответ"),
+            ChatEntry::system("Модель переключена"),
+        ];
+        assert_eq!(last_user_index(&history), Some(1));
+        assert_eq!(last_user_index(&history[..1]), None);
+    }
+
+    #[test]
+    fn test_normalize_entries_keeps_only_dialog() {
+        let entries = vec![
+            ChatEntry::info(GREETING),
+            ChatEntry::system("Модель переключена"),
+            ChatEntry::user("Вопрос  \n"),
+            ChatEntry::error("Сервис недоступен"),
+            ChatEntry::assistant("Ответ\t"),
+            ChatEntry::user("   "),
+        ];
+
+        assert_eq!(
+            contents(&normalize_entries(&entries)),
+            [(Role::User, "Вопрос"), (Role::Assistant, "Ответ")]
+        );
+        // Время создания записей сохраняется.
+        assert_eq!(
+            normalize_entries(&entries)[0].timestamp,
+            entries[2].timestamp
+        );
+    }
+
+    #[test]
+    fn test_render_html_escapes_user_text() {
+        let entries = vec![ChatEntry::user("<script>alert('x')</script> & co")];
+        let html = render_html(&entries, "yandexgpt/latest", &Prefixes::default());
+
+        assert!(!html.contains("<script>"));
+        assert!(html.contains("&lt;script&gt;alert(&#39;x&#39;)&lt;/script&gt; &amp; co"));
+        assert!(html.contains("class=\"entry user\""));
+    }
+
+    #[test]
+    fn test_render_html_converts_answer_markdown() {
+        let answer =
+            "Пример **кода**:\n\n```rust\nfn main() {}\n```\n\n<img src=x onerror=alert(1)>";
+        let entries = vec![ChatEntry::assistant(answer)];
+        let html = render_html(&entries, "yandexgpt/latest", &Prefixes::default());
+
+        assert!(html.contains("<strong>кода</strong>"));
+        assert!(html.contains("<pre><code class=\"language-rust\">fn main() {}"));
+        assert!(!html.contains("<img"));
+        assert!(html.contains("&lt;img"));
+    }
+
+    #[test]
+    fn test_markdown_export_keeps_headings_and_code() {
+        let entries = [
+            ChatEntry::info(GREETING),
+            ChatEntry::user("Как вывести строку?"),
+            ChatEntry::assistant("Так:\n\n```rust\nprintln!(\"привет\");\n```  \n"),
+            ChatEntry::system("Модель переключена"),
+        ];
+        let path = std::env::temp_dir().join(format!("ym-export-{}.md", std::process::id()));
+        write_markdown(&path, &entries, "yandexgpt/latest", &Prefixes::default()).unwrap();
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(contents.starts_with("# Диалог с yandexgpt/latest\n"));
+        assert!(contents.contains("\n**Вы:**\n\nКак вывести строку?\n"));
+        assert!(contents.contains("\n**GPT:**\n\nТак:\n\n```rust\nprintln!(\"привет\");\n```\n"));
+        assert!(!contents.contains("Модель переключена"));
+        assert!(!contents.contains(GREETING));
+
+        // Ошибка записи возвращается, а не обрывает работу.
+        let missing = std::env::temp_dir()
+            .join("ym-no-such-dir")
+            .join("dialog.md");
+        assert!(
+            write_markdown(&missing, &entries, "yandexgpt/latest", &Prefixes::default()).is_err()
+        );
+    }
+
+    #[test]
+    fn test_export_labels_answers_with_their_model() {
+        let entries = [
+            ChatEntry::user("Привет"),
+            ChatEntry::assistant("Здравствуйте").with_model("yandexgpt-lite/latest"),
+            ChatEntry::assistant("Старый ответ"),
+        ];
+        let markdown = render_dialog_markdown(&entries, "yandexgpt/latest", &Prefixes::default());
+        assert!(markdown.contains("\n**yandexgpt-lite/latest:**\n\nЗдравствуйте\n"));
+        assert!(markdown.contains("\n**GPT:**\n\nСтарый ответ\n"));
+
+        let html = render_html(&entries, "yandexgpt/latest", &Prefixes::default());
+        assert!(html.contains("yandexgpt-lite/latest"));
+        assert!(html.contains("yandexgpt/latest"));
+    }
+
+    #[test]
+    fn test_describe_system_prompts() {
+        assert_eq!(
+            describe_system_prompts(&[], false),
+            "Системные инструкции не заданы."
+        );
+
+        let long = "я".repeat(SYSTEM_PROMPT_PREVIEW + 10);
+        let prompts = vec!["Отвечай кратко.".to_string(), long.clone()];
+
+        let short = describe_system_prompts(&prompts, false);
+        assert!(short.contains("1. Отвечай кратко."));
+        assert!(short.contains(&format!("2. {}…", "я".repeat(SYSTEM_PROMPT_PREVIEW))));
+        assert!(short.ends_with("/system full"));
+
+        let full = describe_system_prompts(&prompts, true);
+        assert!(full.contains(&long));
+        assert!(!full.contains("/system full"));
+    }
+
+    #[test]
+    fn test_send_estimate_counts_system_prompts() {
+        let mut options = GPTOptions::default();
+        // «abc» — один токен по оценке estimate_tokens.
+        assert!(send_estimate(&options, &[], "abc").contains("~1 токенов"));
+
+        options.system_prompt = Some("aaaaaa".to_string());
+        options.system_prompts.push("bbb".to_string());
+        let text = send_estimate(&options, &[], "abc");
+        assert!(text.contains("~4 токенов"), "{text}");
+        assert!(text.contains("Отправить запрос к yandexgpt/latest?"));
+        assert!(text.contains("ответ: до 2000 токенов"));
+    }
+
+    #[test]
+    fn test_send_estimate_follows_history_limit() {
+        let history = vec![
+            ChatEntry::info(GREETING),
+            ChatEntry::user("aaaaaaaaa"),
+            ChatEntry::assistant("bbb"),
+        ];
+        let mut options = GPTOptions {
+            chat_max_tokens: Some(500),
+            ..GPTOptions::default()
+        };
+        let text = send_estimate(&options, &history, "ccc");
+        assert!(text.contains("~5 токенов"), "{text}");
+        assert!(text.contains("ответ: до 500 токенов"));
+
+        // Клиент отправит только последний обмен — и оценка тоже.
+        options.max_history_exchanges = 1;
+        assert!(send_estimate(&options, &history, "ccc").contains("~1 токенов"));
+    }
+
+    #[test]
+    fn test_repeats_last_user_message() {
+        let history = vec![
+            ChatEntry::info(GREETING),
+            ChatEntry::user("Привет"),
+            ChatEntry::assistant("Здравствуйте!"),
+        ];
+
+        assert!(repeats_last_user_message(&history, "Привет"));
+        assert!(repeats_last_user_message(&history, "  Привет "));
+        assert!(!repeats_last_user_message(&history, "Здравствуйте!"));
+        assert!(!repeats_last_user_message(&[], "Привет"));
+    }
+
+    #[test]
+    fn test_queued_inputs_are_sent_in_order() {
+        let history = History::from_entries(vec![ChatEntry::user("Первый")]);
+        let mut queue = VecDeque::from(["Второй".to_string(), "Третий".to_string()]);
+
+        // Ответ на первый вопрос пришёл — уходит второй с контекстом до него включительно.
+        history.push(ChatEntry::assistant("Ответ 1"));
+        assert_eq!(push_next_queued(&history, &mut queue), Some(3));
+        history.push(ChatEntry::assistant("Ответ 2"));
+        assert_eq!(push_next_queued(&history, &mut queue), Some(5));
+        assert_eq!(push_next_queued(&history, &mut queue), None);
+
+        let texts: Vec<String> = history.snapshot().into_iter().map(|e| e.text).collect();
+        assert_eq!(texts, ["Первый", "Ответ 1", "Второй", "Ответ 2", "Третий"]);
+    }
+
+    #[test]
+    fn test_interrupted_stream_is_retried_like_answer() {
+        let network = GPTError::Network {
+            description: "обрыв".to_string(),
+        };
+        assert!(will_retry(&network, 1));
+        assert!(!will_retry(&network, NETWORK_RETRY_ATTEMPTS));
+        assert!(will_retry(&GPTError::RateLimited { retry_after: None }, 1));
+        assert!(!will_retry(&GPTError::ContentFiltered, 1));
+    }
+
+    #[tokio::test]
+    async fn test_stream_deltas_build_one_answer() {
+        use futures::StreamExt;
+
+        let history = History::from_entries(vec![ChatEntry::user("Как дела?")]);
+        let mut receiving = false;
+        let mut deltas = futures::stream::iter(["Отлично", ", ", "спасибо!"]);
+        while let Some(delta) = deltas.next().await {
+            append_delta(&history, &mut receiving, "yandexgpt-lite", delta);
+        }
+
+        assert!(receiving);
+        assert_eq!(
+            contents(&history.snapshot()),
+            [
+                (Role::User, "Как дела?"),
+                (Role::Assistant, "Отлично, спасибо!"),
+            ]
+        );
+        assert_eq!(
+            history.snapshot()[1].model.as_deref(),
+            Some("yandexgpt-lite")
+        );
+
+        // Следующий поток открывает новую запись, а не дописывает прежний ответ.
+        let mut receiving = false;
+        append_delta(&history, &mut receiving, "yandexgpt-lite", "Ещё");
+        assert_eq!(history.len(), 3);
+        assert_eq!(last_answer(&history.snapshot()), Some("Ещё"));
+    }
 }
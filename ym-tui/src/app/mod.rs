@@ -4,13 +4,19 @@
 //! - `core` — основная структура и жизненный цикл;
 //! - `ui` — отрисовка интерфейса;
 //! - `events` — обработка пользовательского ввода;
-//! - `messaging` — работа с сообщениями и GPT.
+//! - `history` — записи диалога и их потокобезопасное хранение;
+//! - `messaging` — работа с сообщениями и GPT;
+//! - `search` — поиск по истории диалога;
+//! - `stats` — статистика текущей сессии.
 
 mod core;
 mod events;
+mod history;
 mod messaging;
+mod search;
+mod stats;
 mod ui;
 
 // Реэкспорт для удобства использования
-pub use core::App;
+pub use core::{App, Focus};
 pub use messaging::clear_messages;
@@ -0,0 +1,171 @@
+//! Поиск по истории диалога: строка запроса, найденные записи и текущее совпадение.
+
+use super::history::ChatEntry;
+
+/// Состояние поиска по истории.
+#[derive(Debug, Default, Clone)]
+pub struct Search {
+    /// Строка поиска.
+    pub query: String,
+    /// Строка поиска набирается: клавиши попадают в запрос, а не в поле ввода.
+    pub editing: bool,
+    /// Индексы записей истории, содержащих запрос, по порядку.
+    pub matches: Vec<usize>,
+    /// Позиция текущего совпадения в `matches`.
+    pub current: Option<usize>,
+    /// Историю нужно прокрутить к текущему совпадению при следующей отрисовке.
+    jump_pending: bool,
+}
+
+impl Search {
+    /// Начать набор новой строки поиска.
+    pub fn start(&mut self) {
+        *self = Self {
+            editing: true,
+            ..Self::default()
+        };
+    }
+
+    /// Завершить поиск и снять подсветку.
+    pub fn cancel(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Поиск задан: есть строка, совпадения с которой подсвечиваются.
+    pub fn is_active(&self) -> bool {
+        self.editing || !self.query.is_empty()
+    }
+
+    /// Закончить набор и перейти к последнему совпадению — ближайшему к концу диалога.
+    pub fn confirm(&mut self, entries: &[ChatEntry]) {
+        self.editing = false;
+        self.matches = find_matches(entries, &self.query);
+        self.current = self.matches.len().checked_sub(1);
+        self.jump_pending = self.current.is_some();
+    }
+
+    /// Перейти к следующему (`forward`) или предыдущему совпадению, по кругу.
+    ///
+    /// Совпадения пересчитываются: история могла измениться после набора запроса.
+    pub fn step(&mut self, entries: &[ChatEntry], forward: bool) {
+        self.matches = find_matches(entries, &self.query);
+        let count = self.matches.len();
+        if count == 0 {
+            self.current = None;
+            return;
+        }
+
+        self.current = Some(match self.current {
+            Some(current) if forward => (current + 1) % count,
+            Some(current) => (current + count - 1) % count,
+            None if forward => 0,
+            None => count - 1,
+        });
+        self.jump_pending = true;
+    }
+
+    /// Запись, к которой нужно прокрутить историю, если переход ещё не выполнен.
+    pub fn take_jump(&mut self) -> Option<usize> {
+        if !std::mem::take(&mut self.jump_pending) {
+            return None;
+        }
+        let current = self.current?;
+        self.matches.get(current).copied()
+    }
+
+    /// Подпись для строки состояния: запрос и номер текущего совпадения.
+    pub fn label(&self) -> String {
+        if self.editing {
+            return format!("Поиск: {}█", self.query);
+        }
+        match self.current {
+            Some(current) => format!(
+                "Поиск: {} ({}/{}, n/N)",
+                self.query,
+                current + 1,
+                self.matches.len()
+            ),
+            None => format!("Поиск: {} (не найдено)", self.query),
+        }
+    }
+}
+
+/// Индексы записей, текст которых содержит `query` без учёта регистра.
+///
+/// Регистр сравнивается по правилам Unicode (`Ё` и `ё` совпадают). Пустой запрос ничего
+/// не находит.
+pub fn find_matches(entries: &[ChatEntry], query: &str) -> Vec<usize> {
+    if query.is_empty() {
+        return Vec::new();
+    }
+
+    let query = query.to_lowercase();
+    entries
+        .iter()
+        .enumerate()
+        .filter(|(_, entry)| contains_ignore_case(&entry.text, &query))
+        .map(|(index, _)| index)
+        .collect()
+}
+
+/// Содержит ли `text` строку `lowercase_query`, уже приведённую к нижнему регистру.
+pub fn contains_ignore_case(text: &str, lowercase_query: &str) -> bool {
+    !lowercase_query.is_empty() && text.to_lowercase().contains(lowercase_query)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries() -> Vec<ChatEntry> {
+        vec![
+            ChatEntry::info("Привет!"),
+            ChatEntry::user("Расскажи про ЁЖИКОВ"),
+            ChatEntry::assistant("Ёжики — ночные животные."),
+            ChatEntry::user("Спасибо"),
+            ChatEntry::assistant("Пожалуйста, ёжик одобряет."),
+        ]
+    }
+
+    #[test]
+    fn test_find_matches_ignores_case() {
+        assert_eq!(find_matches(&entries(), "ёжик"), [1, 2, 4]);
+        assert_eq!(find_matches(&entries(), "СПАСИБО"), [3]);
+        assert!(find_matches(&entries(), "кот").is_empty());
+        assert!(find_matches(&entries(), "").is_empty());
+    }
+
+    #[test]
+    fn test_search_steps_through_matches() {
+        let entries = entries();
+        let mut search = Search::default();
+        search.start();
+        search.query.push_str("ёжик");
+        search.confirm(&entries);
+
+        assert!(!search.editing);
+        assert_eq!(search.take_jump(), Some(4));
+        assert_eq!(search.take_jump(), None);
+
+        search.step(&entries, true);
+        assert_eq!(search.take_jump(), Some(1));
+        search.step(&entries, false);
+        search.step(&entries, false);
+        assert_eq!(search.take_jump(), Some(2));
+        assert_eq!(search.label(), "Поиск: ёжик (2/3, n/N)");
+
+        search.cancel();
+        assert!(!search.is_active());
+    }
+
+    #[test]
+    fn test_search_without_matches() {
+        let mut search = Search::default();
+        search.start();
+        search.query.push_str("кот");
+        search.confirm(&entries());
+
+        assert_eq!(search.take_jump(), None);
+        assert_eq!(search.label(), "Поиск: кот (не найдено)");
+    }
+}
@@ -0,0 +1,126 @@
+//! Статистика текущей сессии: число сообщений, задержка ответов, использованные модели.
+
+use std::time::Duration;
+
+/// Накопленная статистика сессии.
+#[derive(Debug, Default, Clone)]
+pub struct SessionStats {
+    /// Отправлено сообщений пользователя.
+    pub user_messages: usize,
+    /// Получено ответов модели.
+    pub assistant_messages: usize,
+    /// Суммарное время ожидания успешных ответов.
+    pub total_latency: Duration,
+    /// Суммарное количество токенов, если API его сообщает.
+    pub total_tokens: Option<u64>,
+    /// Модели, отвечавшие в сессии, в порядке первого использования.
+    pub models: Vec<String>,
+}
+
+impl SessionStats {
+    /// Учесть отправленное сообщение пользователя.
+    pub fn record_user_message(&mut self) {
+        self.user_messages += 1;
+    }
+
+    /// Учесть успешный ответ модели `model`, полученный за `latency`.
+    pub fn record_answer(&mut self, model: &str, latency: Duration) {
+        self.assistant_messages += 1;
+        self.total_latency += latency;
+        if !self.models.iter().any(|m| m == model) {
+            self.models.push(model.to_string());
+        }
+    }
+
+    /// Учесть расход токенов на запрос. Нулевой расход означает, что API его не прислал.
+    pub fn record_tokens(&mut self, input_tokens: u32, completion_tokens: u32) {
+        let tokens = u64::from(input_tokens) + u64::from(completion_tokens);
+        if tokens > 0 {
+            *self.total_tokens.get_or_insert(0) += tokens;
+        }
+    }
+
+    /// Средняя задержка ответа модели.
+    pub fn average_latency(&self) -> Option<Duration> {
+        let answers = u32::try_from(self.assistant_messages).ok()?;
+        (answers > 0).then(|| self.total_latency / answers)
+    }
+
+    /// Сводка для вывода пользователю.
+    pub fn summary(&self) -> String {
+        let latency = self
+            .average_latency()
+            .map_or("—".to_string(), |d| format!("{:.1} с", d.as_secs_f64()));
+        let tokens = self
+            .total_tokens
+            .map_or("нет данных".to_string(), |t| t.to_string());
+        let models = if self.models.is_empty() {
+            "—".to_string()
+        } else {
+            self.models.join(", ")
+        };
+
+        format!(
+            "Статистика сессии\n\
+             • Сообщений пользователя: {}\n\
+             • Ответов модели:         {}\n\
+             • Токенов всего:          {}\n\
+             • Средняя задержка:       {}\n\
+             • Модели:                 {}",
+            self.user_messages, self.assistant_messages, tokens, latency, models
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_average_latency() {
+        let mut stats = SessionStats::default();
+        assert_eq!(stats.average_latency(), None);
+
+        stats.record_answer("yandexgpt/latest", Duration::from_secs(1));
+        stats.record_answer("yandexgpt/latest", Duration::from_secs(3));
+        assert_eq!(stats.average_latency(), Some(Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn test_models_are_unique_in_first_use_order() {
+        let mut stats = SessionStats::default();
+        for model in ["b", "a", "b"] {
+            stats.record_answer(model, Duration::ZERO);
+        }
+        assert_eq!(stats.models, vec!["b", "a"]);
+    }
+
+    #[test]
+    fn test_tokens_are_summed_when_reported() {
+        let mut stats = SessionStats::default();
+        stats.record_tokens(0, 0);
+        assert_eq!(stats.total_tokens, None);
+
+        stats.record_tokens(30, 8);
+        stats.record_tokens(u32::MAX, 2);
+        assert_eq!(stats.total_tokens, Some(38 + u64::from(u32::MAX) + 2));
+        assert!(stats.summary().contains(&format!(
+            "Токенов всего:          {}",
+            40 + u64::from(u32::MAX)
+        )));
+    }
+
+    #[test]
+    fn test_summary_lists_counters() {
+        let mut stats = SessionStats::default();
+        stats.record_user_message();
+        stats.record_answer("yandexgpt-pro", Duration::from_millis(1500));
+
+        let summary = stats.summary();
+        assert!(summary.contains("Сообщений пользователя: 1"));
+        assert!(summary.contains("Ответов модели:         1"));
+        assert!(summary.contains("Средняя задержка:       1.5 с"));
+        assert!(summary.contains("Токенов всего:          нет данных"));
+        assert!(summary.contains("yandexgpt-pro"));
+    }
+}
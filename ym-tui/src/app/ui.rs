@@ -4,19 +4,51 @@
 //! - <https://docs.rs/ratatui/latest/ratatui/widgets/index.html>
 //! - <https://github.com/ratatui/ratatui/tree/master/examples>
 
+use std::ops::Range;
+
 use ratatui::{
     Frame,
-    style::Stylize,
-    text::Line,
+    style::{Color, Modifier, Style, Stylize},
+    text::{Line, Span},
     widgets::{Block, Paragraph},
 };
 
-use super::core::App;
+use super::core::{App, ConnectionStatus, Focus};
+use super::history::{ChatEntry, Role};
+use super::search::contains_ignore_case;
+use crate::settings::{InputLoad, Prefixes};
+use crate::utils::markdown::render_markdown;
+use crate::utils::wrap::{char_width, wrap_lines};
+use ym_yagpt::models::estimate_tokens;
+
+/// Кадры индикатора ожидания ответа модели: один кадр на тик главного цикла.
+const SPINNER_FRAMES: &[char] = &['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+
+/// Цвет рамки области, находящейся в фокусе.
+const FOCUSED_BORDER: Color = Color::Cyan;
 
 /// Отрисовка интерфейса приложения.
 pub fn draw_interface(app: &mut App, frame: &mut Frame) {
     use ratatui::layout::{Constraint, Direction, Layout};
 
+    if app.prefs.compact {
+        // Компактный режим: без заголовка и рамок, максимум места под историю.
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Min(1),
+                Constraint::Length(3),
+                Constraint::Length(1),
+            ])
+            .split(frame.area());
+
+        draw_messages(app, frame, chunks[0]);
+        draw_input(app, frame, chunks[1]);
+        draw_status_bar(app, frame, chunks[2]);
+        draw_send_confirmation(app, frame);
+        return;
+    }
+
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .margin(0)
@@ -35,6 +67,57 @@ pub fn draw_interface(app: &mut App, frame: &mut Frame) {
     draw_messages(app, frame, chunks[1]);
     draw_input(app, frame, chunks[2]);
     draw_status_bar(app, frame, chunks[3]);
+    draw_send_confirmation(app, frame);
+}
+
+/// Окно подтверждения отправки поверх интерфейса, если оно открыто.
+fn draw_send_confirmation(app: &App, frame: &mut Frame) {
+    use ratatui::layout::{Constraint, Flex, Layout};
+    use ratatui::widgets::{Borders, Clear};
+
+    let Some(text) = &app.send_confirmation else {
+        return;
+    };
+
+    let [row] = Layout::vertical([Constraint::Length(5)])
+        .flex(Flex::Center)
+        .areas(frame.area());
+    let [popup] = Layout::horizontal([Constraint::Max(60)])
+        .flex(Flex::Center)
+        .areas(row);
+
+    let block = Block::default()
+        .title(" Подтверждение ")
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Yellow));
+    frame.render_widget(Clear, popup);
+    frame.render_widget(
+        Paragraph::new(text.as_str())
+            .block(block)
+            .wrap(ratatui::widgets::Wrap { trim: true }),
+        popup,
+    );
+}
+
+/// Блок-обёртка для области интерфейса. В компактном режиме — без рамки и заголовка.
+fn pane_block<'a>(app: &App, title: &'a str, focused: bool) -> Block<'a> {
+    if app.prefs.compact {
+        return Block::default();
+    }
+
+    Block::default()
+        .title(title)
+        .borders(ratatui::widgets::Borders::ALL)
+        .border_style(border_style(focused))
+}
+
+/// Стиль рамки блока в зависимости от того, находится ли он в фокусе.
+fn border_style(focused: bool) -> Style {
+    if focused {
+        Style::default().fg(FOCUSED_BORDER)
+    } else {
+        Style::default()
+    }
 }
 
 /// Отрисовка заголовка приложения.
@@ -47,70 +130,701 @@ fn draw_title(frame: &mut Frame, area: ratatui::layout::Rect) {
     frame.render_widget(Paragraph::new(title).centered(), area);
 }
 
+/// Стиль строк записи истории по роли автора.
+fn role_style(role: Role) -> Style {
+    match role {
+        Role::User => Style::default().fg(Color::Cyan),
+        Role::Assistant => Style::default().fg(Color::Green),
+        Role::System => Style::default().fg(Color::DarkGray),
+        Role::Info => Style::default(),
+        Role::Error => Style::default().fg(Color::Red),
+    }
+}
+
+/// Строки записи истории для отрисовки: каждая строка получает стиль роли записи.
+///
+/// Ответы модели разбираются как markdown, при `raw` выводятся исходным текстом; подпись
+/// и время выводятся перед первой строкой.
+fn entry_lines(
+    entry: &ChatEntry,
+    prefixes: &Prefixes,
+    show_timestamps: bool,
+    raw: bool,
+) -> Vec<Line<'static>> {
+    let head = entry.head(prefixes, show_timestamps);
+    let style = role_style(entry.role);
+    if entry.role != Role::Assistant || raw {
+        return (head + &entry.text)
+            .lines()
+            .map(|line| Line::styled(line.to_string(), style))
+            .collect();
+    }
+
+    let mut lines = render_markdown(&entry.text).lines;
+    if !head.is_empty() {
+        match lines.first_mut() {
+            Some(first) => first.spans.insert(0, Span::raw(head)),
+            None => lines.push(Line::from(head)),
+        }
+    }
+    lines
+        .into_iter()
+        .map(|line| line.patch_style(style))
+        .collect()
+}
+
+/// Разделитель дня перед записью `entry`, если её дата отличается от даты записи `previous`.
+///
+/// Разделитель только рисуется: в историю, контекст запроса и экспорт он не попадает.
+fn day_separator(previous: Option<&ChatEntry>, entry: &ChatEntry) -> Option<Line<'static>> {
+    let date = entry.timestamp.date_naive();
+    if previous?.timestamp.date_naive() == date {
+        return None;
+    }
+    Some(Line::styled(
+        format!("— {} —", date.format("%Y-%m-%d")),
+        role_style(Role::System),
+    ))
+}
+
+/// Подсветить строки, содержащие `query` без учёта регистра (пустой запрос — без подсветки).
+fn highlight_matches(lines: Vec<Line<'static>>, query: &str) -> Vec<Line<'static>> {
+    let query = query.to_lowercase();
+    lines
+        .into_iter()
+        .map(|line| {
+            if contains_ignore_case(&line.to_string(), &query) {
+                line.patch_style(Modifier::REVERSED)
+            } else {
+                line
+            }
+        })
+        .collect()
+}
+
 /// Отрисовка блока с историей сообщений.
 fn draw_messages(app: &mut App, frame: &mut Frame, area: ratatui::layout::Rect) {
-    let messages_text: Vec<Line> = app
-        .messages
-        .iter()
-        .map(|msg| Line::from(msg.as_str()))
-        .collect();
+    let messages_block = pane_block(app, " История диалога ", app.focus == Focus::History);
+    let text_area = limit_width(messages_block.inner(area), app.prefs.messages_max_width);
+    app.history_height = text_area.height;
 
-    let messages_block = Block::default()
-        .title(" История диалога ")
-        .borders(ratatui::widgets::Borders::ALL);
+    // Строки переносятся заранее по ширине символов на экране (см. `utils::wrap`) и берутся
+    // из кэша, пока запись, ширина и настройки отображения не изменились.
+    // Переход к совпадению поиска ставит первую строку найденной записи в начало области.
+    // При смене даты между соседними записями перед записью выводится разделитель дня.
+    let prefixes = app.prefs.prefixes();
+    let width = usize::from(text_area.width);
+    let jump = app.search.take_jump();
+    let entries = app.messages.snapshot();
+    app.rendered.prepare(
+        RenderSettings::new(
+            width,
+            &prefixes,
+            app.prefs.show_timestamps,
+            app.raw_markdown,
+        ),
+        entries.len(),
+    );
+    let mut messages_text = Vec::new();
+    for (index, entry) in entries.iter().enumerate() {
+        let previous = index.checked_sub(1).map(|previous| &entries[previous]);
+        messages_text.extend(day_separator(previous, entry));
+        if jump == Some(index) {
+            app.scroll_offset = u16::try_from(messages_text.len()).unwrap_or(u16::MAX);
+            app.follow_tail = false;
+        }
+        let lines = app.rendered.entry(index, entry, || {
+            let lines = entry_lines(
+                entry,
+                &prefixes,
+                app.prefs.show_timestamps,
+                app.raw_markdown,
+            );
+            wrap_lines(lines, width)
+        });
+        messages_text.extend(lines.iter().cloned());
+    }
 
-    let messages_widget = Paragraph::new(messages_text.clone())
-        .block(messages_block)
-        .wrap(ratatui::widgets::Wrap { trim: true })
-        .scroll((app.scroll_offset, 0));
+    // Смещение считается в экранных строках: пока история следует за новыми сообщениями,
+    // внизу видна последняя строка, иначе смещение не выходит за неё.
+    app.history_lines = messages_text.len();
+    let max_offset = max_scroll_offset(app.history_lines, app.history_height);
+    app.scroll_offset = if app.follow_tail {
+        max_offset
+    } else {
+        app.scroll_offset.min(max_offset)
+    };
 
-    frame.render_widget(messages_widget, area);
+    let messages_text = highlight_matches(messages_text, &app.search.query);
+    let messages_widget = Paragraph::new(messages_text).scroll((app.scroll_offset, 0));
+
+    frame.render_widget(messages_block, area);
+    frame.render_widget(messages_widget, text_area);
 }
 
-/// Отрисовка поля ввода сообщения.
-fn draw_input(app: &mut App, frame: &mut Frame, area: ratatui::layout::Rect) {
-    let input_block = Block::default()
-        .title(" Ввод сообщения ")
-        .borders(ratatui::widgets::Borders::ALL);
-
-    // Подсветка курсора.
-    let input_display = {
-        let mut result = String::new();
-        let chars: Vec<char> = app.input_buffer.chars().collect();
-
-        for (i, ch) in chars.iter().enumerate() {
-            if i == app.cursor_pos {
-                result.push('█');
-            }
-            result.push(*ch);
+/// Настройки, от которых зависят строки записей истории на экране.
+#[derive(Debug, PartialEq)]
+struct RenderSettings {
+    width: usize,
+    prefixes: [String; 5],
+    show_timestamps: bool,
+    raw_markdown: bool,
+}
+
+impl RenderSettings {
+    fn new(width: usize, prefixes: &Prefixes, show_timestamps: bool, raw_markdown: bool) -> Self {
+        let Prefixes {
+            user,
+            assistant,
+            system,
+            info,
+            error,
+        } = *prefixes;
+        Self {
+            width,
+            prefixes: [user, assistant, system, info, error].map(String::from),
+            show_timestamps,
+            raw_markdown,
         }
+    }
+}
+
+/// Кэш отрисованных и перенесённых строк записей истории.
+///
+/// Разбор markdown и перенос строк не повторяются на каждом кадре: строки записи
+/// пересчитываются, только когда меняется сама запись, а смена ширины или настроек
+/// отображения сбрасывает кэш целиком.
+#[derive(Debug, Default)]
+pub struct RenderedLines {
+    settings: Option<RenderSettings>,
+    entries: Vec<(ChatEntry, Vec<Line<'static>>)>,
+}
 
-        if app.cursor_pos == chars.len() {
-            result.push('█');
+impl RenderedLines {
+    /// Подготовить кэш к кадру с настройками `settings` и `len` записями.
+    fn prepare(&mut self, settings: RenderSettings, len: usize) {
+        if self.settings.as_ref() != Some(&settings) {
+            self.entries.clear();
+            self.settings = Some(settings);
         }
+        self.entries.truncate(len);
+    }
 
-        result
-    };
+    /// Строки записи `entry` с номером `index`: из кэша, если запись не изменилась,
+    /// иначе — полученные от `render`. Записи запрашиваются по порядку.
+    fn entry(
+        &mut self,
+        index: usize,
+        entry: &ChatEntry,
+        render: impl FnOnce() -> Vec<Line<'static>>,
+    ) -> &[Line<'static>] {
+        let cached = self
+            .entries
+            .get(index)
+            .is_some_and(|(cached, _)| cached == entry);
+        if !cached {
+            let rendered = (entry.clone(), render());
+            match self.entries.get_mut(index) {
+                Some(slot) => *slot = rendered,
+                None => self.entries.push(rendered),
+            }
+        }
+        &self.entries[index].1
+    }
+}
+
+/// Наибольшее смещение истории из `lines` экранных строк в области высотой `height`:
+/// при нём последняя строка оказывается внизу области.
+pub(super) fn max_scroll_offset(lines: usize, height: u16) -> u16 {
+    let offset = lines.saturating_sub(usize::from(height));
+    u16::try_from(offset).unwrap_or(u16::MAX)
+}
+
+/// Колонка не шире `max_width`, отцентрированная внутри `area` (`0` — вся область).
+fn limit_width(area: ratatui::layout::Rect, max_width: u16) -> ratatui::layout::Rect {
+    use ratatui::layout::{Constraint, Flex, Layout};
+
+    if max_width == 0 || area.width <= max_width {
+        return area;
+    }
+
+    let [column] = Layout::horizontal([Constraint::Length(max_width)])
+        .flex(Flex::Center)
+        .areas(area);
+    column
+}
+
+/// Отрисовка поля ввода сообщения.
+///
+/// Текст переносится по ширине на экране (см. [`input_rows`]) и прокручивается так, чтобы строка
+/// с курсором оставалась видна.
+fn draw_input(app: &mut App, frame: &mut Frame, area: ratatui::layout::Rect) {
+    let input_block = pane_block(app, " Ввод сообщения ", app.focus == Focus::Input);
+    let text_area = input_block.inner(area);
+    app.input_width = text_area.width;
+
+    let width = usize::from(text_area.width);
+    let chars: Vec<char> = app.input_buffer.chars().collect();
+    let rows = input_rows(&app.input_buffer, width);
+    let (cursor_row, cursor_col) = input_cursor(&rows, app.cursor_pos);
+
+    let lines: Vec<Line> = rows
+        .iter()
+        .enumerate()
+        .map(|(index, row)| {
+            let text: String = chars[row.clone()].iter().collect();
+            if index == cursor_row {
+                cursor_line(&text, cursor_col)
+            } else {
+                Line::from(text)
+            }
+        })
+        .collect();
+
+    let visible_rows = usize::from(text_area.height.max(1));
+    let scroll = cursor_row.saturating_sub(visible_rows - 1);
 
     frame.render_widget(
-        Paragraph::new(input_display)
+        Paragraph::new(lines)
             .block(input_block)
-            .wrap(ratatui::widgets::Wrap { trim: true })
+            .scroll((u16::try_from(scroll).unwrap_or(u16::MAX), 0))
             .fg(ratatui::style::Color::Yellow),
         area,
     );
 }
 
+/// Строка поля ввода с подсвеченным курсором в колонке `col` (в символах).
+///
+/// Символ под курсором выводится инверсным, курсор в конце строки — блоком `█`.
+fn cursor_line(text: &str, col: usize) -> Line<'static> {
+    let before: String = text.chars().take(col).collect();
+    let mut rest = text.chars().skip(col);
+    let cursor = match rest.next() {
+        Some(ch) => Span::styled(ch.to_string(), Modifier::REVERSED),
+        None => Span::raw("█"),
+    };
+    let after: String = rest.collect();
+    Line::from(vec![Span::raw(before), cursor, Span::raw(after)])
+}
+
+/// Видимые строки поля ввода шириной `width` колонок: диапазоны позиций буфера (в символах).
+///
+/// Строки буфера переносятся по символам, без учёта слов, с учётом ширины символов на экране
+/// (см. [`char_width`]); `width == 0` отключает перенос. После строки, заполнившей ширину
+/// целиком, добавляется пустая строка: там встаёт курсор в конце такой строки.
+pub(super) fn input_rows(buffer: &str, width: usize) -> Vec<Range<usize>> {
+    let mut rows = Vec::new();
+    let mut start = 0;
+
+    for line in buffer.split('\n') {
+        let mut row_start = start;
+        let mut row_width = 0;
+        for (offset, ch) in line.chars().enumerate() {
+            let ch_width = char_width(ch);
+            if width > 0 && row_width + ch_width > width && row_width > 0 {
+                rows.push(row_start..start + offset);
+                row_start = start + offset;
+                row_width = 0;
+            }
+            row_width += ch_width;
+        }
+        let end = start + line.chars().count();
+        rows.push(row_start..end);
+        if width > 0 && row_width >= width {
+            rows.push(end..end);
+        }
+        start = end + 1;
+    }
+
+    rows
+}
+
+/// Строка и колонка курсора среди видимых строк `rows` поля ввода.
+///
+/// Курсор на границе переноса стоит в начале следующей строки.
+pub(super) fn input_cursor(rows: &[Range<usize>], cursor: usize) -> (usize, usize) {
+    let row = rows
+        .iter()
+        .rposition(|row| row.start <= cursor)
+        .unwrap_or(0);
+    let col = cursor.min(rows[row].end) - rows[row].start;
+    (row, col)
+}
+
+/// Цвет счётчика токенов в зависимости от заполненности поля ввода.
+fn input_load_color(load: InputLoad) -> Color {
+    match load {
+        InputLoad::Normal => Color::Green,
+        InputLoad::Warning => Color::Yellow,
+        InputLoad::Limit => Color::Red,
+    }
+}
+
+/// Поддерживает ли терминал 24-битный цвет (по переменной окружения `COLORTERM`).
+///
+/// Окружение читается один раз при старте, результат хранится в [`App::truecolor`].
+pub(super) fn supports_truecolor() -> bool {
+    is_truecolor(std::env::var("COLORTERM").ok().as_deref())
+}
+
+/// Означает ли значение `COLORTERM` поддержку 24-битного цвета.
+fn is_truecolor(colorterm: Option<&str>) -> bool {
+    matches!(colorterm, Some("truecolor" | "24bit"))
+}
+
+/// Цвет температуры: от синего при `0.0` («точно») к красному при `1.0` («креативно»).
+///
+/// Без truecolor шкала сводится к базовым цветам: синий, пурпурный, красный.
+fn temperature_color(temperature: f32, truecolor: bool) -> Color {
+    let t = temperature.clamp(0.0, 1.0);
+    if truecolor {
+        let red = (t * 255.0).round() as u8;
+        return Color::Rgb(red, 64, 255 - red);
+    }
+
+    if t < 1.0 / 3.0 {
+        Color::Blue
+    } else if t < 2.0 / 3.0 {
+        Color::Magenta
+    } else {
+        Color::Red
+    }
+}
+
+/// Цвет индикатора соединения.
+fn connection_color(status: ConnectionStatus) -> Color {
+    match status {
+        ConnectionStatus::Connected => Color::Green,
+        ConnectionStatus::Checking => Color::Yellow,
+        ConnectionStatus::Offline | ConnectionStatus::InvalidKey | ConnectionStatus::Failed => {
+            Color::Red
+        }
+    }
+}
+
+/// Кадр индикатора ожидания для номера тика.
+fn spinner_frame(tick: u64) -> char {
+    SPINNER_FRAMES[(tick % SPINNER_FRAMES.len() as u64) as usize]
+}
+
 /// Отрисовка статус-бара.
 fn draw_status_bar(app: &mut App, frame: &mut Frame, area: ratatui::layout::Rect) {
-    let status = format!(
-        " Сообщений: {} | Длина ввода: {} | Очистить историю: Ctrl+R | Выйти: Ctrl+C, Esc",
+    let mut model = format!(" Модель: {} | Темп.: ", app.model());
+    let temperature = app.gpt_client.gpt_options.temperature;
+    let counts = format!(
+        " | Сообщений: {} | Длина ввода: {} ",
         app.messages.len(),
-        app.input_buffer.len()
+        app.input_buffer.chars().count(),
+    );
+    let tail = format!(
+        " | Отправить: {} | Фокус: Tab | Сменить модель: Alt+M | Очистить историю: Ctrl+R | Выйти: Ctrl+C, Esc",
+        app.prefs.send_on.label()
     );
 
-    frame.render_widget(
-        Paragraph::new(status).block(Block::default().borders(ratatui::widgets::Borders::TOP)),
-        area,
+    if app.is_loading() {
+        let state = if app.receiving {
+            "Получение ответа… (Esc — прервать)"
+        } else {
+            "Ожидание ответа"
+        };
+        model = format!(" {} {} |{}", spinner_frame(app.tick), state, model);
+    }
+    if !app.pending_inputs.is_empty() {
+        model = format!(" В очереди: {} |{}", app.pending_inputs.len(), model);
+    }
+    if let Some(retry) = &app.network_retry {
+        model = format!(
+            " {}, повтор через {} с |{}",
+            retry.reason,
+            retry.seconds_left(),
+            model
+        );
+    }
+
+    // Точный подсчёт через API появляется после паузы в наборе, до него — оценка.
+    let (tokens, approx) = match app.token_counter.tokens(&app.input_buffer) {
+        Some(tokens) => (tokens as usize, ""),
+        None => (estimate_tokens(&app.input_buffer), "~"),
+    };
+    let counter = Span::styled(
+        format!("({}{} ток.)", approx, tokens),
+        Style::default().fg(input_load_color(app.prefs.input_load(tokens))),
+    );
+    let temperature = Span::styled(
+        format!("{:.1}", temperature),
+        Style::default().fg(temperature_color(temperature, app.truecolor)),
+    );
+    let connection = Span::styled(
+        format!(" ● {} |", app.connection.label()),
+        Style::default().fg(connection_color(app.connection)),
     );
+    let search = app.search.is_active().then(|| {
+        Span::styled(
+            format!(" {} |", app.search.label()),
+            Style::default().fg(Color::Yellow),
+        )
+    });
+    let status = Line::from_iter(search.into_iter().chain([
+        connection,
+        Span::raw(model),
+        temperature,
+        Span::raw(counts),
+        counter,
+        Span::raw(tail),
+    ]));
+
+    let status_block = if app.prefs.compact {
+        Block::default()
+    } else {
+        Block::default().borders(ratatui::widgets::Borders::TOP)
+    };
+
+    frame.render_widget(Paragraph::new(status).block(status_block), area);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Local, TimeZone};
+    use ratatui::layout::Rect;
+    use ratatui::style::Modifier;
+
+    #[test]
+    fn test_rendered_lines_are_reused() {
+        let prefixes = Prefixes::default();
+        let settings = |width| RenderSettings::new(width, &prefixes, false, false);
+        let renders = std::cell::Cell::new(0);
+        let render = || {
+            renders.set(renders.get() + 1);
+            vec![Line::from("строка")]
+        };
+        let mut cache = RenderedLines::default();
+        let first = ChatEntry::user("Привет");
+        let second = ChatEntry::assistant("Здравствуйте");
+
+        cache.prepare(settings(80), 2);
+        cache.entry(0, &first, render);
+        cache.entry(1, &second, render);
+        assert_eq!(renders.get(), 2);
+
+        // Тот же кадр ещё раз — ничего не пересчитывается.
+        cache.prepare(settings(80), 2);
+        assert_eq!(cache.entry(0, &first, render), [Line::from("строка")]);
+        cache.entry(1, &second, render);
+        assert_eq!(renders.get(), 2);
+
+        // Изменилась только вторая запись (ответ дописывается по частям).
+        cache.prepare(settings(80), 2);
+        cache.entry(0, &first, render);
+        cache.entry(1, &ChatEntry::assistant("Здравствуйте!"), render);
+        assert_eq!(renders.get(), 3);
+
+        // Другая ширина — пересчитываются все записи.
+        cache.prepare(settings(60), 2);
+        cache.entry(0, &first, render);
+        assert_eq!(renders.get(), 4);
+    }
+
+    #[test]
+    fn test_limit_width_centers_column() {
+        let area = Rect::new(0, 1, 200, 30);
+        assert_eq!(limit_width(area, 100), Rect::new(50, 1, 100, 30));
+    }
+
+    #[test]
+    fn test_limit_width_keeps_narrow_or_unlimited_area() {
+        let area = Rect::new(1, 1, 80, 20);
+        assert_eq!(limit_width(area, 100), area);
+        assert_eq!(limit_width(area, 0), area);
+    }
+
+    #[test]
+    fn test_day_separator_marks_date_change() {
+        use chrono::{Local, TimeZone};
+
+        let at = |day, hour| {
+            let mut entry = ChatEntry::user("Привет");
+            entry.timestamp = Local.with_ymd_and_hms(2024, 6, day, hour, 0, 0).unwrap();
+            entry
+        };
+        let (morning, evening, next_day) = (at(1, 9), at(1, 21), at(2, 8));
+
+        assert_eq!(day_separator(None, &morning), None);
+        assert_eq!(day_separator(Some(&morning), &evening), None);
+        let separator = day_separator(Some(&evening), &next_day).unwrap();
+        assert_eq!(separator.to_string(), "— 2024-06-02 —");
+        assert_eq!(separator.style, role_style(Role::System));
+    }
+
+    #[test]
+    fn test_history_lines_are_colored_by_role() {
+        use ratatui::{Terminal, backend::TestBackend, widgets::Wrap};
+
+        let entries = [
+            ChatEntry::user("Привет"),
+            ChatEntry::assistant("Здравствуйте!\nЧем помочь?"),
+            ChatEntry::system("Ответ обрезан"),
+            ChatEntry::error("таймаут"),
+        ];
+        let lines: Vec<Line> = entries
+            .iter()
+            .flat_map(|entry| entry_lines(entry, &Prefixes::default(), false, false))
+            .collect();
+        let mut terminal = Terminal::new(TestBackend::new(20, 6)).unwrap();
+        terminal
+            .draw(|frame| {
+                let paragraph = Paragraph::new(lines).wrap(Wrap { trim: false });
+                frame.render_widget(paragraph, frame.area());
+            })
+            .unwrap();
+
+        let buffer = terminal.backend().buffer();
+        let row = |y: u16| {
+            (0..buffer.area.width)
+                .map(|x| buffer[(x, y)].symbol())
+                .collect::<String>()
+        };
+        assert_eq!(row(0).trim_end(), "Вы: Привет");
+        assert_eq!(row(2).trim_end(), "Чем помочь?");
+        // Длинная строка переносится, перенос сохраняет стиль роли.
+        assert_eq!(row(3).trim_end(), "Система: Ответ");
+        assert_eq!(row(4).trim_end(), "обрезан");
+
+        assert_eq!(buffer[(0, 0)].fg, Color::Cyan);
+        assert_eq!(buffer[(0, 1)].fg, Color::Green);
+        assert_eq!(buffer[(0, 2)].fg, Color::Green);
+        assert_eq!(buffer[(0, 3)].fg, Color::DarkGray);
+        assert_eq!(buffer[(0, 4)].fg, Color::DarkGray);
+        assert_eq!(buffer[(0, 5)].fg, Color::Red);
+    }
+
+    #[test]
+    fn test_search_highlights_matching_lines() {
+        let lines = vec![Line::from("Про ЁЖИКОВ"), Line::from("про котов")];
+
+        let lines = highlight_matches(lines, "ёжик");
+        assert!(lines[0].style.add_modifier.contains(Modifier::REVERSED));
+        assert!(!lines[1].style.add_modifier.contains(Modifier::REVERSED));
+
+        let lines = highlight_matches(lines.into_iter().skip(1).collect(), "");
+        assert_eq!(lines[0].style, Style::default());
+    }
+
+    #[test]
+    fn test_assistant_answer_is_rendered_as_markdown() {
+        let answer = "Это **важно**\n```\n  let x = 1;\n```";
+        let prefixes = Prefixes {
+            assistant: "GPT",
+            ..Prefixes::default()
+        };
+        let lines = entry_lines(&ChatEntry::assistant(answer), &prefixes, false, false);
+
+        let text: Vec<String> = lines.iter().map(|line| line.to_string()).collect();
+        assert_eq!(text, ["GPT: Это важно", "  let x = 1;"]);
+        assert_eq!(lines[0].style.fg, Some(Color::Green));
+        assert_eq!(lines[0].spans[0].content, "GPT: ");
+        let bold = lines[0].spans[2].style;
+        assert!(bold.add_modifier.contains(Modifier::BOLD));
+    }
+
+    #[test]
+    fn test_assistant_head_is_built_separately_from_body() {
+        let prefixes = Prefixes {
+            assistant: "GPT",
+            ..Prefixes::default()
+        };
+        let mut entry = ChatEntry::assistant("**GPT: **");
+        entry.timestamp = Local.with_ymd_and_hms(2025, 3, 1, 9, 5, 0).unwrap();
+
+        let lines = entry_lines(&entry, &prefixes, true, false);
+        assert_eq!(lines[0].spans[0].content, "[09:05] GPT: ");
+        assert_eq!(lines[0].to_string(), "[09:05] GPT: GPT: ");
+
+        // Пустой ответ всё равно получает подпись.
+        let lines = entry_lines(&ChatEntry::assistant(""), &prefixes, false, false);
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].to_string(), "GPT: ");
+    }
+
+    #[test]
+    fn test_raw_answer_keeps_markup() {
+        let answer = "Это **важно**\n```\n  let x = 1;\n```";
+        let lines = entry_lines(
+            &ChatEntry::assistant(answer),
+            &Prefixes::default(),
+            false,
+            true,
+        );
+
+        let text: Vec<String> = lines.iter().map(|line| line.to_string()).collect();
+        assert_eq!(text, ["Это **важно**", "```", "  let x = 1;", "```"]);
+        assert!(lines.iter().all(|line| line.style.fg == Some(Color::Green)));
+    }
+
+    #[test]
+    fn test_input_rows_wrap_by_chars() {
+        let empty = input_rows("", 10);
+        assert_eq!((empty.len(), empty[0].clone()), (1, 0..0));
+        assert_eq!(input_rows("привет\nмир", 0), [0..6, 7..10]);
+        assert_eq!(input_rows("привет\nмир", 4), [0..4, 4..6, 7..10]);
+        // За строкой ровно во всю ширину следует пустая строка для курсора.
+        assert_eq!(input_rows("мир\n", 3), [0..3, 3..3, 4..4]);
+    }
+
+    #[test]
+    fn test_input_rows_count_display_width() {
+        // Иероглиф занимает две колонки: в строку шириной 5 помещается «ab你».
+        assert_eq!(input_rows("ab你好cd", 5), [0..3, 3..6]);
+        // Широкий символ не делится: колонка в конце строки остаётся пустой.
+        assert_eq!(input_rows("abcd👍", 5), [0..4, 4..5]);
+        assert_eq!(input_rows("你好", 4), [0..2, 2..2]);
+    }
+
+    #[test]
+    fn test_input_cursor_row_and_column() {
+        let rows = input_rows("привет\nмир", 4);
+        assert_eq!(input_cursor(&rows, 0), (0, 0));
+        // Граница переноса — начало следующей видимой строки.
+        assert_eq!(input_cursor(&rows, 4), (1, 0));
+        // Конец строки буфера перед переводом строки.
+        assert_eq!(input_cursor(&rows, 6), (1, 2));
+        assert_eq!(input_cursor(&rows, 7), (2, 0));
+        assert_eq!(input_cursor(&rows, 10), (2, 3));
+    }
+
+    #[test]
+    fn test_spinner_frame_cycles_with_ticks() {
+        assert_eq!(spinner_frame(0), SPINNER_FRAMES[0]);
+        assert_eq!(spinner_frame(1), SPINNER_FRAMES[1]);
+        assert_eq!(
+            spinner_frame(SPINNER_FRAMES.len() as u64),
+            SPINNER_FRAMES[0]
+        );
+        assert_eq!(
+            spinner_frame(u64::MAX),
+            SPINNER_FRAMES[15 % SPINNER_FRAMES.len()]
+        );
+    }
+
+    #[test]
+    fn test_temperature_color_truecolor_gradient() {
+        assert_eq!(temperature_color(0.0, true), Color::Rgb(0, 64, 255));
+        assert_eq!(temperature_color(1.0, true), Color::Rgb(255, 64, 0));
+        assert_eq!(temperature_color(0.5, true), Color::Rgb(128, 64, 127));
+    }
+
+    #[test]
+    fn test_truecolor_detection() {
+        assert!(is_truecolor(Some("truecolor")));
+        assert!(is_truecolor(Some("24bit")));
+        assert!(!is_truecolor(Some("256color")));
+        assert!(!is_truecolor(None));
+    }
+
+    #[test]
+    fn test_temperature_color_basic_fallback() {
+        assert_eq!(temperature_color(0.0, false), Color::Blue);
+        assert_eq!(temperature_color(0.5, false), Color::Magenta);
+        assert_eq!(temperature_color(0.9, false), Color::Red);
+    }
 }
@@ -4,10 +4,16 @@
 //! проверки "здоровья", а также настройку минимально требуемых данных (например, авторизация),
 //! до вызова терминала.
 
-use crate::settings::access_file_path;
+use crate::settings::{
+    ModelOverrides, Preferences, access_file_path, active_profile, prefs_file_path,
+    resolve_config_path, save_named, set_access_file_path, set_active_profile,
+    validate_profile_name,
+};
 use crate::utils::tools::{ask_user, user_input_with_question};
-use clap::Parser;
+use clap::{CommandFactory, Parser};
+use std::io::{self, Read};
 use std::process::exit;
+use ym_yagpt::client::GPTClient;
 use ym_yagpt::models::AccessData;
 
 /// Структура аргументов командной строки при запуске приложения.
@@ -19,16 +25,113 @@ pub struct Cli {
     /// Установка данных для работы с нейросетью.
     #[arg(short, long)]
     pub init: bool,
+
+    /// Компактный интерфейс без рамок и заголовка (выбор запоминается).
+    #[arg(long)]
+    pub compact: bool,
+
+    /// Путь к файлу данных доступа вместо стандартного (допускаются `~` и относительные пути).
+    #[arg(short, long, value_name = "PATH")]
+    pub config: Option<String>,
+
+    /// Профиль доступа: файл `profiles/<NAME>.json` в каталоге конфигурации.
+    /// Без ключа действует `default_profile` из настроек.
+    #[arg(long, value_name = "NAME", conflicts_with = "config")]
+    pub profile: Option<String>,
+
+    /// Отправить один запрос, вывести ответ и завершить работу без интерфейса.
+    /// `-` — прочитать запрос из стандартного ввода.
+    #[arg(short, long, value_name = "TEXT")]
+    pub prompt: Option<String>,
+
+    /// Модель на время сессии (например, `yandexgpt-lite/latest`).
+    #[arg(short, long, value_name = "NAME")]
+    pub model: Option<String>,
+
+    /// Температура генерации на время сессии, от 0 до 1.
+    #[arg(
+        short,
+        long,
+        value_name = "0..=1",
+        value_parser = parse_temperature,
+        allow_negative_numbers = true
+    )]
+    pub temperature: Option<f32>,
+
+    /// Лимит токенов ответа на время сессии.
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(i64).range(1..))]
+    pub max_tokens: Option<i64>,
+}
+
+impl Cli {
+    /// Параметры модели из командной строки. Важнее параметров из файла настроек.
+    pub fn model_overrides(&self) -> ModelOverrides {
+        ModelOverrides {
+            model: self.model.clone(),
+            temperature: self.temperature,
+            max_tokens: self.max_tokens,
+        }
+    }
+}
+
+/// Разбор температуры: число от 0 до 1 включительно.
+fn parse_temperature(raw: &str) -> Result<f32, String> {
+    let temperature: f32 = raw
+        .parse()
+        .map_err(|_| format!("ожидается число, получено «{}»", raw))?;
+    if !(0.0..=1.0).contains(&temperature) {
+        return Err(format!(
+            "допустимы значения от 0 до 1, получено {}",
+            temperature
+        ));
+    }
+    Ok(temperature)
+}
+
+/// Завершить работу ошибкой проверки аргументов в формате clap.
+pub fn invalid_arguments(message: &str) -> ! {
+    Cli::command()
+        .error(clap::error::ErrorKind::ValueValidation, message)
+        .exit()
 }
 
 /// Обработка аргументов командной строки.
 ///
-/// Подробнее в документации к clap.
-pub fn cli_action() {
+/// Возвращает разобранные аргументы для настройки интерфейса. Подробнее в документации к clap.
+/// С ключом `--prompt` выполняет разовый запрос и завершает процесс, не возвращаясь.
+pub async fn cli_action() -> Cli {
     let cli = Cli::parse();
 
+    if let Some(raw) = &cli.config {
+        // При инициализации файла ещё может не быть, достаточно существующего каталога.
+        match resolve_config_path(raw, !cli.init) {
+            Ok(path) => set_access_file_path(path),
+            Err(err) => {
+                eprintln!("{}", err);
+                exit(1);
+            }
+        }
+    } else if let Some(name) = cli
+        .profile
+        .clone()
+        .or_else(|| Preferences::load(&prefs_file_path()).0.default_profile)
+    {
+        if let Err(err) = validate_profile_name(&name) {
+            eprintln!("{}", err);
+            exit(1);
+        }
+        set_active_profile(Some(name));
+    }
+
     if !cli.init && !is_app_ready() {
-        no_access_data()
+        // Отсутствующий профиль можно сразу создать; без профиля нужен явный --init.
+        let create = active_profile().is_some_and(|name| {
+            ask_user(&format!("Профиль {} не найден. Создать? (д/Н)", name), "no")
+        });
+        if !create {
+            no_access_data()
+        }
+        init_user_data();
     }
 
     if cli.init {
@@ -36,6 +139,78 @@ pub fn cli_action() {
         // Обязательная проверка, что файл был создан инициализацией.
         is_app_ready();
     }
+
+    if let Some(prompt) = &cli.prompt {
+        one_shot(prompt, &cli.model_overrides()).await;
+    }
+
+    cli
+}
+
+/// Разовый запрос без интерфейса: ответ — в stdout, ошибка — в stderr с кодом выхода 1.
+///
+/// Параметры модели берутся из файла настроек, `overrides` из командной строки важнее их.
+async fn one_shot(prompt: &str, overrides: &ModelOverrides) -> ! {
+    let prompt = match read_prompt(prompt, io::stdin()) {
+        Ok(prompt) => prompt,
+        Err(err) => {
+            eprintln!("Запрос не прочитан: {}", err);
+            exit(1);
+        }
+    };
+
+    let client = match GPTClient::new().load_auth(access_file_path()) {
+        Ok(client) => client,
+        Err(err) => {
+            eprintln!("{}", err);
+            exit(1);
+        }
+    };
+    let (prefs, warning) = Preferences::load(&prefs_file_path());
+    if let Some(warning) = warning {
+        eprintln!("{}", warning);
+    }
+    let client = client.with_data_logging(prefs.data_logging);
+    let client = match prefs.model.apply(client) {
+        Ok(client) => client,
+        Err(err) => {
+            eprintln!("Параметры модели из настроек не применены: {}", err);
+            exit(1);
+        }
+    };
+    let client = overrides
+        .apply(client)
+        .unwrap_or_else(|err| invalid_arguments(&err));
+
+    let answer = client.ask_gpt(&prompt).await;
+    match answer {
+        Ok(answer) => {
+            println!("{}", answer);
+            exit(0);
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            exit(1);
+        }
+    }
+}
+
+/// Текст запроса из аргумента `--prompt`: `-` означает чтение всего `input`.
+///
+/// Пустой запрос — ошибка: модель его не примет.
+fn read_prompt(arg: &str, mut input: impl Read) -> io::Result<String> {
+    let prompt = if arg == "-" {
+        let mut text = String::new();
+        input.read_to_string(&mut text)?;
+        text
+    } else {
+        arg.to_string()
+    };
+
+    if prompt.trim().is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "пустой запрос"));
+    }
+    Ok(prompt)
 }
 
 /// Вывод типового сообщения об отсутствии необходимых данных и рекомендации по действиям.
@@ -91,7 +266,21 @@ fn init_user_data() {
     let api_key = loop_input_user("API-Key: ", AccessData::validator_api_key);
 
     // Создание конфигурационного файла с данными.
-    AccessData::new(id_catalog, api_key).save_me(access_file_path());
+    let access = AccessData::new(id_catalog, api_key);
+    let saved = match active_profile() {
+        Some(name) => save_named(&access, &name),
+        None => access
+            .save_me(access_file_path())
+            .map(|_| access_file_path())
+            .map_err(|err| err.to_string()),
+    };
+    match saved {
+        Ok(path) => println!("Данные доступа сохранены в {}", path.display()),
+        Err(err) => {
+            eprintln!("Данные доступа не сохранены. {}", err);
+            exit(1);
+        }
+    }
 }
 
 /// Получить от пользователя данные в командной строке.
@@ -121,3 +310,89 @@ fn loop_input_user(ask: &str, func_validator: fn(&str) -> bool) -> String {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_prompt_from_argument() {
+        let prompt = read_prompt("кратко перескажи", io::empty()).unwrap();
+        assert_eq!(prompt, "кратко перескажи");
+    }
+
+    #[test]
+    fn test_read_prompt_from_stdin() {
+        let stdin = "Текст\nиз файла\n".as_bytes();
+        assert_eq!(read_prompt("-", stdin).unwrap(), "Текст\nиз файла\n");
+    }
+
+    #[test]
+    fn test_read_prompt_rejects_empty_input() {
+        assert!(read_prompt("  ", io::empty()).is_err());
+        let err = read_prompt("-", "\n".as_bytes()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_model_flags_are_parsed() {
+        let cli = Cli::try_parse_from([
+            "ym",
+            "--model",
+            "yandexgpt-lite/latest",
+            "-t",
+            "0.3",
+            "--max-tokens",
+            "1000",
+        ])
+        .unwrap();
+        assert_eq!(
+            cli.model_overrides(),
+            ModelOverrides {
+                model: Some("yandexgpt-lite/latest".to_string()),
+                temperature: Some(0.3),
+                max_tokens: Some(1000),
+            }
+        );
+
+        let cli = Cli::try_parse_from(["ym"]).unwrap();
+        assert_eq!(cli.model_overrides(), ModelOverrides::default());
+    }
+
+    #[test]
+    fn test_invalid_model_flags_are_rejected() {
+        for args in [
+            ["ym", "--temperature", "1.5"],
+            ["ym", "--temperature", "-0.1"],
+            ["ym", "--temperature", "тепло"],
+            ["ym", "--max-tokens", "0"],
+        ] {
+            let err = Cli::try_parse_from(args).err().unwrap();
+            assert_eq!(
+                err.kind(),
+                clap::error::ErrorKind::ValueValidation,
+                "{:?}",
+                args
+            );
+        }
+    }
+
+    #[test]
+    fn test_prompt_argument_is_parsed() {
+        let cli = Cli::try_parse_from(["ym", "--prompt", "-"]).unwrap();
+        assert_eq!(cli.prompt.as_deref(), Some("-"));
+        let cli = Cli::try_parse_from(["ym"]).unwrap();
+        assert_eq!(cli.prompt, None);
+    }
+
+    #[test]
+    fn test_profile_flag() {
+        let cli = Cli::try_parse_from(["ym", "--profile", "work"]).unwrap();
+        assert_eq!(cli.profile.as_deref(), Some("work"));
+
+        let err = Cli::try_parse_from(["ym", "--profile", "work", "--config", "a.json"])
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), clap::error::ErrorKind::ArgumentConflict);
+    }
+}
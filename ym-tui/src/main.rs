@@ -6,16 +6,102 @@ mod cli;
 mod settings;
 mod utils;
 
+use std::io::{self, Write};
+
 use app::App;
 
 #[tokio::main]
 async fn main() -> color_eyre::Result<()> {
     // Первоначально обработка командной строки.
-    cli::cli_action();
+    let cli = cli::cli_action().await;
+
+    let mut app = App::new();
+    // Ключи командной строки важнее параметров модели из файла настроек.
+    if let Err(err) = app.override_model(&cli.model_overrides()) {
+        cli::invalid_arguments(&err);
+    }
+    if cli.compact {
+        app.set_compact(true);
+    }
 
     color_eyre::install()?;
     let terminal = ratatui::init();
-    let result = App::new().run(terminal).await;
-    ratatui::restore();
+    // Вставка из буфера обмена приходит одним событием, а не потоком нажатий;
+    // захват мыши нужен для прокрутки истории колесом.
+    crossterm::execute!(
+        std::io::stdout(),
+        crossterm::event::EnableBracketedPaste,
+        crossterm::event::EnableMouseCapture
+    )?;
+    // Без расширенного протокола клавиатуры терминал не отличает Shift+Enter от Enter.
+    let keyboard_enhancement =
+        crossterm::terminal::supports_keyboard_enhancement().unwrap_or(false);
+    if keyboard_enhancement {
+        crossterm::execute!(
+            std::io::stdout(),
+            crossterm::event::PushKeyboardEnhancementFlags(
+                crossterm::event::KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES
+            )
+        )?;
+    }
+    install_panic_hook(keyboard_enhancement);
+
+    let result = app.run(terminal).await;
+    restore_terminal(keyboard_enhancement);
     result
 }
+
+/// Восстанавливать терминал и при панике, до того как предыдущий обработчик её напечатает.
+///
+/// Хук `ratatui::init` выводит терминал из raw-режима, но не знает о захвате мыши, вставке
+/// и расширенном протоколе клавиатуры: без этого хука после паники оболочка получает
+/// escape-последовательности вместо щелчков и нажатий. Обработчик `color_eyre` остаётся
+/// в цепочке и печатает отчёт уже в нормальный терминал.
+///
+/// Проверка вручную: добавить `panic!()` в обработку клавиши, запустить `ym`, нажать её —
+/// отчёт о панике печатается целиком, а колесо мыши и ввод в оболочке работают как обычно.
+fn install_panic_hook(keyboard_enhancement: bool) {
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        restore_terminal(keyboard_enhancement);
+        previous(info);
+    }));
+}
+
+/// Вернуть терминал в обычный режим. Безопасно вызывать повторно.
+fn restore_terminal(keyboard_enhancement: bool) {
+    let _ = reset_input_modes(&mut io::stdout(), keyboard_enhancement);
+    ratatui::restore();
+}
+
+/// Отключить режимы ввода, включённые при запуске: расширенный протокол клавиатуры
+/// (если он включался), захват мыши и вставку одним событием.
+fn reset_input_modes(out: &mut impl Write, keyboard_enhancement: bool) -> io::Result<()> {
+    if keyboard_enhancement {
+        crossterm::execute!(out, crossterm::event::PopKeyboardEnhancementFlags)?;
+    }
+    crossterm::execute!(
+        out,
+        crossterm::event::DisableMouseCapture,
+        crossterm::event::DisableBracketedPaste
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reset_input_modes_disables_what_was_enabled() {
+        let mut out = Vec::new();
+        reset_input_modes(&mut out, true).unwrap();
+        let sequences = String::from_utf8(out).unwrap();
+        assert!(sequences.contains("\x1b[<1u"));
+        assert!(sequences.contains("\x1b[?1000l"));
+        assert!(sequences.contains("\x1b[?2004l"));
+
+        let mut out = Vec::new();
+        reset_input_modes(&mut out, false).unwrap();
+        assert!(!String::from_utf8(out).unwrap().contains("\x1b[<1u"));
+    }
+}
@@ -1,35 +1,69 @@
 //! Модуль настроек YM.
 extern crate directories;
-use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock, PoisonError};
+use ym_yagpt::client::GPTClient;
+use ym_yagpt::models::{AccessData, model_token_limit};
+
+use crate::utils::tools::sibling_path;
 
 /// Название файла для хранения конфигурации данных "по-умолчанию".
 pub const ACCESS_FILE: &str = "access.json";
 
-/// Предоставляет полный путь `PathBut` к `ACCESS_FILE` в режиме разработки.
-#[cfg(debug_assertions)]
-pub fn access_file_path() -> PathBuf {
-    let mut access_path = PathBuf::new();
-    access_path.push(env!("CARGO_MANIFEST_DIR"));
-    access_path.push(ACCESS_FILE);
+/// Каталог именованных профилей доступа: `profiles/<имя>.json` в каталоге конфигурации.
+pub const PROFILES_DIR: &str = "profiles";
+
+/// Название файла пользовательских настроек интерфейса.
+pub const PREFS_FILE: &str = "prefs.json";
+
+/// Название файла сохранённой истории диалога.
+pub const HISTORY_FILE: &str = "history.json";
+
+/// Префиксы записей по ролям. Пустой префикс выводит запись без подписи.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Prefixes<'a> {
+    pub user: &'a str,
+    pub assistant: &'a str,
+    pub system: &'a str,
+    pub info: &'a str,
+    pub error: &'a str,
+}
+
+impl Default for Prefixes<'_> {
+    fn default() -> Self {
+        Self {
+            user: "Вы",
+            assistant: "",
+            system: "Система",
+            info: "",
+            error: "Ошибка ответа модели",
+        }
+    }
+}
 
-    access_path
+/// Предоставляет каталог конфигурации в режиме разработки: корень крейта.
+#[cfg(debug_assertions)]
+pub fn config_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
 }
 
-/// Предоставляет полный путь к `ACCESS_FILE` после сборки.
+/// Предоставляет каталог конфигурации после сборки.
 ///
-/// В текущей реализации файл сохраняется в системный каталог ОС. Перед возвратом ссылки проверяет
-/// существование пути, при необходимости создаёт недостающие элементы (каталоги). Наличие самого
-/// файла конфигурации не проверяет.
+/// В текущей реализации это системный каталог ОС. Перед возвратом ссылки проверяет
+/// существование пути, при необходимости создаёт недостающие элементы (каталоги).
 ///
 /// Linux:
 ///
-/// * /home/пользователь/.config/ym/{ACCESS_FILE}
+/// * /home/пользователь/.config/ym/
 ///
 /// Windows:
 ///
-/// * C:\Users\Пользователь\AppData\Roaming\intelligence\ym\{ACCESS_FILE}
+/// * C:\Users\Пользователь\AppData\Roaming\intelligence\ym\
 #[cfg(not(debug_assertions))]
-pub fn access_file_path() -> PathBuf {
+pub fn config_dir() -> PathBuf {
     let proj_dirs = directories::ProjectDirs::from("com", "intelligence", "ym")
         .expect("Не удаётся определить проектную директорию");
 
@@ -38,5 +72,694 @@ pub fn access_file_path() -> PathBuf {
             .expect("Не удалось создать директорию для данных");
     }
 
-    proj_dirs.config_dir().join(ACCESS_FILE)
+    proj_dirs.config_dir().to_path_buf()
+}
+
+/// Путь к файлу доступа, заданный пользователем (`--config`). Устанавливается один раз при старте.
+static ACCESS_FILE_OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
+
+/// Активный профиль доступа (`--profile`, `default_profile` или `/profile switch`).
+static ACTIVE_PROFILE: Mutex<Option<String>> = Mutex::new(None);
+
+/// Предоставляет полный путь `PathBuf` к файлу данных доступа.
+///
+/// Если путь задан через [`set_access_file_path`], возвращается он, при выбранном профиле —
+/// файл профиля (см. [`profile_file_path`]), иначе — `ACCESS_FILE` в каталоге конфигурации.
+/// Наличие самого файла конфигурации не проверяет.
+pub fn access_file_path() -> PathBuf {
+    match ACCESS_FILE_OVERRIDE.get() {
+        Some(path) => path.clone(),
+        None => access_path_in(&config_dir(), active_profile().as_deref()),
+    }
+}
+
+/// Файл доступа в каталоге конфигурации `dir`: профиля `profile` или `ACCESS_FILE`.
+fn access_path_in(dir: &Path, profile: Option<&str>) -> PathBuf {
+    match profile {
+        Some(name) => dir.join(PROFILES_DIR).join(format!("{}.json", name)),
+        None => dir.join(ACCESS_FILE),
+    }
+}
+
+/// Проверить имя профиля: непустое, из латиницы, цифр, `-` и `_`. Так имя остаётся
+/// именем файла и не выводит за пределы каталога профилей.
+pub fn validate_profile_name(name: &str) -> Result<(), String> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(format!(
+            "Недопустимое имя профиля «{}»: допустимы латиница, цифры, - и _",
+            name
+        ))
+    }
+}
+
+/// Путь к файлу профиля `name` в каталоге конфигурации.
+pub fn profile_file_path(name: &str) -> Result<PathBuf, String> {
+    validate_profile_name(name)?;
+    Ok(access_path_in(&config_dir(), Some(name)))
+}
+
+/// Можно ли переключить профиль в чате. При `--config` файл доступа задан явно и
+/// [`access_file_path`] профиль не учитывает: переключение отменилось бы первым `/reload`.
+pub fn check_profile_switch() -> Result<(), String> {
+    profile_switch_with(ACCESS_FILE_OVERRIDE.get().map(PathBuf::as_path))
+}
+
+/// Проверка [`check_profile_switch`] при файле доступа `override_path` из `--config`.
+fn profile_switch_with(override_path: Option<&Path>) -> Result<(), String> {
+    match override_path {
+        Some(path) => Err(format!(
+            "Файл доступа задан ключом --config ({}), профили не переключаются. \
+             Запустите ym без --config.",
+            path.display()
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Имя активного профиля; `None` — используется `ACCESS_FILE`.
+pub fn active_profile() -> Option<String> {
+    ACTIVE_PROFILE
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone()
+}
+
+/// Выбрать активный профиль. Имя должно быть проверено [`validate_profile_name`].
+pub fn set_active_profile(name: Option<String>) {
+    *ACTIVE_PROFILE
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner) = name;
+}
+
+/// Имена сохранённых профилей по алфавиту.
+pub fn profile_names() -> Vec<String> {
+    profile_names_in(&config_dir())
+}
+
+/// Имена профилей в каталоге конфигурации `dir`. Файлы с недопустимыми именами пропускаются.
+fn profile_names_in(dir: &Path) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(dir.join(PROFILES_DIR)) else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = entries
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            if path.extension()? != "json" {
+                return None;
+            }
+            let name = path.file_stem()?.to_str()?.to_string();
+            validate_profile_name(&name).ok().map(|_| name)
+        })
+        .collect();
+    names.sort();
+    names
+}
+
+/// Загрузить данные доступа профиля `name` вместе с предупреждениями о файле.
+pub fn load_named(name: &str) -> Result<(AccessData, Vec<String>), String> {
+    load_named_in(&config_dir(), name)
+}
+
+fn load_named_in(dir: &Path, name: &str) -> Result<(AccessData, Vec<String>), String> {
+    validate_profile_name(name)?;
+    AccessData::load_checked(&access_path_in(dir, Some(name))).map_err(|err| err.to_string())
+}
+
+/// Сохранить данные доступа в профиль `name`, создав каталог профилей. Возвращает путь к файлу.
+pub fn save_named(access: &AccessData, name: &str) -> Result<PathBuf, String> {
+    save_named_in(&config_dir(), access, name)
+}
+
+fn save_named_in(dir: &Path, access: &AccessData, name: &str) -> Result<PathBuf, String> {
+    validate_profile_name(name)?;
+    let path = access_path_in(dir, Some(name));
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|err| format!("Не удалось создать каталог {}: {}", parent.display(), err))?;
+    }
+    match access.save_me(path.clone()) {
+        Ok(()) => Ok(path),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+/// Заменить путь к файлу доступа на пользовательский. Повторные вызовы игнорируются.
+pub fn set_access_file_path(path: PathBuf) {
+    let _ = ACCESS_FILE_OVERRIDE.set(path);
+}
+
+/// Разрешить путь к файлу конфигурации, указанный пользователем.
+///
+/// `~` в начале раскрывается в домашний каталог, относительный путь отсчитывается от текущего
+/// каталога. При `must_exist` файл должен существовать и читаться, иначе достаточно
+/// существующего родительского каталога (файл будет создан при `--init`).
+pub fn resolve_config_path(raw: &str, must_exist: bool) -> Result<PathBuf, String> {
+    let cwd = std::env::current_dir()
+        .map_err(|err| format!("Не удалось определить текущий каталог: {}", err))?;
+    let home = directories::BaseDirs::new().map(|dirs| dirs.home_dir().to_path_buf());
+    let path = expand_path(raw, &cwd, home.as_deref())?;
+
+    if must_exist {
+        fs::File::open(&path)
+            .map_err(|err| format!("Файл конфигурации {} недоступен: {}", path.display(), err))?;
+    } else if let Some(parent) = path.parent()
+        && !parent.is_dir()
+    {
+        return Err(format!("Каталог {} не существует", parent.display()));
+    }
+
+    Ok(path)
+}
+
+/// Разрешить путь к файлу, в который приложение сохраняет диалог (`/save`, `/export`).
+///
+/// `~` в начале раскрывается в домашний каталог, относительный путь отсчитывается от текущего
+/// каталога, а не от каталога конфигурации. Недостающие родительские каталоги создаются.
+/// Существует ли уже сам файл, не проверяется: перезапись подтверждает вызывающая сторона.
+pub fn resolve_output_path(raw: &str) -> Result<PathBuf, String> {
+    let cwd = std::env::current_dir()
+        .map_err(|err| format!("Не удалось определить текущий каталог: {}", err))?;
+    let home = directories::BaseDirs::new().map(|dirs| dirs.home_dir().to_path_buf());
+    output_path_in(raw, &cwd, home.as_deref())
+}
+
+fn output_path_in(raw: &str, cwd: &Path, home: Option<&Path>) -> Result<PathBuf, String> {
+    let path = expand_path(raw, cwd, home)?;
+    if path.is_dir() {
+        return Err(format!("{} — каталог, укажите имя файла", path.display()));
+    }
+    if let Some(parent) = path.parent()
+        && !parent.is_dir()
+    {
+        fs::create_dir_all(parent)
+            .map_err(|err| format!("Не удалось создать каталог {}: {}", parent.display(), err))?;
+    }
+    Ok(path)
+}
+
+/// Превратить введённый путь в абсолютный относительно `cwd` и домашнего каталога `home`.
+fn expand_path(raw: &str, cwd: &Path, home: Option<&Path>) -> Result<PathBuf, String> {
+    let raw = raw.trim();
+    if raw.is_empty() {
+        return Err("Путь к файлу пуст".to_string());
+    }
+
+    let path = match raw.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') || rest.starts_with('\\') => {
+            let home = home.ok_or("Не удалось определить домашний каталог для `~`")?;
+            home.join(rest.trim_start_matches(['/', '\\']))
+        }
+        _ => PathBuf::from(raw),
+    };
+
+    if path.is_absolute() {
+        Ok(path)
+    } else {
+        Ok(cwd.join(path))
+    }
+}
+
+/// Предоставляет полный путь `PathBuf` к `PREFS_FILE` в каталоге конфигурации.
+pub fn prefs_file_path() -> PathBuf {
+    config_dir().join(PREFS_FILE)
+}
+
+/// Предоставляет полный путь `PathBuf` к `HISTORY_FILE` в каталоге конфигурации.
+pub fn history_file_path() -> PathBuf {
+    config_dir().join(HISTORY_FILE)
+}
+
+/// Какое сочетание клавиш отправляет сообщение.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SendOn {
+    /// Enter отправляет сообщение.
+    #[default]
+    Enter,
+    /// Ctrl+Enter отправляет сообщение, а Enter переносит строку.
+    CtrlEnter,
+}
+
+impl SendOn {
+    /// Название сочетания клавиш для подсказок в интерфейсе.
+    pub fn label(&self) -> &'static str {
+        match self {
+            SendOn::Enter => "Enter",
+            SendOn::CtrlEnter => "Ctrl+Enter",
+        }
+    }
+}
+
+/// Параметры модели, переопределяющие встроенные значения клиента.
+///
+/// Задаются в файле настроек и ключами командной строки; ключи важнее файла.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ModelOverrides {
+    /// Модель вместо модели по умолчанию.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Температура генерации, от `0.0` до `1.0`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    /// Лимит токенов ответа.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<i64>,
+}
+
+impl ModelOverrides {
+    /// Применить заданные параметры к клиенту.
+    ///
+    /// В отличие от построителей клиента не паникует: при недопустимом значении возвращает
+    /// описание ошибки, а клиент не меняется. Лимит токенов проверяется для итоговой модели.
+    pub fn apply(&self, client: GPTClient) -> Result<GPTClient, String> {
+        if let Some(temperature) = self.temperature
+            && !(0.0..=1.0).contains(&temperature)
+        {
+            return Err(format!(
+                "температура должна быть от 0 до 1, получено: {}",
+                temperature
+            ));
+        }
+        if let Some(max_tokens) = self.max_tokens {
+            let model = self.model.as_deref().unwrap_or(&client.gpt_options.model);
+            if max_tokens <= 0 {
+                return Err(format!(
+                    "лимит токенов должен быть больше 0, получено: {}",
+                    max_tokens
+                ));
+            }
+            if let Some(limit) = model_token_limit(model)
+                && max_tokens > limit
+            {
+                return Err(format!(
+                    "лимит токенов {} превышает лимит модели {} ({})",
+                    max_tokens, model, limit
+                ));
+            }
+        }
+
+        let mut client = client;
+        if let Some(model) = &self.model {
+            client = client.with_model(model);
+        }
+        if let Some(temperature) = self.temperature {
+            client = client.with_temperature(temperature);
+        }
+        if let Some(max_tokens) = self.max_tokens {
+            client = client.with_max_tokens(max_tokens);
+        }
+        Ok(client)
+    }
+}
+
+/// Пользовательские настройки интерфейса.
+///
+/// Хранятся в `PREFS_FILE` рядом с данными доступа. Отсутствующие в файле поля получают значения
+/// по умолчанию, поэтому файл может содержать только изменённые настройки.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Preferences {
+    /// Спрашивать подтверждение, если сообщение совпадает с предыдущим сообщением пользователя.
+    pub confirm_duplicate: bool,
+    /// Компактный интерфейс: без заголовка и рамок блоков.
+    pub compact: bool,
+    /// Показывать время сообщений в истории (`[ЧЧ:ММ]`).
+    pub show_timestamps: bool,
+    /// Сочетание клавиш для отправки сообщения: `enter` или `ctrl_enter`.
+    pub send_on: SendOn,
+    /// С какой длины серия пустых строк в ответе сворачивается в одну (`0` — не сворачивать).
+    pub blank_lines_threshold: usize,
+    /// С какой оценки числа токенов в поле ввода счётчик становится жёлтым.
+    pub input_tokens_warn: usize,
+    /// С какой оценки числа токенов в поле ввода счётчик становится красным.
+    pub input_tokens_limit: usize,
+    /// Префикс сообщений пользователя в истории и экспорте.
+    pub user_name: String,
+    /// Префикс ответов модели в истории; пустая строка — без префикса.
+    pub assistant_name: String,
+    /// Префикс служебных сообщений; пустая строка — без префикса.
+    pub system_prefix: String,
+    /// Префикс информационных строк (например, приветствия); по умолчанию без префикса.
+    pub info_prefix: String,
+    /// Префикс ошибок запроса к модели; пустая строка — без префикса.
+    pub error_prefix: String,
+    /// Наибольшая ширина колонки сообщений в символах, колонка центрируется.
+    /// `0` — без ограничения.
+    pub messages_max_width: u16,
+    /// Наибольший размер вставки в поле ввода в символах; лишнее отбрасывается.
+    pub paste_max_chars: usize,
+    /// Спрашивать подтверждение с оценкой токенов перед каждой отправкой модели.
+    pub confirm_before_send: bool,
+    /// Сколько вариантов ответа запрашивать у модели; выбрать другой вариант — `/pick N`.
+    pub alternatives: u32,
+    /// Сколько последних обменов (вопрос и ответ) отправлять модели; `0` — все. Более ранние
+    /// сообщения остаются в истории на экране. Прежнее имя поля — `max_history_messages`.
+    #[serde(alias = "max_history_messages")]
+    pub max_history_exchanges: usize,
+    /// Показывать ответ модели по мере генерации. Действует, когда запрашивается один вариант.
+    pub stream: bool,
+    /// Профиль доступа, выбираемый при запуске без `--profile`. Без него — `ACCESS_FILE`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_profile: Option<String>,
+    /// Разрешить Yandex Cloud журналировать запросы. `false` отправляет заголовок
+    /// `x-data-logging-enabled: false`.
+    pub data_logging: bool,
+    /// Считать токены поля ввода токенизатором модели после паузы в наборе. Выключено
+    /// по умолчанию: черновик уходит на сервер до того, как пользователь решил его отправить.
+    pub count_tokens: bool,
+    /// Модель, температура и лимит токенов вместо встроенных значений.
+    #[serde(flatten)]
+    pub model: ModelOverrides,
+}
+
+/// Заполненность поля ввода относительно порогов из настроек.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputLoad {
+    /// Далеко до лимита.
+    Normal,
+    /// Достигнут порог предупреждения.
+    Warning,
+    /// Достигнут или превышен лимит.
+    Limit,
+}
+
+impl Default for Preferences {
+    fn default() -> Self {
+        Self {
+            confirm_duplicate: false,
+            compact: false,
+            show_timestamps: false,
+            send_on: SendOn::default(),
+            blank_lines_threshold: 3,
+            input_tokens_warn: 1500,
+            input_tokens_limit: 2000,
+            user_name: "Вы".to_string(),
+            assistant_name: String::new(),
+            system_prefix: "Система".to_string(),
+            info_prefix: String::new(),
+            error_prefix: "Ошибка ответа модели".to_string(),
+            messages_max_width: 100,
+            paste_max_chars: 20_000,
+            confirm_before_send: false,
+            alternatives: 1,
+            max_history_exchanges: 10,
+            stream: true,
+            default_profile: None,
+            data_logging: true,
+            count_tokens: false,
+            model: ModelOverrides::default(),
+        }
+    }
+}
+
+impl Preferences {
+    /// Загрузить настройки из файла. Отсутствующий или повреждённый файл даёт настройки
+    /// по умолчанию.
+    ///
+    /// О повреждённом файле возвращается предупреждение, а сам файл копируется в
+    /// `<path>.bak`: следующее сохранение настроек его перезапишет.
+    pub fn load(path: &Path) -> (Self, Option<String>) {
+        match Self::load_checked(path) {
+            Ok(prefs) => (prefs, None),
+            Err(err) => {
+                let backup = sibling_path(path, "bak");
+                let kept = match fs::copy(path, &backup) {
+                    Ok(_) => format!("копия повреждённого файла: {}", backup.display()),
+                    Err(copy_err) => format!("копию файла сохранить не удалось: {}", copy_err),
+                };
+                let warning = format!(
+                    "Настройки не загружены, используются значения по умолчанию ({}); {}.",
+                    err, kept
+                );
+                (Self::default(), Some(warning))
+            }
+        }
+    }
+
+    /// Загрузить настройки из файла, сообщая о повреждённом файле.
+    ///
+    /// Отсутствующий файл — не ошибка: возвращаются настройки по умолчанию.
+    pub fn load_checked(path: &Path) -> Result<Self, String> {
+        match fs::read_to_string(path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .map_err(|err| format!("{}: ошибка разбора: {}", path.display(), err)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(format!("{} недоступен: {}", path.display(), err)),
+        }
+    }
+
+    /// Префиксы записей истории по ролям.
+    pub fn prefixes(&self) -> Prefixes<'_> {
+        Prefixes {
+            user: &self.user_name,
+            assistant: &self.assistant_name,
+            system: &self.system_prefix,
+            info: &self.info_prefix,
+            error: &self.error_prefix,
+        }
+    }
+
+    /// Заполненность поля ввода для оценки `tokens` токенов.
+    pub fn input_load(&self, tokens: usize) -> InputLoad {
+        if tokens >= self.input_tokens_limit {
+            InputLoad::Limit
+        } else if tokens >= self.input_tokens_warn {
+            InputLoad::Warning
+        } else {
+            InputLoad::Normal
+        }
+    }
+
+    /// Сохранить настройки в файл.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let json = serde_json::to_string_pretty(self).map_err(io::Error::other)?;
+        fs::write(path, json)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile_path_resolution() {
+        let dir = Path::new("/conf");
+        assert_eq!(access_path_in(dir, None), Path::new("/conf/access.json"));
+        assert_eq!(
+            access_path_in(dir, Some("work")),
+            Path::new("/conf/profiles/work.json")
+        );
+
+        assert!(validate_profile_name("home_2-b").is_ok());
+        for bad in ["", "../work", "my profile", "дом", "a/b"] {
+            assert!(validate_profile_name(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_profile_switch_rejected_with_config_flag() {
+        assert!(profile_switch_with(None).is_ok());
+        let err = profile_switch_with(Some(Path::new("/work/access.json"))).unwrap_err();
+        assert!(err.contains("--config (/work/access.json)"), "{}", err);
+    }
+
+    #[test]
+    fn test_named_profiles_roundtrip() {
+        let dir = std::env::temp_dir().join(format!("ym-profiles-{}", std::process::id()));
+        let access = AccessData::new("b1gcatalog".to_string(), "AQVN-key".to_string());
+
+        let path = save_named_in(&dir, &access, "work").unwrap();
+        assert_eq!(path, dir.join("profiles").join("work.json"));
+        save_named_in(&dir, &access, "home").unwrap();
+        fs::write(dir.join("profiles").join("notes.txt"), "").unwrap();
+
+        assert_eq!(profile_names_in(&dir), ["home", "work"]);
+        let (loaded, warnings) = load_named_in(&dir, "work").unwrap();
+        assert_eq!(loaded.folder(), "b1gcatalog");
+        assert!(warnings.is_empty());
+        assert!(load_named_in(&dir, "missing").is_err());
+        assert!(save_named_in(&dir, &access, "../escape").is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_partial_prefs_use_defaults() {
+        let prefs: Preferences = serde_json::from_str("{}").unwrap();
+        assert_eq!(prefs, Preferences::default());
+    }
+
+    #[test]
+    fn test_history_limit_reads_old_name() {
+        let prefs: Preferences = serde_json::from_str(r#"{"max_history_messages": 4}"#).unwrap();
+        assert_eq!(prefs.max_history_exchanges, 4);
+        let prefs: Preferences = serde_json::from_str(r#"{"max_history_exchanges": 3}"#).unwrap();
+        assert_eq!(prefs.max_history_exchanges, 3);
+    }
+
+    #[test]
+    fn test_model_overrides_are_top_level_prefs() {
+        let prefs: Preferences =
+            serde_json::from_str(r#"{"model": "yandexgpt-lite/latest", "temperature": 0.2}"#)
+                .unwrap();
+        assert_eq!(prefs.model.model.as_deref(), Some("yandexgpt-lite/latest"));
+        assert_eq!(prefs.model.temperature, Some(0.2));
+        assert_eq!(prefs.model.max_tokens, None);
+
+        let json = serde_json::to_string(&Preferences::default()).unwrap();
+        assert!(!json.contains("temperature"));
+    }
+
+    #[test]
+    fn test_model_overrides_apply_to_client() {
+        let overrides = ModelOverrides {
+            model: Some("yandexgpt-lite/latest".to_string()),
+            temperature: Some(0.1),
+            max_tokens: Some(500),
+        };
+        let client = overrides.apply(GPTClient::new()).unwrap();
+        assert_eq!(client.gpt_options.model, "yandexgpt-lite/latest");
+        assert_eq!(client.gpt_options.temperature, 0.1);
+        assert_eq!(client.gpt_options.max_tokens, 500);
+
+        let defaults = GPTClient::new().gpt_options;
+        let client = ModelOverrides::default().apply(GPTClient::new()).unwrap();
+        assert_eq!(client.gpt_options.model, defaults.model);
+        assert_eq!(client.gpt_options.max_tokens, defaults.max_tokens);
+    }
+
+    #[test]
+    fn test_invalid_model_overrides_are_errors() {
+        let hot = ModelOverrides {
+            temperature: Some(1.5),
+            ..Default::default()
+        };
+        let err = hot.apply(GPTClient::new()).unwrap_err();
+        assert!(err.contains("температура"));
+
+        let huge = ModelOverrides {
+            max_tokens: Some(10_000_000),
+            ..Default::default()
+        };
+        let err = huge.apply(GPTClient::new()).unwrap_err();
+        assert!(err.contains("лимит модели"));
+    }
+
+    #[test]
+    fn test_send_on_is_snake_case() {
+        let prefs: Preferences = serde_json::from_str(r#"{"send_on": "ctrl_enter"}"#).unwrap();
+        assert_eq!(prefs.send_on, SendOn::CtrlEnter);
+        assert_eq!(Preferences::default().send_on, SendOn::Enter);
+    }
+
+    #[test]
+    fn test_default_prefixes_match_history_defaults() {
+        assert_eq!(Preferences::default().prefixes(), Prefixes::default());
+    }
+
+    #[test]
+    fn test_input_load_thresholds() {
+        let prefs = Preferences {
+            input_tokens_warn: 10,
+            input_tokens_limit: 20,
+            ..Preferences::default()
+        };
+        assert_eq!(prefs.input_load(0), InputLoad::Normal);
+        assert_eq!(prefs.input_load(9), InputLoad::Normal);
+        assert_eq!(prefs.input_load(10), InputLoad::Warning);
+        assert_eq!(prefs.input_load(20), InputLoad::Limit);
+        assert_eq!(prefs.input_load(500), InputLoad::Limit);
+    }
+
+    #[test]
+    fn test_expand_path_forms() {
+        let cwd = Path::new("/work/project");
+        let home = Some(Path::new("/home/user"));
+
+        assert_eq!(
+            expand_path("/etc/ym/access.json", cwd, home).unwrap(),
+            PathBuf::from("/etc/ym/access.json")
+        );
+        assert_eq!(
+            expand_path("conf/access.json", cwd, home).unwrap(),
+            PathBuf::from("/work/project/conf/access.json")
+        );
+        assert_eq!(
+            expand_path("./access.json", cwd, home).unwrap(),
+            PathBuf::from("/work/project/./access.json")
+        );
+        assert_eq!(
+            expand_path("~/ym/access.json", cwd, home).unwrap(),
+            PathBuf::from("/home/user/ym/access.json")
+        );
+        assert_eq!(
+            expand_path("~", cwd, home).unwrap(),
+            PathBuf::from("/home/user")
+        );
+    }
+
+    #[test]
+    fn test_expand_path_tilde_in_name_is_literal() {
+        let cwd = Path::new("/work");
+        assert_eq!(
+            expand_path("~backup.json", cwd, None).unwrap(),
+            PathBuf::from("/work/~backup.json")
+        );
+    }
+
+    #[test]
+    fn test_expand_path_errors() {
+        let cwd = Path::new("/work");
+        assert!(expand_path("  ", cwd, None).is_err());
+        assert!(expand_path("~/access.json", cwd, None).is_err());
+    }
+
+    #[test]
+    fn test_output_path_is_relative_to_cwd_and_creates_dirs() {
+        let cwd = std::env::temp_dir().join(format!("ym-output-{}", std::process::id()));
+        let path = output_path_in("exports/today/dialog.md", &cwd, None).unwrap();
+
+        assert_eq!(path, cwd.join("exports/today/dialog.md"));
+        assert!(cwd.join("exports/today").is_dir());
+        assert!(output_path_in("exports", &cwd, None).is_err());
+        assert!(output_path_in(" ", &cwd, None).is_err());
+        fs::remove_dir_all(&cwd).unwrap();
+    }
+
+    #[test]
+    fn test_resolve_config_path_requires_existing_file() {
+        let missing = std::env::temp_dir().join("ym-missing-config.json");
+        let raw = missing.to_str().unwrap();
+
+        assert!(resolve_config_path(raw, true).is_err());
+        assert_eq!(resolve_config_path(raw, false).unwrap(), missing);
+    }
+
+    #[test]
+    fn test_missing_prefs_file_gives_defaults() {
+        let path = std::env::temp_dir().join("ym-prefs-does-not-exist.json");
+        assert_eq!(Preferences::load(&path), (Preferences::default(), None));
+        assert_eq!(Preferences::load_checked(&path), Ok(Preferences::default()));
+    }
+
+    #[test]
+    fn test_load_checked_reports_corrupt_prefs() {
+        let path = std::env::temp_dir().join("ym-prefs-corrupt.json");
+        fs::write(&path, "{ not json").unwrap();
+
+        assert!(Preferences::load_checked(&path).is_err());
+        let (prefs, warning) = Preferences::load(&path);
+        assert_eq!(prefs, Preferences::default());
+        let backup = std::env::temp_dir().join("ym-prefs-corrupt.json.bak");
+        assert!(warning.unwrap().contains(&backup.display().to_string()));
+        assert_eq!(fs::read_to_string(&backup).unwrap(), "{ not json");
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&backup).unwrap();
+    }
 }
@@ -0,0 +1,184 @@
+//! Упрощённый разбор markdown в ответах модели для отрисовки в терминале.
+//!
+//! Поддерживаются только блоки кода в ограждении ```` ``` ````, `встроенный код` и **жирный**
+//! текст. Несбалансированная разметка выводится как есть.
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span, Text};
+
+/// Ограждение блока кода.
+const FENCE: &str = "```";
+
+/// Стиль кода: блоков и встроенных фрагментов.
+const CODE_STYLE: Style = Style::new().fg(Color::LightYellow).bg(Color::DarkGray);
+
+/// Стиль жирного текста.
+const BOLD_STYLE: Style = Style::new().add_modifier(Modifier::BOLD);
+
+/// Преобразовать markdown-текст в стилизованный [`Text`].
+///
+/// Строки ограждений блока кода не выводятся, строки внутри блока выводятся без разбора
+/// со стилем кода. Ограждение без закрывающей пары считается обычным текстом.
+pub fn render_markdown(text: &str) -> Text<'static> {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut result = Vec::with_capacity(lines.len());
+    let mut i = 0;
+
+    while i < lines.len() {
+        let line = lines[i];
+        if is_fence(line)
+            && let Some(len) = lines[i + 1..].iter().position(|line| is_fence(line))
+        {
+            result.extend(
+                lines[i + 1..i + 1 + len]
+                    .iter()
+                    .map(|code| Line::from(Span::styled(code.to_string(), CODE_STYLE))),
+            );
+            i += len + 2;
+        } else if is_fence(line) {
+            result.push(Line::from(line.to_string()));
+            i += 1;
+        } else {
+            result.push(render_inline(line));
+            i += 1;
+        }
+    }
+
+    Text::from(result)
+}
+
+/// Текст без markdown-разметки: то, что видно на экране после [`render_markdown`].
+pub fn strip_markdown(text: &str) -> String {
+    render_markdown(text)
+        .lines
+        .iter()
+        .map(|line| line.to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Строка открывает или закрывает блок кода.
+fn is_fence(line: &str) -> bool {
+    line.trim_start().starts_with(FENCE)
+}
+
+/// Разбор строки вне блока кода: `встроенный код` и **жирный** текст.
+fn render_inline(line: &str) -> Line<'static> {
+    let mut spans = Vec::new();
+    let mut plain = String::new();
+    let mut rest = line;
+
+    while let Some(ch) = rest.chars().next() {
+        let marked = if let Some(tail) = rest.strip_prefix("**") {
+            tail.find("**")
+                .filter(|&end| end > 0)
+                .map(|end| (&tail[..end], BOLD_STYLE, &tail[end + 2..]))
+        } else if let Some(tail) = rest.strip_prefix('`') {
+            tail.find('`')
+                .filter(|&end| end > 0)
+                .map(|end| (&tail[..end], CODE_STYLE, &tail[end + 1..]))
+        } else {
+            None
+        };
+
+        match marked {
+            Some((content, style, tail)) => {
+                if !plain.is_empty() {
+                    spans.push(Span::raw(std::mem::take(&mut plain)));
+                }
+                spans.push(Span::styled(content.to_string(), style));
+                rest = tail;
+            }
+            None => {
+                plain.push(ch);
+                rest = &rest[ch.len_utf8()..];
+            }
+        }
+    }
+
+    if !plain.is_empty() {
+        spans.push(Span::raw(plain));
+    }
+    Line::from(spans)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Содержимое строк без стилей.
+    fn plain_lines(text: &Text) -> Vec<String> {
+        text.lines.iter().map(|line| line.to_string()).collect()
+    }
+
+    #[test]
+    fn test_fenced_code_block() {
+        let text = render_markdown("Пример:\n```rust\nfn main() {\n    run();\n}\n```\nГотово.");
+
+        assert_eq!(
+            plain_lines(&text),
+            ["Пример:", "fn main() {", "    run();", "}", "Готово."]
+        );
+        for line in &text.lines[1..4] {
+            assert!(line.spans.iter().all(|span| span.style == CODE_STYLE));
+        }
+        assert_eq!(text.lines[4].spans[0].style, Style::default());
+    }
+
+    #[test]
+    fn test_inline_code() {
+        let line = render_inline("Вызовите `cargo run` дважды");
+
+        let spans: Vec<_> = line
+            .spans
+            .iter()
+            .map(|span| (span.content.as_ref(), span.style))
+            .collect();
+        assert_eq!(
+            spans,
+            [
+                ("Вызовите ", Style::default()),
+                ("cargo run", CODE_STYLE),
+                (" дважды", Style::default()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_bold() {
+        let line = render_inline("Это **важно** и **очень**");
+
+        let bold: Vec<_> = line
+            .spans
+            .iter()
+            .filter(|span| span.style == BOLD_STYLE)
+            .map(|span| span.content.as_ref())
+            .collect();
+        assert_eq!(bold, ["важно", "очень"]);
+        assert_eq!(line.to_string(), "Это важно и очень");
+    }
+
+    #[test]
+    fn test_strip_markdown() {
+        assert_eq!(
+            strip_markdown("Вызовите `run()`:\n```rust\nrun();\n```\n**Готово**."),
+            "Вызовите run():\nrun();\nГотово."
+        );
+        assert_eq!(strip_markdown("цена 5 ** 2"), "цена 5 ** 2");
+    }
+
+    #[test]
+    fn test_unbalanced_markup_is_plain_text() {
+        let text = render_markdown("```rust\nfn main() {}\nцена 5 ** 2 и `x");
+
+        assert_eq!(
+            plain_lines(&text),
+            ["```rust", "fn main() {}", "цена 5 ** 2 и `x"]
+        );
+        assert!(
+            text.lines
+                .iter()
+                .flat_map(|line| &line.spans)
+                .all(|span| span.style == Style::default())
+        );
+    }
+}
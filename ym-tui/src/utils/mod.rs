@@ -1 +1,3 @@
+pub mod markdown;
 pub mod tools;
+pub mod wrap;
@@ -1,5 +1,7 @@
 //! Модуль общих универсальных методов обработки.
+use std::fs;
 use std::io::{Error, Write, stdin, stdout};
+use std::path::{Path, PathBuf};
 
 /// Получить ответ пользователя (yes/no) и вернуть соответствующий логический тип (`true`/`false`).
 ///
@@ -78,6 +80,72 @@ pub fn user_input_with_question(question: &str, new_line: bool) -> Result<String
     user_input()
 }
 
+/// Свернуть серии из `threshold` и более подряд идущих пустых строк в одну пустую строку.
+///
+/// Строка из одних пробельных символов считается пустой. При `threshold == 0` текст
+/// возвращается без изменений.
+pub fn collapse_blank_lines(text: &str, threshold: usize) -> String {
+    if threshold == 0 {
+        return text.to_string();
+    }
+
+    let lines: Vec<&str> = text.split('\n').collect();
+    let mut result: Vec<&str> = Vec::with_capacity(lines.len());
+    let mut i = 0;
+
+    while i < lines.len() {
+        let run = lines[i..]
+            .iter()
+            .take_while(|line| line.trim().is_empty())
+            .count();
+
+        if run == 0 {
+            result.push(lines[i]);
+            i += 1;
+        } else {
+            if run >= threshold {
+                result.push("");
+            } else {
+                result.extend_from_slice(&lines[i..i + run]);
+            }
+            i += run;
+        }
+    }
+
+    result.join("\n")
+}
+
+/// Путь рядом с `path` с дополнительным расширением `extension`: `history.json.bak`.
+pub fn sibling_path(path: &Path, extension: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(extension);
+    path.with_file_name(name)
+}
+
+/// Записать `contents` в файл `path` так, чтобы сбой не оставил его обрезанным.
+///
+/// Данные пишутся во временный файл рядом (`<path>.tmp`) и переименовываются в `path`.
+/// Прежнее содержимое `path`, если файл был, сохраняется в `<path>.bak`.
+pub fn write_with_backup(path: &Path, contents: &[u8]) -> Result<(), Error> {
+    let temp = sibling_path(path, "tmp");
+    let written = fs::File::create(&temp).and_then(|mut file| {
+        file.write_all(contents)?;
+        file.sync_all()
+    });
+    if let Err(err) = written {
+        let _ = fs::remove_file(&temp);
+        return Err(err);
+    }
+
+    if path.exists() {
+        fs::copy(path, sibling_path(path, "bak"))?;
+    }
+    fs::rename(&temp, path).inspect_err(|_| {
+        let _ = fs::remove_file(&temp);
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -87,7 +155,7 @@ mod tests {
         let yes_inputs = ["y", "Y", "yes", "YES", "д", "Д", "да", "Да", "ДА"];
         for input in yes_inputs {
             let res = yes_or_no(input, "no");
-            assert_eq!(res.unwrap(), true, "Не распознано как 'да': {}", input);
+            assert!(res.unwrap(), "Не распознано как 'да': {}", input);
         }
     }
 
@@ -96,20 +164,20 @@ mod tests {
         let no_inputs = ["n", "N", "no", "NO", "н", "Н", "нет", "Нет", "НЕТ"];
         for input in no_inputs {
             let res = yes_or_no(input, "yes");
-            assert_eq!(res.unwrap(), false, "Не распознано как 'нет': {}", input);
+            assert!(!res.unwrap(), "Не распознано как 'нет': {}", input);
         }
     }
 
     #[test]
     fn test_empty_uses_default_yes() {
         let res = yes_or_no("", "yes").unwrap();
-        assert_eq!(res, true);
+        assert!(res);
     }
 
     #[test]
     fn test_empty_uses_default_no() {
         let res = yes_or_no("", "no").unwrap();
-        assert_eq!(res, false);
+        assert!(!res);
     }
 
     #[test]
@@ -130,4 +198,50 @@ mod tests {
         let res = yes_or_no("", "invalid");
         assert!(res.is_err());
     }
+
+    #[test]
+    fn test_collapse_blank_lines_long_run() {
+        let text = "Первый абзац\n\n\n\n\nВторой абзац";
+        assert_eq!(
+            collapse_blank_lines(text, 3),
+            "Первый абзац\n\nВторой абзац"
+        );
+    }
+
+    #[test]
+    fn test_collapse_blank_lines_keeps_short_runs() {
+        let text = "a\n\n\nb";
+        assert_eq!(collapse_blank_lines(text, 3), text);
+    }
+
+    #[test]
+    fn test_collapse_blank_lines_whitespace_only_lines() {
+        let text = "a\n  \n\t\n \nb";
+        assert_eq!(collapse_blank_lines(text, 3), "a\n\nb");
+    }
+
+    #[test]
+    fn test_write_with_backup_keeps_previous_file() {
+        let dir = std::env::temp_dir().join(format!("ym-write-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("history.json");
+
+        write_with_backup(&path, b"first").unwrap();
+        assert!(!sibling_path(&path, "bak").exists());
+        write_with_backup(&path, b"second").unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "second");
+        assert_eq!(
+            fs::read_to_string(dir.join("history.json.bak")).unwrap(),
+            "first"
+        );
+        assert!(!dir.join("history.json.tmp").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_collapse_blank_lines_disabled() {
+        let text = "a\n\n\n\n\nb";
+        assert_eq!(collapse_blank_lines(text, 0), text);
+    }
 }
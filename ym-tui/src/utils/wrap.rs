@@ -0,0 +1,147 @@
+//! Перенос строк по ширине на экране, а не по числу символов.
+//!
+//! Иероглифы и большинство эмодзи занимают две колонки терминала, а комбинируемые знаки —
+//! ни одной. Строки переносятся заранее по ширине из `unicode-width` и выводятся без переноса
+//! ratatui, поэтому текст не заходит на рамки панелей.
+use ratatui::style::Style;
+use ratatui::text::{Line, Span};
+use unicode_width::UnicodeWidthChar;
+
+/// Ширина символа в колонках терминала. Управляющие символы не занимают места.
+pub fn char_width(ch: char) -> usize {
+    ch.width().unwrap_or(0)
+}
+
+/// Ширина строки в колонках терминала.
+pub fn display_width(text: &str) -> usize {
+    text.chars().map(char_width).sum()
+}
+
+/// Перенести строки `lines` по ширине `width` колонок (`0` — без переноса).
+pub fn wrap_lines(lines: Vec<Line<'static>>, width: usize) -> Vec<Line<'static>> {
+    lines
+        .into_iter()
+        .flat_map(|line| wrap_line(line, width))
+        .collect()
+}
+
+/// Перенести стилизованную строку по ширине `width` колонок (`0` — без переноса).
+///
+/// Перенос идёт по последнему пробелу, уместившемуся в строку; слово длиннее ширины
+/// разрывается. Пробелы сохраняются: отступы в блоках кода значимы. Стили фрагментов
+/// и строки переносятся на каждую часть.
+pub fn wrap_line(line: Line<'static>, width: usize) -> Vec<Line<'static>> {
+    if width == 0 || display_width(&line.to_string()) <= width {
+        return vec![line];
+    }
+
+    let chars: Vec<(char, Style)> = line
+        .spans
+        .iter()
+        .flat_map(|span| span.content.chars().map(move |ch| (ch, span.style)))
+        .collect();
+
+    let mut rows = Vec::new();
+    let mut row: Vec<(char, Style)> = Vec::new();
+    let mut row_width = 0;
+    // Позиция в `row` сразу после последнего пробела.
+    let mut after_space = None;
+
+    for (ch, style) in chars {
+        let ch_width = char_width(ch);
+        if row_width + ch_width > width && !row.is_empty() {
+            let rest = match after_space {
+                Some(at) if at < row.len() => row.split_off(at),
+                _ => Vec::new(),
+            };
+            rows.push(std::mem::replace(&mut row, rest));
+            row_width = row.iter().map(|&(ch, _)| char_width(ch)).sum();
+            after_space = None;
+            // Перенесённый хвост слова вместе с широким символом может снова не уместиться.
+            if row_width + ch_width > width && !row.is_empty() {
+                rows.push(std::mem::take(&mut row));
+                row_width = 0;
+            }
+        }
+        row.push((ch, style));
+        row_width += ch_width;
+        if ch.is_whitespace() {
+            after_space = Some(row.len());
+        }
+    }
+    rows.push(row);
+
+    rows.into_iter()
+        .map(|row| {
+            let mut wrapped = styled_line(row);
+            wrapped.style = line.style;
+            wrapped.alignment = line.alignment;
+            wrapped
+        })
+        .collect()
+}
+
+/// Собрать строку из символов, объединяя соседние символы одного стиля во фрагмент.
+fn styled_line(chars: Vec<(char, Style)>) -> Line<'static> {
+    let mut spans: Vec<Span<'static>> = Vec::new();
+    for (ch, style) in chars {
+        match spans.last_mut() {
+            Some(span) if span.style == style => span.content.to_mut().push(ch),
+            _ => spans.push(Span::styled(ch.to_string(), style)),
+        }
+    }
+    Line::from(spans)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::style::Color;
+
+    /// Содержимое строк без стилей.
+    fn plain(lines: &[Line]) -> Vec<String> {
+        lines.iter().map(|line| line.to_string()).collect()
+    }
+
+    #[test]
+    fn test_display_width_of_wide_and_combining_chars() {
+        assert_eq!(display_width("你好"), 4);
+        assert_eq!(display_width("ok👍"), 4);
+        assert_eq!(display_width("е\u{301}"), 1);
+    }
+
+    #[test]
+    fn test_wide_chars_wrap_by_columns() {
+        let lines = wrap_line(Line::from("ab你好cd"), 5);
+        assert_eq!(plain(&lines), ["ab你", "好cd"]);
+
+        let lines = wrap_line(Line::from("你好你好"), 3);
+        assert_eq!(plain(&lines), ["你", "好", "你", "好"]);
+        assert!(lines.iter().all(|line| line.width() <= 3));
+
+        // Широкий символ после пробела не должен расширять строку с перенесённым хвостом.
+        let lines = wrap_line(Line::from("a你 你你"), 3);
+        assert_eq!(plain(&lines), ["a你", " ", "你", "你"]);
+        assert!(lines.iter().all(|line| line.width() <= 3));
+    }
+
+    #[test]
+    fn test_emoji_wrap_point() {
+        let lines = wrap_line(Line::from("ok 👍👍 да"), 6);
+        assert_eq!(plain(&lines), ["ok ", "👍👍 ", "да"]);
+
+        let lines = wrap_line(Line::from("abcd👍"), 5);
+        assert_eq!(plain(&lines), ["abcd", "👍"]);
+    }
+
+    #[test]
+    fn test_wrap_keeps_styles_and_indent() {
+        let red = Style::new().fg(Color::Red);
+        let line = Line::from(vec![Span::raw("    let "), Span::styled("значение", red)]);
+
+        let lines = wrap_line(line, 10);
+        assert_eq!(plain(&lines), ["    let ", "значение"]);
+        assert_eq!(lines[1].spans[0].style, red);
+        assert_eq!(wrap_lines(vec![Line::from("коротко")], 0).len(), 1);
+    }
+}
@@ -1,29 +1,74 @@
 //! Клиент для взаимодействия с YandexGPT API.
 
-use crate::errors::GPTError;
+use crate::errors::{GPTError, Result, api_error, parse_retry_after};
 use crate::models::*;
-use reqwest::Client;
+use crate::stream::StreamDecoder;
+use crate::transport::{HttpTransport, Transport, TransportResponse};
+use futures::{Stream, StreamExt};
+use reqwest::header::{AUTHORIZATION, HeaderMap, HeaderName, HeaderValue, USER_AGENT};
+use serde::de::DeserializeOwned;
 use serde_json::json;
-use std::error::Error;
+use std::collections::VecDeque;
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
 
 /// Клиент для текстового общения с языковой моделью.
 ///
 /// Документация: <https://clck.ru/3Qf3nV>
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct GPTClient {
     pub access: AccessData,
     /// Ссылка на API Yandex Cloud для работы с YandexGPT.
     pub api_url: String,
+    /// Ссылка на API эмбеддингов (см. [`GPTClient::embed`]).
+    pub embedding_url: String,
+    /// Ссылка на API токенизатора (см. [`GPTClient::count_tokens`]).
+    pub tokenize_url: String,
     pub gpt_options: GPTOptions,
+    /// Транспорт запросов, общий для клонов клиента (см. [`GPTClient::with_transport`]).
+    transport: Arc<dyn Transport>,
+    /// Таймаут запроса целиком. Если не задан, действует поведение `reqwest` по умолчанию.
+    timeout: Option<Duration>,
+    /// Адрес прокси (см. [`GPTClient::with_proxy`]).
+    proxy: Option<String>,
+    /// Число попыток отправки запроса (см. [`GPTClient::with_retry`]).
+    max_attempts: u32,
+    /// Режим проверки запросов без отправки (см. [`GPTClient::with_dry_run`]).
+    dry_run: bool,
+    /// Дополнительные заголовки запросов (см. [`GPTClient::with_header`]).
+    headers: HeaderMap,
 }
 
+/// Заголовок, которым запрос отказывается от журналирования в Yandex Cloud
+/// (см. [`GPTClient::with_data_logging`]).
+const DATA_LOGGING_HEADER: &str = "x-data-logging-enabled";
+
+/// `User-Agent` запросов по умолчанию (см. [`GPTClient::with_user_agent`]).
+const DEFAULT_USER_AGENT: &str = "YM001";
+
+/// Пауза перед первым повтором запроса; каждая следующая вдвое длиннее.
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(200);
+
+/// Наибольшая пауза из `Retry-After`, которую клиент выжидает сам. При более долгой
+/// ошибка возвращается вызывающей стороне.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(30);
+
 impl Default for GPTClient {
     fn default() -> Self {
         Self {
             access: AccessData::default(),
             api_url: URL_API.to_string(),
+            embedding_url: URL_EMBEDDINGS.to_string(),
+            tokenize_url: URL_TOKENIZE.to_string(),
             gpt_options: GPTOptions::default(),
+            transport: Arc::new(HttpTransport::new()),
+            timeout: None,
+            proxy: None,
+            max_attempts: 1,
+            dry_run: false,
+            headers: HeaderMap::new(),
         }
     }
 }
@@ -47,57 +92,387 @@ impl GPTClient {
         Self::default()
     }
 
+    /// Создать клиент с данными доступа `access` и параметрами `config`.
+    ///
+    /// Параметры проверяются [`ClientConfig::validate`], ошибка — [`GPTError::ConfigError`].
+    /// Остальные настройки клиента получают значения по умолчанию (см. [`GPTClient::config`]).
+    pub fn from_config(access: AccessData, config: ClientConfig) -> Result<Self> {
+        config.validate()?;
+        let mut client = Self::new().with_access(access);
+        client.gpt_options.model = config.model;
+        client.gpt_options.temperature = config.temperature;
+        client.gpt_options.max_tokens = config.max_tokens;
+        client.gpt_options.system_prompt = config
+            .system_prompt
+            .filter(|prompt| !prompt.trim().is_empty());
+        client.max_attempts = config.retry.max(1);
+        if let Some(timeout) = config.timeout {
+            client.set_timeout(timeout)?;
+        }
+        Ok(client)
+    }
+
+    /// Параметры клиента, входящие в [`ClientConfig`]: модель, температура, лимит токенов,
+    /// основная системная инструкция, таймаут и число попыток. Их можно изменить и собрать
+    /// новый клиент через [`GPTClient::from_config`].
+    ///
+    /// Прочие настройки в [`ClientConfig`] не входят и при такой пересборке сбрасываются:
+    /// URI модели, отдельный лимит для чата, дополнительные системные инструкции, зерно,
+    /// число вариантов, формат JSON, лимит истории, адреса API, прокси, заголовки и транспорт.
+    /// Чтобы сохранить их, клонируйте клиент и меняйте его построителями `with_*`.
+    pub fn config(&self) -> ClientConfig {
+        ClientConfig {
+            model: self.gpt_options.model.clone(),
+            temperature: self.gpt_options.temperature,
+            max_tokens: self.gpt_options.max_tokens,
+            system_prompt: self.gpt_options.system_prompt.clone(),
+            timeout: self.timeout,
+            retry: self.max_attempts,
+        }
+    }
+
     /// Установить данные авторизации.
     pub fn set_auth(mut self, id_catalog: String, api_key: String) -> Self {
         self.access = AccessData::new(id_catalog, api_key);
         self
     }
 
+    /// Установить заранее подготовленные данные авторизации.
+    pub fn with_access(mut self, access: AccessData) -> Self {
+        self.access = access;
+        self
+    }
+
+    /// Авторизоваться IAM-токеном (`Authorization: Bearer ...`) вместо API-ключа.
+    ///
+    /// IAM-токен действует около 12 часов. Клиент его не обновляет: получить новый токен
+    /// и снова вызвать этот метод — задача вызывающей стороны.
+    pub fn with_iam_token(mut self, token: &str) -> Self {
+        self.access.iam_token = Some(token.to_string());
+        self
+    }
+
     /// Загрузить данные авторизации из файла.
-    pub fn load_auth(mut self, access_file: PathBuf) -> Self {
-        self.access = AccessData::load_it(access_file);
+    ///
+    /// Ошибка чтения или разбора файла возвращается вызывающей стороне (см.
+    /// [`AccessData::load_it`]).
+    pub fn load_auth(mut self, access_file: PathBuf) -> Result<Self> {
+        self.access = AccessData::load_it(access_file)?;
+        Ok(self)
+    }
+
+    /// Ограничить время выполнения запроса (от подключения до получения ответа целиком).
+    ///
+    /// Транспорт заменяется новым [`HttpTransport`], поэтому открытые соединения не
+    /// переиспользуются, а транспорт, заданный через [`GPTClient::with_transport`], сбрасывается.
+    /// Прокси из [`GPTClient::with_proxy`] сохраняется. Если новый HTTP-клиент собрать
+    /// не удалось, возвращается [`GPTError::ConfigError`].
+    pub fn with_timeout(mut self, timeout: Duration) -> Result<Self> {
+        self.set_timeout(timeout)?;
+        Ok(self)
+    }
+
+    /// Задать таймаут и пересобрать транспорт (см. [`GPTClient::with_timeout`]).
+    ///
+    /// Таймаут запоминается, только если транспорт с ним и с текущим прокси собран: прокси
+    /// не отбрасывается ради таймаута.
+    fn set_timeout(&mut self, timeout: Duration) -> Result<()> {
+        let transport = HttpTransport::with_options(Some(timeout), self.proxy.as_deref())?;
+        self.timeout = Some(timeout);
+        self.transport = Arc::new(transport);
+        Ok(())
+    }
+
+    /// Отправлять запросы через прокси `url`: HTTP (`http://proxy.local:3128`) или SOCKS5
+    /// (`socks5://127.0.0.1:1080`, `socks5h://...` — с разрешением имён на стороне прокси).
+    ///
+    /// Без этого вызова прокси берётся из переменных окружения `HTTPS_PROXY`, `HTTP_PROXY`
+    /// и `ALL_PROXY` (с учётом `NO_PROXY`), как в [`HttpTransport::with_options`].
+    /// Как и [`GPTClient::with_timeout`], пересобирает транспорт; таймаут сохраняется.
+    /// Некорректный адрес — [`GPTError::ConfigError`].
+    pub fn with_proxy(mut self, url: &str) -> Result<Self> {
+        let transport = HttpTransport::with_options(self.timeout, Some(url))?;
+        self.proxy = Some(url.to_string());
+        self.transport = Arc::new(transport);
+        Ok(self)
+    }
+
+    /// Отправлять запросы через `transport` вместо HTTP-клиента по умолчанию.
+    ///
+    /// Нужен прежде всего в тестах: подделка транспорта возвращает заготовленные ответы
+    /// без сети, а повторы, разбор ответа и ошибок работают как обычно.
+    pub fn with_transport(mut self, transport: impl Transport + 'static) -> Self {
+        self.transport = Arc::new(transport);
+        self
+    }
+
+    /// Повторять запрос при временных сбоях (см. [`GPTError::is_retryable`]): ответах 5xx,
+    /// превышении лимита запросов (429) и ошибках соединения.
+    ///
+    /// `max_attempts` — общее число попыток, включая первую; по умолчанию `1` (без повторов).
+    /// Паузы растут экспоненциально (200 мс, 400 мс, 800 мс, ...) со случайной добавкой,
+    /// чтобы клиенты не повторяли запросы синхронно. После ответа 429 выдерживается пауза
+    /// из `Retry-After`; если она дольше 30 с, ошибка возвращается сразу. Ошибки авторизации
+    /// и прочие ответы 4xx возвращаются сразу.
+    ///
+    /// **Паника**: при `max_attempts == 0`.
+    pub fn with_retry(mut self, max_attempts: u32) -> Self {
+        assert!(max_attempts > 0, "Число попыток должно быть не меньше 1");
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// Не отправлять запросы к модели, а возвращать вместо ответа их тело: JSON с отступами.
+    ///
+    /// Позволяет проверить модель, системные инструкции, роли и параметры генерации, не
+    /// расходуя квоту. Заголовки запроса, в том числе `Authorization`, в вывод не попадают.
+    /// Действует на запросы генерации (`ask_gpt*`, `chat_with_gpt*`);
+    /// данные доступа по-прежнему нужны, так как из них берётся каталог для URI модели.
+    pub fn with_dry_run(mut self, enabled: bool) -> Self {
+        self.dry_run = enabled;
+        self
+    }
+
+    /// Передавать заголовок `User-Agent: user_agent` вместо `YM001`.
+    ///
+    /// Ошибки те же, что у [`GPTClient::with_header`].
+    pub fn with_user_agent(self, user_agent: &str) -> Result<Self> {
+        self.with_header(USER_AGENT.as_str(), user_agent)
+    }
+
+    /// Добавлять ко всем запросам заголовок `name: value` (например,
+    /// `x-data-logging-enabled: false`).
+    ///
+    /// Повторный вызов с тем же именем заменяет значение. Заголовок заменяет одноимённый
+    /// заголовок клиента (`User-Agent`, `Content-Type`, `x-folder-id`), кроме `Authorization`:
+    /// авторизацию задают только данные доступа. Для `Authorization`, а также для
+    /// некорректных имени или значения возвращается `GPTError::ConfigError`.
+    pub fn with_header(mut self, name: &str, value: &str) -> Result<Self> {
+        let header =
+            HeaderName::from_bytes(name.as_bytes()).map_err(|err| GPTError::ConfigError {
+                description: format!("некорректное имя заголовка «{}»: {}", name, err),
+            })?;
+        if header == AUTHORIZATION {
+            return Err(GPTError::ConfigError {
+                description: "заголовок Authorization задаётся данными доступа".to_string(),
+            });
+        }
+        let value = HeaderValue::from_str(value).map_err(|err| GPTError::ConfigError {
+            description: format!("некорректное значение заголовка {}: {}", header, err),
+        })?;
+
+        self.headers.insert(header, value);
+        Ok(self)
+    }
+
+    /// Разрешить или запретить Yandex Cloud журналировать запросы и ответы.
+    ///
+    /// По умолчанию (и при `enabled = true`) заголовок не передаётся: действует политика
+    /// Yandex Cloud, при которой тексты запросов и ответов могут сохраняться на стороне
+    /// сервиса. При `enabled = false` каждый запрос получает заголовок
+    /// `x-data-logging-enabled: false`, и тексты не журналируются.
+    pub fn with_data_logging(mut self, enabled: bool) -> Self {
+        if enabled {
+            self.headers.remove(DATA_LOGGING_HEADER);
+        } else {
+            self.headers.insert(
+                HeaderName::from_static(DATA_LOGGING_HEADER),
+                HeaderValue::from_static("false"),
+            );
+        }
+        self
+    }
+
+    /// Просить модель отвечать в JSON.
+    ///
+    /// Без схемы (`None`) ответ — произвольный JSON-объект (`json_object`), со схемой —
+    /// JSON, соответствующий переданной JSON Schema (`json_schema`). Разобрать ответ
+    /// в свой тип можно через [`GPTClient::ask_gpt_typed`].
+    pub fn with_json_output(mut self, schema: Option<serde_json::Value>) -> Self {
+        self.gpt_options.json_output = Some(match schema {
+            Some(schema) => JsonOutput::Schema(schema),
+            None => JsonOutput::Object,
+        });
         self
     }
 
-    /// Изменить URL API.
+    /// Задать общий адрес API: адреса генерации, эмбеддингов и токенизатора строятся от него.
+    ///
+    /// Подходит для другого региона или собственного шлюза, например
+    /// `https://gateway.example.com/yandex` даст
+    /// `https://gateway.example.com/yandex/foundationModels/v1/completion`. Адрес должен быть
+    /// абсолютным `http`- или `https`-адресом, иначе — [`GPTError::ConfigError`].
+    pub fn with_base_url(mut self, base: &str) -> Result<Self> {
+        let url = reqwest::Url::parse(base).map_err(|err| GPTError::ConfigError {
+            description: format!("некорректный адрес API «{}»: {}", base, err),
+        })?;
+        if !matches!(url.scheme(), "http" | "https") || url.host().is_none() {
+            return Err(GPTError::ConfigError {
+                description: format!(
+                    "адрес API «{}» должен начинаться с http:// или https://",
+                    base
+                ),
+            });
+        }
+
+        let base = url.as_str().trim_end_matches('/');
+        self.api_url = format!("{}{}", base, PATH_COMPLETION);
+        self.embedding_url = format!("{}{}", base, PATH_EMBEDDINGS);
+        self.tokenize_url = format!("{}{}", base, PATH_TOKENIZE);
+        Ok(self)
+    }
+
+    /// Изменить URL API генерации. Адреса эмбеддингов и токенизатора не меняются
+    /// (см. [`GPTClient::with_base_url`]).
     pub fn with_new_url(mut self, api_url: String) -> Self {
         self.api_url = api_url;
         self
     }
 
+    /// Изменить URL API эмбеддингов.
+    pub fn with_embedding_url(mut self, embedding_url: String) -> Self {
+        self.embedding_url = embedding_url;
+        self
+    }
+
+    /// Изменить URL API токенизатора.
+    pub fn with_tokenize_url(mut self, tokenize_url: String) -> Self {
+        self.tokenize_url = tokenize_url;
+        self
+    }
+
     /// Изменить модель.
     pub fn with_model(mut self, model: &str) -> Self {
         self.gpt_options.model = model.to_string();
         self
     }
 
+    /// Задать полный URI модели вместо шаблона `gpt://{каталог}/{model}`.
+    ///
+    /// Нужен для закреплённых версий (`gpt://b1g.../yandexgpt/rc`) и дообученных моделей
+    /// (`ds://...`). Имеет приоритет над [`GPTClient::with_model`]: имя модели после этого
+    /// используется только для оценки лимита контекста.
+    pub fn with_model_uri(mut self, uri: &str) -> Self {
+        self.gpt_options.model_uri = Some(uri.to_string());
+        self
+    }
+
     /// Изменить температуру.
+    ///
+    /// Допустимы значения от `0.0` до `1.0` включительно. Значение `0.0` отправляется в API как
+    /// есть и делает ответы максимально детерминированными (см. [`GPTClient::deterministic`]).
+    ///
+    /// **Паника**: при значении вне диапазона `0.0..=1.0`.
     pub fn with_temperature(mut self, temperature: f32) -> Self {
-        if !(0.0..=1.0).contains(&temperature) {
-            panic!(
-                "Температура должна быть между 0 и 1, получено: {}",
-                temperature
-            );
+        if let Err(err) = validate_temperature(temperature) {
+            panic!("{}", err);
         }
         self.gpt_options.temperature = temperature;
         self
     }
 
+    /// Включить детерминированный режим: температура `0.0`.
+    ///
+    /// Одинаковые запросы в этом режиме дают (почти) одинаковые ответы, что полезно для тестов
+    /// и воспроизводимых результатов.
+    pub fn deterministic(mut self) -> Self {
+        self.gpt_options.temperature = GPTOptions::deterministic().temperature;
+        self
+    }
+
+    /// Передавать в запросе зерно генерации `seed`.
+    ///
+    /// Вместе с [`GPTClient::deterministic`] повторяет ответ на одинаковый запрос, что нужно
+    /// для тестов и кэширования. Ограничения: параметр уходит в `completion_options.seed`, но
+    /// не описан в документации YandexGPT API, поэтому воспроизводимость не гарантирована;
+    /// сервер, не знающий параметра, игнорирует его без ошибки.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.gpt_options.seed = Some(seed);
+        self
+    }
+
+    /// Запрашивать `count` вариантов ответа (по умолчанию один).
+    ///
+    /// Параметр уходит в `completion_options.num_alternatives`. Все варианты возвращают
+    /// [`GPTClient::ask_gpt_all`] и [`GPTClient::chat_with_gpt_all`], остальные методы берут
+    /// первый. Ограничения: как и `seed` (см. [`GPTClient::with_seed`]), параметр не описан
+    /// в документации YandexGPT API. Сервер, не знающий его, отвечает одним вариантом без
+    /// ошибки, поэтому число полученных вариантов стоит сверять с запрошенным.
+    ///
+    /// **Паника**: при `count == 0`.
+    pub fn with_alternatives(mut self, count: u32) -> Self {
+        if count == 0 {
+            panic!("Количество вариантов ответа должно быть больше 0");
+        }
+        self.gpt_options.alternatives = count;
+        self
+    }
+
     /// Изменить максимальное количество токенов.
+    ///
+    /// По умолчанию лимит един для одиночных запросов (`ask_gpt`) и чата (`chat_with_gpt`).
+    /// Для чата его можно переопределить через [`GPTClient::with_chat_max_tokens`].
+    ///
+    /// Лимит проверяется при сборке запроса против модели, заданной к этому моменту: значение
+    /// не больше нуля или больше лимита контекста модели (см. [`model_token_limit`]) приводит
+    /// к `GPTError::ConfigError` без обращения к API. Порядок вызова `with_model` и
+    /// `with_max_tokens` поэтому не важен.
     pub fn with_max_tokens(mut self, max_tokens: i64) -> Self {
-        if max_tokens <= 0 {
-            panic!("Количество токенов должно быть больше 0");
-        }
         self.gpt_options.max_tokens = max_tokens;
         self
     }
 
-    /// Сформировать URI модели, по шаблону: gpt://{id_catalog}/{model_name}.
+    /// Задать отдельный лимит токенов ответа для режима чата (`chat_with_gpt`).
+    ///
+    /// Одиночные запросы по-прежнему используют общий `max_tokens`.
+    ///
+    /// Проверяется при сборке запроса так же, как [`GPTClient::with_max_tokens`].
+    pub fn with_chat_max_tokens(mut self, max_tokens: i64) -> Self {
+        self.gpt_options.chat_max_tokens = Some(max_tokens);
+        self
+    }
+
+    /// Ограничить число последних обменов диалога, отправляемых в режиме чата
+    /// (`0` — без ограничения, по умолчанию `10`).
+    ///
+    /// Обмен — сообщение пользователя и ответы на него; отправляемая часть всегда начинается
+    /// с сообщения пользователя (см. [`recent_exchanges`]). Более ранние сообщения остаются
+    /// у вызывающего кода, но модели не отправляются. Системные инструкции отправляются всегда.
+    pub fn with_max_history_exchanges(mut self, count: usize) -> Self {
+        self.gpt_options.max_history_exchanges = count;
+        self
+    }
+
+    /// Задать основную системную инструкцию (персону) для `ask_gpt` и `chat_with_gpt`.
+    ///
+    /// В отличие от [`GPTClient::add_system_prompt`], заменяет прежнюю персону, а не
+    /// накапливается; отправляется первой. Пустая строка сбрасывает персону.
+    pub fn with_system_prompt(mut self, prompt: &str) -> Self {
+        self.gpt_options.system_prompt = (!prompt.trim().is_empty()).then(|| prompt.to_string());
+        self
+    }
+
+    /// Добавить системную инструкцию (персона, формат ответа, ограничения и т.п.).
+    ///
+    /// Инструкции накапливаются и отправляются сообщениями с ролью `system` в начале каждого
+    /// запроса — строго в порядке добавления. Пустые строки игнорируются.
+    pub fn add_system_prompt(mut self, prompt: &str) -> Self {
+        if !prompt.trim().is_empty() {
+            self.gpt_options.system_prompts.push(prompt.to_string());
+        }
+        self
+    }
+
+    /// Сформировать URI модели, по шаблону: gpt://{folder}/{model_name}.
+    ///
+    /// Каталог — `folder_id` из данных доступа, а если он не задан — `id_catalog`.
+    ///
+    /// URI, заданный через [`GPTClient::with_model_uri`], возвращается без изменений.
     fn model_uri(&self) -> String {
-        format!(
-            "gpt://{}/{}",
-            self.access.id_catalog, self.gpt_options.model
-        )
+        match &self.gpt_options.model_uri {
+            Some(uri) => uri.clone(),
+            None => format!("gpt://{}/{}", self.access.folder(), self.gpt_options.model),
+        }
     }
 
     /// Сделать запрос к языковой модели.
@@ -108,119 +483,1688 @@ impl GPTClient {
     /// **Пример**
     ///
     /// ```rust,no_run
+    /// # use ym_yagpt::GPTClient;
+    /// # async fn example(client: GPTClient) -> ym_yagpt::Result<()> {
     /// let result = client.ask_gpt("Привет, как ты?").await?;
+    /// # Ok(())
+    /// # }
     /// ```
-    pub async fn ask_gpt(&self, prompt: &str) -> Result<String, Box<dyn Error>> {
+    pub async fn ask_gpt(&self, prompt: &str) -> Result<String> {
+        Ok(self.ask_gpt_full(prompt).await?.text)
+    }
+
+    /// Задать вопрос и разобрать ответ как JSON в тип `T`.
+    ///
+    /// Имеет смысл вместе с [`GPTClient::with_json_output`]. Обрамление ответа блоком
+    /// кода Markdown (`` ```json ``) отбрасывается; ответ, который не разбирается в `T`, —
+    /// [`GPTError::ParseError`].
+    pub async fn ask_gpt_typed<T: DeserializeOwned>(&self, prompt: &str) -> Result<T> {
+        let answer = self.ask_gpt(prompt).await?;
+        Ok(serde_json::from_str(strip_code_fence(&answer))?)
+    }
+
+    /// Задать вопрос и получить ответ со статусом завершения и расходом токенов.
+    pub async fn ask_gpt_full(&self, prompt: &str) -> Result<GPTAnswer> {
+        if !self.access.has_data() {
+            return Err(GPTError::InvalidCredential);
+        }
+
+        let request_data = self.build_ask_request(prompt)?;
+        let response = self.send_request(&request_data).await?;
+        self.extract_detailed(response).await
+    }
+
+    /// Задать вопрос и получать ответ по частям, по мере генерации.
+    ///
+    /// Элементы потока — новые фрагменты текста ([`StreamEvent::Delta`], а не весь
+    /// накопленный ответ), последним приходит [`StreamEvent::Done`] с полным ответом,
+    /// статусом завершения и расходом токенов. Ошибка сети или разбора отдаётся последним
+    /// элементом; соединение, закрытое до финального статуса, — [`GPTError::Network`].
+    pub async fn ask_gpt_stream(
+        &self,
+        prompt: &str,
+    ) -> Result<impl Stream<Item = Result<StreamEvent>> + use<>> {
+        if !self.access.has_data() {
+            return Err(GPTError::InvalidCredential);
+        }
+
+        self.stream_deltas(self.build_ask_request(prompt)?).await
+    }
+
+    /// Общение модели с историей сообщений с получением ответа по частям.
+    ///
+    /// Поток устроен так же, как у [`GPTClient::ask_gpt_stream`].
+    pub async fn chat_with_gpt_stream(
+        &self,
+        messages: &[ChatMessage],
+    ) -> Result<impl Stream<Item = Result<StreamEvent>> + use<>> {
+        if !self.access.has_data() {
+            return Err(GPTError::InvalidCredential);
+        }
+
+        self.stream_deltas(self.build_chat_request(messages)?).await
+    }
+
+    /// Отправить запрос `request_data` в потоковом режиме и отдавать события ответа.
+    async fn stream_deltas(
+        &self,
+        mut request_data: serde_json::Value,
+    ) -> Result<impl Stream<Item = Result<StreamEvent>> + use<>> {
+        request_data["completion_options"]["stream"] = json!(true);
+        let response = self.send_request(&request_data).await?;
+
+        let state = (response.body, StreamDecoder::default(), VecDeque::new());
+        let deltas =
+            futures::stream::unfold(state, |(mut bytes, mut decoder, mut pending)| async move {
+                loop {
+                    if let Some(delta) = pending.pop_front() {
+                        return Some((delta, (bytes, decoder, pending)));
+                    }
+                    if decoder.is_finished() {
+                        return None;
+                    }
+                    match bytes.next().await {
+                        Some(Ok(chunk)) => pending.extend(decoder.feed(&chunk)),
+                        Some(Err(err)) => pending.extend(decoder.finish_with(err)),
+                        None => pending.extend(decoder.finish()),
+                    }
+                }
+            });
+
+        Ok(deltas)
+    }
+
+    /// Сделать запрос к языковой модели и получить все варианты ответа
+    /// (см. [`GPTClient::with_alternatives`]) в порядке, присланном API.
+    ///
+    /// Варианты с пустым текстом пропускаются. Если текста нет ни в одном,
+    /// возвращается `GPTError::EmptyResponse` или `GPTError::ContentFiltered`.
+    pub async fn ask_gpt_all(&self, prompt: &str) -> Result<Vec<GPTAnswer>> {
         if !self.access.has_data() {
-            return Err(Box::new(GPTError::InvalidCredential));
+            return Err(GPTError::InvalidCredential);
         }
 
-        let request_data = self.build_ask_request(prompt);
+        let request_data = self.build_ask_request(prompt)?;
         let response = self.send_request(&request_data).await?;
-        let answer = self.extract_answer(response).await?;
+        self.extract_all(response).await
+    }
+
+    /// Получить векторное представление текста (эмбеддинг) для семантического поиска.
+    ///
+    /// Используется модель поисковых запросов `emb://{каталог}/text-search-query/latest`
+    /// и те же данные авторизации, что и для генерации.
+    pub async fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        if !self.access.has_data() {
+            return Err(GPTError::InvalidCredential);
+        }
+
+        let request = EmbeddingRequest {
+            model_uri: format!("emb://{}/{}", self.access.folder(), EMBEDDING_MODEL_QUERY),
+            text: text.to_string(),
+        };
+        let response = self.send_to(&self.embedding_url, &json!(request)).await?;
+        let parsed: EmbeddingResponse = response.json().await?;
+
+        if parsed.embedding.is_empty() {
+            return Err(GPTError::EmptyResponse);
+        }
+        Ok(parsed.embedding)
+    }
+
+    /// Подсчитать токены текста токенизатором текущей модели.
+    ///
+    /// Точнее [`estimate_tokens`] и не расходует квоту генерации, но требует запроса к API.
+    /// В режиме [`GPTClient::with_dry_run`] текст не отправляется: возвращается
+    /// [`GPTError::ConfigError`].
+    pub async fn count_tokens(&self, text: &str) -> Result<u32> {
+        if !self.access.has_data() {
+            return Err(GPTError::InvalidCredential);
+        }
+        if self.dry_run {
+            return Err(GPTError::ConfigError {
+                description: "в режиме проверки запросов текст на подсчёт токенов не отправляется"
+                    .to_string(),
+            });
+        }
+
+        let request = TokenizeRequest {
+            model_uri: self.model_uri(),
+            text: text.to_string(),
+        };
+        let response = self.send_to(&self.tokenize_url, &json!(request)).await?;
+        let parsed: TokenizeResponse = response.json().await?;
+        Ok(parsed.tokens.len() as u32)
+    }
 
-        Ok(answer)
+    /// Получить эмбеддинги нескольких текстов: по запросу на каждый, в том же порядке.
+    ///
+    /// Первая же ошибка прерывает обработку.
+    pub async fn embed_batch(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+        let mut embeddings = Vec::with_capacity(texts.len());
+        for text in texts {
+            embeddings.push(self.embed(text).await?);
+        }
+        Ok(embeddings)
+    }
+
+    /// Проверить доступность API, данные авторизации и модель минимальным запросом.
+    ///
+    /// Запрос уходит токенизатору (см. [`GPTClient::count_tokens`]), а не модели, поэтому
+    /// квоту генерации не расходует. Ошибки авторизации и сети те же, что у
+    /// [`GPTClient::ask_gpt`]; в режиме [`GPTClient::with_dry_run`] запрос не отправляется.
+    pub async fn ping(&self) -> Result<()> {
+        self.count_tokens("ping").await.map(|_| ())
+    }
+
+    /// Модели, которые можно передать в [`GPTClient::with_model`].
+    ///
+    /// API Yandex Cloud не отдаёт список моделей, поэтому возвращается перечень известных
+    /// (см. [`known_models`]). Текущая модель клиента, если её нет в перечне, идёт последней.
+    pub fn list_models(&self) -> Vec<ModelInfo> {
+        let mut models = known_models();
+        let current = &self.gpt_options.model;
+        if !models.iter().any(|model| &model.name == current) {
+            models.push(ModelInfo::new(current, "текущая модель клиента"));
+        }
+        models
     }
 
     /// Собрать запрос к API.
-    fn build_ask_request(&self, prompt: &str) -> serde_json::Value {
+    fn build_ask_request(&self, prompt: &str) -> Result<serde_json::Value> {
+        let max_tokens = self.gpt_options.max_tokens;
+        validate_max_tokens(&self.gpt_options.model, max_tokens)?;
+
         let message = vec![ChatMessage {
             role: "user".to_string(),
             text: prompt.to_string(),
         }];
 
-        self.build_request(message)
+        Ok(self.build_request(message, max_tokens))
     }
 
     /// Отправить HTTP-запрос.
-    async fn send_request(
-        &self,
-        body: &serde_json::Value,
-    ) -> Result<reqwest::Response, Box<dyn Error>> {
-        let client = Client::new();
-
-        let response = client
-            .post(&self.api_url)
-            .header("Authorization", format!("Api-Key {}", self.access.api_key))
-            .header("Content-Type", "application/json")
-            .header("User-Agent", "YM001")
-            .json(body)
-            .send()
-            .await?;
-
-        if !response.status().is_success() {
-            let status = response.status();
+    ///
+    /// Каждый запрос получает уникальный заголовок `x-request-id` (UUID v4). Он же попадает
+    /// в описание ошибки, чтобы по нему можно было найти запрос в поддержке Yandex Cloud.
+    ///
+    /// Временные сбои повторяются согласно [`GPTClient::with_retry`]; каждая попытка получает
+    /// свой `x-request-id`.
+    ///
+    /// В режиме [`GPTClient::with_dry_run`] запрос не отправляется (см. [`dry_run_response`]).
+    async fn send_request(&self, body: &serde_json::Value) -> Result<TransportResponse> {
+        if self.dry_run {
+            return dry_run_response(body);
+        }
+        self.send_to(&self.api_url, body).await
+    }
+
+    /// Отправить HTTP-запрос на указанный адрес (см. [`GPTClient::send_request`]).
+    async fn send_to(&self, url: &str, body: &serde_json::Value) -> Result<TransportResponse> {
+        let mut attempt = 1;
+        loop {
+            match self.send_once(url, body).await {
+                Err(err) if attempt < self.max_attempts && err.is_retryable() => {
+                    let delay = match err {
+                        GPTError::RateLimited {
+                            retry_after: Some(delay),
+                        } if delay > MAX_RETRY_AFTER => return Err(err),
+                        GPTError::RateLimited {
+                            retry_after: Some(delay),
+                        } => delay,
+                        _ => retry_delay(attempt),
+                    };
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Одна попытка отправки запроса.
+    async fn send_once(&self, url: &str, body: &serde_json::Value) -> Result<TransportResponse> {
+        let request_id = Uuid::new_v4().to_string();
+
+        let mut headers = vec![
+            ("Authorization", self.access.auth_method().header_value()),
+            ("Content-Type", "application/json".to_string()),
+            ("User-Agent", DEFAULT_USER_AGENT.to_string()),
+            ("x-request-id", request_id.clone()),
+        ];
+        if let Some(folder_id) = &self.access.folder_id {
+            headers.push(("x-folder-id", folder_id.clone()));
+        }
+        // Заголовки пользователя заменяют одноимённые; `Authorization` среди них не бывает.
+        for (name, value) in &self.headers {
+            headers.retain(|(existing, _)| !existing.eq_ignore_ascii_case(name.as_str()));
+            let value = String::from_utf8_lossy(value.as_bytes()).into_owned();
+            headers.push((name.as_str(), value));
+        }
+
+        let response = self
+            .transport
+            .post_json(url, &headers, body)
+            .await
+            .map_err(|err| with_request_id(err, &request_id))?;
+
+        if response.status == 429 {
+            let retry_after = response.header("retry-after").and_then(parse_retry_after);
+            return Err(GPTError::RateLimited { retry_after });
+        }
+        if !response.is_success() {
+            let status = response.status;
             let error_text = response.text().await.unwrap_or_default();
-            let code = status.as_u16() as i32;
+            let code = i32::from(status);
 
-            let err = if status == reqwest::StatusCode::UNAUTHORIZED {
+            let err = if status == 401 {
                 GPTError::InvalidCredential
             } else {
-                GPTError::APIError {
-                    code,
-                    description: error_text,
-                }
+                api_error(code, &error_text)
             };
 
-            return Err(Box::new(err));
+            return Err(with_request_id(err, &request_id));
         }
 
         Ok(response)
     }
 
-    /// Извлечь ответ из JSON.
-    async fn extract_answer(&self, response: reqwest::Response) -> Result<String, Box<dyn Error>> {
+    /// Извлечь ответ вместе со статусом завершения генерации.
+    async fn extract_detailed(&self, response: TransportResponse) -> Result<GPTAnswer> {
+        Ok(self.extract_all(response).await?.remove(0))
+    }
+
+    /// Извлечь все варианты ответа с текстом. Варианты с пустым текстом пропускаются;
+    /// если не осталось ни одного, возвращается ошибка первого из них.
+    async fn extract_all(&self, response: TransportResponse) -> Result<Vec<GPTAnswer>> {
         let parsed: ApiResponse = response.json().await?;
+        let usage = parsed.result.usage;
 
-        parsed
-            .result
-            .alternatives
-            .into_iter()
-            .next()
-            .map(|alt| alt.message.text)
-            .ok_or_else(|| Box::new(GPTError::EmptyResponse) as Box<dyn Error>)
+        let mut answers = Vec::new();
+        let mut first_error = None;
+        for alternative in parsed.result.alternatives {
+            match alternative.non_empty() {
+                Ok(alternative) => answers.push(GPTAnswer::from(alternative).with_usage(usage)),
+                Err(err) => {
+                    first_error.get_or_insert(err);
+                }
+            }
+        }
+        if answers.is_empty() {
+            return Err(first_error.unwrap_or(GPTError::EmptyResponse));
+        }
+        Ok(answers)
     }
 
     /// Общение модели с историей сообщений.
-    pub async fn chat_with_gpt(&self, messages: &[String]) -> Result<String, Box<dyn Error>> {
-        let request_data = self.build_chat_request(messages);
+    ///
+    /// Роли сообщений задаёт вызывающая сторона; системные инструкции из настроек
+    /// добавляются в начало автоматически.
+    pub async fn chat_with_gpt(&self, messages: &[ChatMessage]) -> Result<String> {
+        Ok(self.chat_with_gpt_detailed(messages).await?.text)
+    }
+
+    /// Общение модели с историей сообщений с признаком обрезанного по лимиту ответа.
+    ///
+    /// Обрезанный ответ можно продолжить, отправив его в истории с просьбой продолжить.
+    pub async fn chat_with_gpt_detailed(&self, messages: &[ChatMessage]) -> Result<GPTAnswer> {
+        let request_data = self.build_chat_request(messages)?;
         let response = self.send_request(&request_data).await?;
-        let answer = self.extract_answer(response).await?;
+        self.extract_detailed(response).await
+    }
 
-        Ok(answer)
+    /// Общение модели с историей сообщений: все варианты ответа
+    /// (см. [`GPTClient::with_alternatives`]).
+    pub async fn chat_with_gpt_all(&self, messages: &[ChatMessage]) -> Result<Vec<GPTAnswer>> {
+        let request_data = self.build_chat_request(messages)?;
+        let response = self.send_request(&request_data).await?;
+        self.extract_all(response).await
     }
 
     /// Формирование тела запроса с историей сообщений.
-    fn build_chat_request(&self, messages: &[String]) -> serde_json::Value {
-        let role = ["assistant", "user"];
+    fn build_chat_request(&self, messages: &[ChatMessage]) -> Result<serde_json::Value> {
+        let max_tokens = self
+            .gpt_options
+            .chat_max_tokens
+            .unwrap_or(self.gpt_options.max_tokens);
+        validate_max_tokens(&self.gpt_options.model, max_tokens)?;
 
-        let msg_pack: Vec<ChatMessage> = messages
-            .iter()
-            // .rev()
-            .enumerate()
-            .map(|(i, m)| ChatMessage {
-                role: role[i % 2].to_string(),
-                text: m.clone(),
-            })
-            .collect();
+        let recent = recent_exchanges(messages, self.gpt_options.max_history_exchanges);
+        let msg_pack = self.fit_context(recent.to_vec(), max_tokens);
+        Ok(self.build_request(msg_pack, max_tokens))
+    }
+
+    /// Отбросить старейшие сообщения диалога, если вместе с системными инструкциями и ответом
+    /// они не помещаются в лимит контекста модели (по оценке [`estimate_tokens`]).
+    ///
+    /// Последнее сообщение сохраняется всегда, а оставшийся диалог начинается с сообщения
+    /// пользователя, как и у [`recent_exchanges`]. Для моделей без известного лимита диалог
+    /// не изменяется.
+    fn fit_context(&self, mut dialog: Vec<ChatMessage>, max_tokens: i64) -> Vec<ChatMessage> {
+        let Some(limit) = model_token_limit(&self.gpt_options.model) else {
+            return dialog;
+        };
 
-        self.build_request(msg_pack)
+        let budget = (limit - max_tokens).max(0) as usize;
+        let system: usize = self
+            .gpt_options
+            .system_messages()
+            .map(|prompt| estimate_tokens(prompt))
+            .sum();
+        let mut total = system
+            + dialog
+                .iter()
+                .map(|m| estimate_tokens(&m.text))
+                .sum::<usize>();
+
+        let mut drop = 0;
+        while total > budget && drop + 1 < dialog.len() {
+            total -= estimate_tokens(&dialog[drop].text);
+            drop += 1;
+        }
+        while drop > 0 && drop + 1 < dialog.len() && dialog[drop].role != "user" {
+            drop += 1;
+        }
+        dialog.drain(..drop);
+        dialog
     }
 
     /// Единый компоновщик тела запроса к языковой модели.
-    fn build_request(&self, messages: Vec<ChatMessage>) -> serde_json::Value {
+    fn build_request(&self, dialog: Vec<ChatMessage>, max_tokens: i64) -> serde_json::Value {
+        let messages: Vec<ChatMessage> = self
+            .gpt_options
+            .system_messages()
+            .map(|prompt| ChatMessage {
+                role: "system".to_string(),
+                text: prompt.clone(),
+            })
+            .chain(dialog)
+            .collect();
+
         let completion_options = CompletionOptions {
             stream: false,
             temperature: self.gpt_options.temperature,
-            max_tokens: self.gpt_options.max_tokens,
+            max_tokens,
+            seed: self.gpt_options.seed,
+            num_alternatives: self.gpt_options.alternatives,
+        };
+
+        let (json_object, json_schema) = match &self.gpt_options.json_output {
+            None => (None, None),
+            Some(JsonOutput::Object) => (Some(true), None),
+            Some(JsonOutput::Schema(schema)) => (
+                None,
+                Some(JsonSchema {
+                    schema: schema.clone(),
+                }),
+            ),
         };
 
         let api_req = ApiRequest {
             model_uri: self.model_uri(),
             completion_options,
             messages,
+            json_object,
+            json_schema,
         };
 
         json!(api_req)
     }
 }
+
+/// Убрать обрамление ответа блоком кода Markdown, если оно есть.
+fn strip_code_fence(text: &str) -> &str {
+    let text = text.trim();
+    let Some(body) = text.strip_prefix("```") else {
+        return text;
+    };
+    let body = body.strip_suffix("```").unwrap_or(body);
+    // Первая строка блока — необязательный язык (`json`).
+    match body.split_once('\n') {
+        Some((lang, rest)) if !lang.contains(['{', '[']) => rest.trim(),
+        _ => body.trim(),
+    }
+}
+
+/// Дописать идентификатор запроса к описанию ошибки.
+fn with_request_id(err: GPTError, request_id: &str) -> GPTError {
+    let tag = |description: String| format!("{} [x-request-id: {}]", description, request_id);
+    match err {
+        GPTError::APIError { code, description } => GPTError::APIError {
+            code,
+            description: tag(description),
+        },
+        GPTError::Network { description } => GPTError::Network {
+            description: tag(description),
+        },
+        GPTError::Request { description } => GPTError::Request {
+            description: tag(description),
+        },
+        GPTError::ContextLengthExceeded { max_tokens, .. } => GPTError::ContextLengthExceeded {
+            max_tokens,
+            request_id: Some(request_id.to_string()),
+        },
+        other => other,
+    }
+}
+
+/// Пауза перед повтором после попытки `attempt`: экспоненциальный рост и случайная добавка
+/// до половины базового значения.
+fn retry_delay(attempt: u32) -> Duration {
+    let base = RETRY_BASE_DELAY * 2u32.pow(attempt.saturating_sub(1).min(10));
+    let jitter = fastrand::u64(0..=base.as_millis() as u64 / 2);
+    base + Duration::from_millis(jitter)
+}
+
+/// Ответ API с единственным вариантом, текст которого — тело запроса `body` с отступами.
+fn dry_run_response(body: &serde_json::Value) -> Result<TransportResponse> {
+    let text = serde_json::to_string_pretty(body)?;
+    let answer = json!({
+        "result": {
+            "alternatives": [{
+                "message": {"role": "assistant", "text": text},
+                "status": STATUS_FINAL,
+            }],
+        },
+    });
+    Ok(TransportResponse::new(200, answer.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::testing::FakeTransport;
+
+    /// Успешный ответ модели с текстом «ok».
+    const OK_BODY: &str = r#"{"result":{"alternatives":[{"message":{"role":"assistant","text":"ok"},"status":"ALTERNATIVE_STATUS_FINAL"}]}}"#;
+
+    #[test]
+    fn test_with_timeout_keeps_other_settings() {
+        let client = test_client().with_timeout(Duration::from_secs(5)).unwrap();
+
+        assert_eq!(client.timeout, Some(Duration::from_secs(5)));
+        assert_eq!(client.gpt_options.max_tokens, 500);
+        assert!(client.access.has_data());
+    }
+
+    #[test]
+    fn test_list_models_appends_custom_model() {
+        let known = test_client().list_models();
+        assert!(
+            known
+                .iter()
+                .any(|model| model.name == crate::MODEL_YANDEXGPT_LATEST)
+        );
+
+        let models = test_client().with_model("llama/latest").list_models();
+        assert_eq!(models.len(), known.len() + 1);
+        assert_eq!(models.last().unwrap().name, "llama/latest");
+        assert_eq!(models.last().unwrap().context_limit, None);
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_returns_request_json() {
+        let transport = FakeTransport::new(&[]);
+        let requests = transport.requests.clone();
+        let client = test_client()
+            .with_transport(transport)
+            .with_system_prompt("Отвечай кратко")
+            .with_dry_run(true);
+
+        let answer = client
+            .chat_with_gpt(&[ChatMessage::user("Привет")])
+            .await
+            .unwrap();
+        let payload: serde_json::Value = serde_json::from_str(&answer).unwrap();
+
+        assert_eq!(
+            payload["model_uri"],
+            "gpt://b1gcatalog/yandexgpt-lite/latest"
+        );
+        assert_eq!(payload["messages"][0]["role"], "system");
+        assert_eq!(payload["messages"][0]["text"], "Отвечай кратко");
+        assert_eq!(payload["messages"][1]["text"], "Привет");
+        assert!(answer.contains('\n'));
+        assert!(!answer.contains(&test_client().access.auth_method().header_value()));
+        assert!(requests.lock().unwrap().is_empty());
+
+        let answer = client.ask_gpt("Вопрос").await.unwrap();
+        assert!(answer.contains("Вопрос"));
+    }
+
+    #[test]
+    fn test_with_proxy_rejects_bogus_url() {
+        let result = test_client().with_proxy("http://[::1:3128");
+        assert!(matches!(result, Err(GPTError::ConfigError { .. })));
+
+        let client = test_client()
+            .with_timeout(Duration::from_secs(5))
+            .unwrap()
+            .with_proxy("http://127.0.0.1:3128")
+            .unwrap();
+        assert_eq!(client.proxy.as_deref(), Some("http://127.0.0.1:3128"));
+        assert_eq!(client.timeout, Some(Duration::from_secs(5)));
+
+        // Новый таймаут не сбрасывает прокси.
+        let client = client.with_timeout(Duration::from_secs(9)).unwrap();
+        assert_eq!(client.proxy.as_deref(), Some("http://127.0.0.1:3128"));
+        assert_eq!(client.timeout, Some(Duration::from_secs(9)));
+    }
+
+    #[tokio::test]
+    async fn test_retry_gives_up_after_max_attempts() {
+        let error = r#"{"error":{"message":"unavailable"}}"#;
+        let transport = FakeTransport::new(&[(503, error), (503, error), (200, OK_BODY)]);
+        let requests = transport.requests.clone();
+        let client = test_client().with_retry(2).with_transport(transport);
+
+        let result = client.ask_gpt("Привет").await;
+        assert!(matches!(result, Err(GPTError::APIError { code: 503, .. })));
+        assert_eq!(requests.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_client_errors_are_not_retried() {
+        let error = r#"{"error":{"message":"bad request"}}"#;
+        let transport = FakeTransport::new(&[(401, error), (400, error)]);
+        let requests = transport.requests.clone();
+        let client = test_client().with_retry(3).with_transport(transport);
+
+        let first = client.ask_gpt("Привет").await;
+        let second = client.ask_gpt("Привет").await;
+        assert!(matches!(first, Err(GPTError::InvalidCredential)));
+        assert!(matches!(second, Err(GPTError::APIError { code: 400, .. })));
+        assert_eq!(requests.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_context_overflow_is_reported_distinctly() {
+        let error = r#"{"error":{"grpcCode":3,"httpCode":400,"message":"Number of input tokens must be no more than 8192, got 12011","httpStatus":"Bad Request","details":[]}}"#;
+        let transport = FakeTransport::new(&[(400, error)]);
+        let requests = transport.requests.clone();
+        let client = test_client().with_retry(3).with_transport(transport);
+
+        let err = client
+            .chat_with_gpt(&[ChatMessage::user("…")])
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            GPTError::ContextLengthExceeded {
+                max_tokens: Some(8192),
+                request_id: Some(_),
+            }
+        ));
+        assert!(!err.is_retryable());
+
+        // Идентификатор в ошибке совпадает с отправленным заголовком.
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        let (_, sent) = requests[0]
+            .headers
+            .iter()
+            .find(|(name, _)| name == "x-request-id")
+            .unwrap();
+        assert!(
+            err.to_string()
+                .ends_with(&format!("[x-request-id: {}]", sent))
+        );
+    }
+
+    #[test]
+    fn test_base_url_derives_endpoints() {
+        let client = GPTClient::new().with_base_url(URL_BASE).unwrap();
+        assert_eq!(client.api_url, URL_API);
+        assert_eq!(client.embedding_url, URL_EMBEDDINGS);
+        assert_eq!(client.tokenize_url, URL_TOKENIZE);
+
+        let client = GPTClient::new()
+            .with_base_url("http://localhost:8080/yandex/")
+            .unwrap();
+        assert_eq!(
+            client.api_url,
+            "http://localhost:8080/yandex/foundationModels/v1/completion"
+        );
+        assert_eq!(
+            client.tokenize_url,
+            "http://localhost:8080/yandex/foundationModels/v1/tokenize"
+        );
+    }
+
+    #[test]
+    fn test_invalid_base_url_is_config_error() {
+        for base in [
+            "",
+            "llm.api.cloud.yandex.net",
+            "ftp://example.com",
+            "http://",
+        ] {
+            assert!(
+                matches!(
+                    GPTClient::new().with_base_url(base),
+                    Err(GPTError::ConfigError { .. })
+                ),
+                "{}",
+                base
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_no_retry_by_default() {
+        let error = r#"{"error":{"message":"unavailable"}}"#;
+        let transport = FakeTransport::new(&[(503, error), (200, OK_BODY)]);
+        let requests = transport.requests.clone();
+        let client = test_client().with_transport(transport);
+
+        let result = client.ask_gpt("Привет").await;
+        assert!(matches!(result, Err(GPTError::APIError { code: 503, .. })));
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_retry_delay_grows_exponentially() {
+        for (attempt, base) in [(1, 200), (2, 400), (3, 800)] {
+            let delay = retry_delay(attempt).as_millis() as u64;
+            assert!(
+                (base..=base + base / 2).contains(&delay),
+                "{}: {}",
+                attempt,
+                delay
+            );
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_zero_retry_attempts_panics() {
+        GPTClient::new().with_retry(0);
+    }
+
+    #[tokio::test]
+    async fn test_request_id_header_is_unique_per_request() {
+        let transport = FakeTransport::answering(&["ok", "ok"]);
+        let requests = transport.requests.clone();
+        let client = test_client().with_transport(transport);
+
+        assert_eq!(client.ask_gpt("Первый").await.unwrap(), "ok");
+        assert_eq!(client.ask_gpt("Второй").await.unwrap(), "ok");
+
+        let ids: Vec<String> = requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|request| {
+                let (_, id) = request
+                    .headers
+                    .iter()
+                    .find(|(name, _)| name == "x-request-id")?;
+                Some(id.clone())
+            })
+            .collect();
+        assert_eq!(ids.len(), 2);
+        assert_ne!(ids[0], ids[1]);
+        assert!(ids.iter().all(|id| Uuid::parse_str(id).is_ok()));
+    }
+
+    #[tokio::test]
+    async fn test_ask_gpt_full_reports_usage() {
+        let body = r#"{"result":{"alternatives":[{"message":{"role":"assistant","text":"Ответ"},"status":"ALTERNATIVE_STATUS_TRUNCATED_FINAL"}],"usage":{"inputTextTokens":"12","completionTokens":"500","totalTokens":"512"}}}"#;
+        let client = test_client().with_transport(FakeTransport::new(&[(200, body)]));
+
+        let response = client.ask_gpt_full("Привет").await.unwrap();
+        assert_eq!(
+            response,
+            GPTAnswer {
+                text: "Ответ".to_string(),
+                truncated: true,
+                status: STATUS_TRUNCATED.to_string(),
+                input_tokens: 12,
+                completion_tokens: 500,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ask_gpt_all_returns_every_alternative() {
+        let body = r#"{"result":{"alternatives":[
+            {"message":{"role":"assistant","text":"Первый"},"status":"ALTERNATIVE_STATUS_FINAL"},
+            {"message":{"role":"assistant","text":"Второй"},"status":"ALTERNATIVE_STATUS_FINAL"},
+            {"message":{"role":"assistant","text":"Третий"},"status":"ALTERNATIVE_STATUS_TRUNCATED_FINAL"}
+        ]}}"#;
+        let transport = FakeTransport::new(&[(200, body)]);
+        let requests = transport.requests.clone();
+        let client = test_client().with_transport(transport).with_alternatives(3);
+
+        let answers = client.ask_gpt_all("Привет").await.unwrap();
+        let texts: Vec<&str> = answers.iter().map(|answer| answer.text.as_str()).collect();
+        assert_eq!(texts, ["Первый", "Второй", "Третий"]);
+        assert!(answers[2].truncated);
+        let requests = requests.lock().unwrap();
+        assert_eq!(
+            requests[0].body["completion_options"]["num_alternatives"],
+            3
+        );
+    }
+
+    #[tokio::test]
+    async fn test_chat_answers_report_usage() {
+        let body = r#"{"result":{"alternatives":[
+            {"message":{"role":"assistant","text":"Первый"},"status":"ALTERNATIVE_STATUS_FINAL"},
+            {"message":{"role":"assistant","text":"Второй"},"status":"ALTERNATIVE_STATUS_FINAL"}
+        ],"usage":{"inputTextTokens":"30","completionTokens":"8","totalTokens":"38"}}}"#;
+        let client = test_client()
+            .with_alternatives(2)
+            .with_transport(FakeTransport::new(&[(200, body), (200, body)]));
+        let messages = [ChatMessage::user("Привет")];
+
+        let answers = client.chat_with_gpt_all(&messages).await.unwrap();
+        assert_eq!(answers.len(), 2);
+        assert!(
+            answers
+                .iter()
+                .all(|answer| (answer.input_tokens, answer.completion_tokens) == (30, 8))
+        );
+
+        let answer = client.chat_with_gpt_detailed(&messages).await.unwrap();
+        assert_eq!((answer.input_tokens, answer.completion_tokens), (30, 8));
+    }
+
+    #[tokio::test]
+    async fn test_empty_answers_are_errors() {
+        let filtered = r#"{"result":{"alternatives":[
+            {"message":{"role":"assistant","text":""},"status":"ALTERNATIVE_STATUS_CONTENT_FILTER"}
+        ]}}"#;
+        let mixed = r#"{"result":{"alternatives":[
+            {"message":{"role":"assistant","text":" "},"status":"ALTERNATIVE_STATUS_FINAL"},
+            {"message":{"role":"assistant","text":"Второй"},"status":"ALTERNATIVE_STATUS_FINAL"}
+        ]}}"#;
+        let client = test_client().with_transport(FakeTransport::new(&[
+            (200, filtered),
+            (200, filtered),
+            (200, mixed),
+        ]));
+
+        assert!(matches!(
+            client.ask_gpt("1").await,
+            Err(GPTError::ContentFiltered)
+        ));
+        assert!(matches!(
+            client.ask_gpt_all("2").await,
+            Err(GPTError::ContentFiltered)
+        ));
+        let answers = client.ask_gpt_all("3").await.unwrap();
+        assert_eq!(answers.len(), 1);
+        assert_eq!(answers[0].text, "Второй");
+    }
+
+    #[tokio::test]
+    async fn test_ask_gpt_all_without_variants_is_empty_response() {
+        let client = test_client().with_transport(FakeTransport::new(&[(
+            200,
+            r#"{"result":{"alternatives":[]}}"#,
+        )]));
+
+        let result = client.ask_gpt_all("Привет").await;
+        assert!(matches!(result, Err(GPTError::EmptyResponse)));
+    }
+
+    #[tokio::test]
+    async fn test_embed_batch_parses_embeddings() {
+        let transport = FakeTransport::new(&[
+            (200, r#"{"embedding":[0.5,-1.25,2],"numTokens":"3"}"#),
+            (200, r#"{"embedding":[1,0,0],"numTokens":"2"}"#),
+        ]);
+        let requests = transport.requests.clone();
+        let client = test_client()
+            .with_embedding_url("http://embeddings.local".to_string())
+            .with_transport(transport);
+
+        let embeddings = client.embed_batch(&["кот", "пёс"]).await.unwrap();
+        assert_eq!(embeddings, [vec![0.5, -1.25, 2.0], vec![1.0, 0.0, 0.0]]);
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(
+            requests
+                .iter()
+                .all(|request| request.url == "http://embeddings.local")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_count_tokens_counts_returned_tokens() {
+        let tokens = r#"{"tokens":[
+            {"id":"1","special":true,"text":"<s>"},
+            {"id":"5021","special":false,"text":"▁При"},
+            {"id":"3942","special":false,"text":"вет"}
+        ],"modelVersion":"23.10.2024"}"#;
+        let transport = FakeTransport::new(&[(200, tokens)]);
+        let requests = transport.requests.clone();
+        let client = test_client()
+            .with_tokenize_url("http://tokenizer.local".to_string())
+            .with_transport(transport);
+
+        assert_eq!(client.count_tokens("Привет").await.unwrap(), 3);
+        assert_eq!(requests.lock().unwrap()[0].url, "http://tokenizer.local");
+    }
+
+    #[tokio::test]
+    async fn test_count_tokens_sends_nothing_in_dry_run() {
+        let transport = FakeTransport::new(&[]);
+        let requests = transport.requests.clone();
+        let client = test_client().with_transport(transport).with_dry_run(true);
+
+        assert!(matches!(
+            client.count_tokens("Черновик").await,
+            Err(GPTError::ConfigError { .. })
+        ));
+        assert!(requests.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_embedding_request_shape() {
+        let request = EmbeddingRequest {
+            model_uri: format!("emb://b1gcatalog/{}", EMBEDDING_MODEL_QUERY),
+            text: "кот".to_string(),
+        };
+
+        assert_eq!(
+            json!(request),
+            json!({"modelUri": "emb://b1gcatalog/text-search-query/latest", "text": "кот"})
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ping_uses_tokenizer_instead_of_completion() {
+        let tokens = r#"{"tokens":[{"id":"1","special":false,"text":"ping"}]}"#;
+        let transport = FakeTransport::new(&[(200, tokens)]);
+        let requests = transport.requests.clone();
+        let client = test_client()
+            .with_tokenize_url("http://tokenizer.local".to_string())
+            .with_transport(transport);
+
+        assert!(client.ping().await.is_ok());
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].url, "http://tokenizer.local");
+        assert!(requests[0].body.get("completionOptions").is_none());
+        assert!(requests[0].body.get("completion_options").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_ask_gpt_stream_yields_deltas() {
+        // Строки приходят отдельными фрагментами, вторая — разрезанной посередине.
+        let chunks = [
+            concat!(
+                r#"{"result":{"alternatives":[{"message":{"role":"assistant","text":"Пр"},"status":"ALTERNATIVE_STATUS_PARTIAL"}]}}"#,
+                "\n",
+                r#"{"result":{"alternatives":[{"message":"#,
+            ),
+            concat!(
+                r#"{"role":"assistant","text":"Привет"},"status":"ALTERNATIVE_STATUS_FINAL"}]}}"#,
+                "\n",
+            ),
+        ];
+        let response = TransportResponse {
+            status: 200,
+            headers: Vec::new(),
+            body: Box::pin(futures::stream::iter(
+                chunks.map(|chunk| Ok(chunk.as_bytes().to_vec())),
+            )),
+        };
+        let client = test_client().with_transport(FakeTransport::with_responses(vec![response]));
+
+        let events: Vec<StreamEvent> = client
+            .ask_gpt_stream("Привет")
+            .await
+            .unwrap()
+            .map(|event| event.unwrap())
+            .collect()
+            .await;
+
+        assert_eq!(
+            events[..2],
+            [
+                StreamEvent::Delta("Пр".to_string()),
+                StreamEvent::Delta("ивет".to_string())
+            ]
+        );
+        assert!(matches!(&events[2], StreamEvent::Done(answer) if answer.text == "Привет"));
+    }
+
+    #[tokio::test]
+    async fn test_ping_without_credentials_fails_locally() {
+        let result = GPTClient::new().ping().await;
+        assert!(matches!(result, Err(GPTError::InvalidCredential)));
+    }
+
+    #[test]
+    fn test_request_id_is_added_to_api_error() {
+        let err = GPTError::APIError {
+            code: 500,
+            description: "сбой".to_string(),
+        };
+        match with_request_id(err, "abc") {
+            GPTError::APIError { description, .. } => {
+                assert_eq!(description, "сбой [x-request-id: abc]")
+            }
+            other => panic!("неожиданная ошибка: {:?}", other),
+        }
+    }
+
+    /// Клиент с известными параметрами для сверки тела запроса.
+    fn test_client() -> GPTClient {
+        GPTClient::new()
+            .set_auth("b1gcatalog".to_string(), "secret".to_string())
+            .with_model("yandexgpt-lite/latest")
+            .with_temperature(0.5)
+            .with_max_tokens(500)
+    }
+
+    #[tokio::test]
+    async fn test_fake_transport_answer_and_headers() {
+        let transport = FakeTransport::new(&[(200, OK_BODY)]);
+        let requests = transport.requests.clone();
+        let client = test_client().with_transport(transport);
+
+        assert_eq!(client.ask_gpt("Привет").await.unwrap(), "ok");
+
+        let requests = requests.lock().unwrap();
+        let (url, headers) = (&requests[0].url, &requests[0].headers);
+        assert_eq!(url, URL_API);
+        assert!(headers.contains(&("Authorization".to_string(), "Api-Key secret".to_string())));
+        assert!(headers.iter().any(|(name, _)| name == "x-request-id"));
+    }
+
+    #[tokio::test]
+    async fn test_custom_headers_reach_request() {
+        let transport = FakeTransport::new(&[(200, OK_BODY)]);
+        let requests = transport.requests.clone();
+        let client = test_client()
+            .with_transport(transport)
+            .with_user_agent("my-app/1.0")
+            .unwrap()
+            .with_header("X-Client-Tag", "docs")
+            .unwrap();
+
+        client.ask_gpt("Привет").await.unwrap();
+
+        let requests = requests.lock().unwrap();
+        let headers = &requests[0].headers;
+        let values = |name: &str| -> Vec<&str> {
+            headers
+                .iter()
+                .filter(|(header, _)| header.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.as_str())
+                .collect()
+        };
+        assert_eq!(values("user-agent"), ["my-app/1.0"]);
+        assert_eq!(values("x-client-tag"), ["docs"]);
+        assert_eq!(values("authorization"), ["Api-Key secret"]);
+    }
+
+    #[tokio::test]
+    async fn test_json_output_reaches_request() {
+        let schema = json!({
+            "type": "object",
+            "properties": {"city": {"type": "string"}},
+            "required": ["city"]
+        });
+        let client = test_client().with_dry_run(true);
+
+        let plain: serde_json::Value =
+            serde_json::from_str(&client.ask_gpt("1").await.unwrap()).unwrap();
+        assert!(plain.get("json_object").is_none());
+        assert!(plain.get("json_schema").is_none());
+
+        let client = client.with_json_output(None);
+        let object: serde_json::Value =
+            serde_json::from_str(&client.ask_gpt("2").await.unwrap()).unwrap();
+        assert_eq!(object["json_object"], true);
+
+        let client = client.with_json_output(Some(schema.clone()));
+        let typed: serde_json::Value =
+            serde_json::from_str(&client.ask_gpt("3").await.unwrap()).unwrap();
+        assert_eq!(typed["json_schema"]["schema"], schema);
+        assert!(typed.get("json_object").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_ask_gpt_typed_parses_answer() {
+        #[derive(Debug, PartialEq, serde::Deserialize)]
+        struct Weather {
+            city: String,
+            degrees: i32,
+        }
+
+        let answer = |text: &str| {
+            let body = json!({"result": {"alternatives": [{
+                "message": {"role": "assistant", "text": text},
+                "status": "ALTERNATIVE_STATUS_FINAL"
+            }]}});
+            TransportResponse::new(200, body.to_string())
+        };
+        let client =
+            test_client()
+                .with_json_output(None)
+                .with_transport(FakeTransport::with_responses(vec![
+                    answer(r#"{"city": "Казань", "degrees": 12}"#),
+                    answer("```json\n{\"city\": \"Омск\", \"degrees\": -3}\n```"),
+                    answer("Не знаю"),
+                ]));
+
+        let expected = Weather {
+            city: "Казань".to_string(),
+            degrees: 12,
+        };
+        assert_eq!(
+            client.ask_gpt_typed::<Weather>("?").await.unwrap(),
+            expected
+        );
+        let fenced: Weather = client.ask_gpt_typed("?").await.unwrap();
+        assert_eq!(fenced.city, "Омск");
+        assert!(matches!(
+            client.ask_gpt_typed::<Weather>("?").await,
+            Err(GPTError::ParseError { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_data_logging_header() {
+        let transport = FakeTransport::new(&[(200, OK_BODY), (200, OK_BODY), (200, OK_BODY)]);
+        let requests = transport.requests.clone();
+        let client = test_client().with_transport(transport);
+
+        client.ask_gpt("1").await.unwrap();
+        let client = client.with_data_logging(false);
+        client.ask_gpt("2").await.unwrap();
+        client.with_data_logging(true).ask_gpt("3").await.unwrap();
+
+        let logging: Vec<Option<String>> = requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|request| {
+                request
+                    .headers
+                    .iter()
+                    .find(|(name, _)| name == DATA_LOGGING_HEADER)
+                    .map(|(_, value)| value.clone())
+            })
+            .collect();
+        assert_eq!(logging, [None, Some("false".to_string()), None]);
+    }
+
+    #[test]
+    fn test_with_header_rejects_authorization_and_bad_values() {
+        for (name, value) in [
+            ("Authorization", "Bearer stolen"),
+            ("authorization", "x"),
+            ("bad name", "x"),
+            ("x-note", "line\nbreak"),
+        ] {
+            let result = test_client().with_header(name, value);
+            assert!(
+                matches!(result, Err(GPTError::ConfigError { .. })),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_chat_stream_yields_deltas() {
+        let body = concat!(
+            r#"{"result":{"alternatives":[{"message":{"text":"Да"},"status":"ALTERNATIVE_STATUS_PARTIAL"}]}}"#,
+            "\n",
+            r#"{"result":{"alternatives":[{"message":{"text":"Да, конечно"},"status":"ALTERNATIVE_STATUS_FINAL"}]}}"#,
+            "\n",
+        );
+        let client = test_client().with_transport(FakeTransport::new(&[(200, body)]));
+        let dialog = [ChatMessage::user("Можно?")];
+
+        let deltas: Vec<String> = client
+            .chat_with_gpt_stream(&dialog)
+            .await
+            .unwrap()
+            .filter_map(|event| async move {
+                match event.unwrap() {
+                    StreamEvent::Delta(delta) => Some(delta),
+                    StreamEvent::Done(_) => None,
+                }
+            })
+            .collect()
+            .await;
+
+        assert_eq!(deltas, ["Да", ", конечно"]);
+    }
+
+    #[tokio::test]
+    async fn test_fake_transport_maps_errors() {
+        let client = test_client().with_transport(FakeTransport::new(&[
+            (401, "unauthorized"),
+            (
+                400,
+                r#"{"error":{"message":"temperature is out of range"}}"#,
+            ),
+            (200, r#"{"result":{"alternatives":[]}}"#),
+        ]));
+
+        assert!(matches!(
+            client.ask_gpt("1").await,
+            Err(GPTError::InvalidCredential)
+        ));
+        match client.ask_gpt("2").await {
+            Err(GPTError::APIError { code, description }) => {
+                assert_eq!(code, 400);
+                assert!(description.contains("температуры"), "{}", description);
+            }
+            other => panic!("Ожидалась APIError, получено: {:?}", other),
+        }
+        assert!(matches!(
+            client.ask_gpt("3").await,
+            Err(GPTError::EmptyResponse)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_is_mapped_from_429() {
+        let client = test_client().with_transport(FakeTransport::with_responses(vec![
+            TransportResponse::new(429, "slow down").with_header("Retry-After", "12"),
+            TransportResponse::new(429, "slow down"),
+        ]));
+
+        assert!(matches!(
+            client.ask_gpt("1").await,
+            Err(GPTError::RateLimited { retry_after: Some(delay) }) if delay == Duration::from_secs(12)
+        ));
+        assert!(matches!(
+            client.ask_gpt("2").await,
+            Err(GPTError::RateLimited { retry_after: None })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_waits_retry_after() {
+        let transport = FakeTransport::with_responses(vec![
+            TransportResponse::new(429, "").with_header("retry-after", "0"),
+            TransportResponse::new(200, OK_BODY),
+        ]);
+        let requests = transport.requests.clone();
+        let client = test_client().with_retry(2).with_transport(transport);
+
+        assert_eq!(client.ask_gpt("Привет").await.unwrap(), "ok");
+        assert_eq!(requests.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_long_retry_after_is_not_awaited() {
+        let transport = FakeTransport::with_responses(vec![
+            TransportResponse::new(429, "").with_header("Retry-After", "3600"),
+            TransportResponse::new(200, OK_BODY),
+        ]);
+        let requests = transport.requests.clone();
+        let client = test_client().with_retry(3).with_transport(transport);
+
+        assert!(matches!(
+            client.ask_gpt("Привет").await,
+            Err(GPTError::RateLimited { .. })
+        ));
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_fake_transport_retries_server_errors() {
+        let transport = FakeTransport::new(&[(503, "busy"), (500, "oops"), (200, OK_BODY)]);
+        let requests = transport.requests.clone();
+        let client = test_client().with_retry(3).with_transport(transport);
+
+        assert_eq!(client.ask_gpt("Привет").await.unwrap(), "ok");
+        assert_eq!(requests.lock().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_build_request_shape() {
+        let body = test_client().build_request(
+            vec![ChatMessage {
+                role: "user".to_string(),
+                text: "Привет".to_string(),
+            }],
+            500,
+        );
+
+        assert_eq!(
+            body,
+            json!({
+                "model_uri": "gpt://b1gcatalog/yandexgpt-lite/latest",
+                "completion_options": {
+                    "stream": false,
+                    "temperature": 0.5,
+                    "max_tokens": 500
+                },
+                "messages": [{"role": "user", "text": "Привет"}]
+            })
+        );
+    }
+
+    #[test]
+    fn test_build_ask_request_shape() {
+        let body = test_client()
+            .build_ask_request("Сколько будет 2 + 2?")
+            .unwrap();
+
+        assert_eq!(body["model_uri"], "gpt://b1gcatalog/yandexgpt-lite/latest");
+        assert_eq!(
+            body["messages"],
+            json!([{"role": "user", "text": "Сколько будет 2 + 2?"}])
+        );
+    }
+
+    #[test]
+    fn test_folder_id_overrides_model_uri_catalog() {
+        let access = AccessData::new("b1gcatalog".to_string(), "secret".to_string())
+            .with_folder_id("b1gfolder");
+        let body = test_client()
+            .with_access(access)
+            .build_ask_request("Привет")
+            .unwrap();
+
+        assert_eq!(body["model_uri"], "gpt://b1gfolder/yandexgpt-lite/latest");
+    }
+
+    #[test]
+    fn test_build_chat_request_shape() {
+        let history = vec![
+            ChatMessage::assistant("YandexGPT готов к диалогу."),
+            ChatMessage::user("Привет"),
+            ChatMessage::assistant("Здравствуйте!"),
+        ];
+        let body = test_client().build_chat_request(&history).unwrap();
+
+        assert_eq!(
+            body["messages"],
+            json!([
+                {"role": "assistant", "text": "YandexGPT готов к диалогу."},
+                {"role": "user", "text": "Привет"},
+                {"role": "assistant", "text": "Здравствуйте!"},
+            ])
+        );
+        assert_eq!(body["completion_options"]["max_tokens"], 500);
+    }
+
+    #[test]
+    fn test_chat_roles_are_taken_from_messages() {
+        // Роли не зависят от позиции: история начинается с ответа модели,
+        // а два сообщения пользователя подряд остаются `user`.
+        let history = vec![
+            ChatMessage::assistant("YandexGPT готов к диалогу."),
+            ChatMessage::user("Первый вопрос"),
+            ChatMessage::user("Уточнение"),
+            ChatMessage::assistant("Ответ"),
+            ChatMessage::user("Второй вопрос"),
+        ];
+        let body = test_client()
+            .add_system_prompt("Отвечай кратко.")
+            .build_chat_request(&history)
+            .unwrap();
+        let roles: Vec<&str> = body["messages"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|message| message["role"].as_str().unwrap())
+            .collect();
+
+        assert_eq!(
+            roles,
+            ["system", "assistant", "user", "user", "assistant", "user"]
+        );
+    }
+
+    #[test]
+    fn test_chat_max_tokens_applies_only_to_chat() {
+        let client = test_client().with_chat_max_tokens(1500);
+        let history = vec![ChatMessage::user("Привет")];
+
+        let chat = client.build_chat_request(&history).unwrap();
+        let ask = client.build_ask_request("Привет").unwrap();
+
+        assert_eq!(chat["completion_options"]["max_tokens"], 1500);
+        assert_eq!(ask["completion_options"]["max_tokens"], 500);
+    }
+
+    #[test]
+    fn test_request_has_only_known_top_level_fields() {
+        let body = test_client().build_ask_request("?").unwrap();
+        let mut keys: Vec<&str> = body
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect();
+        keys.sort();
+
+        assert_eq!(keys, ["completion_options", "messages", "model_uri"]);
+    }
+
+    #[test]
+    fn test_long_chat_is_trimmed_to_model_limit() {
+        // 3000 символов — около 1000 токенов; лимит yandexgpt-lite 8192 минус 500 на ответ.
+        let history: Vec<ChatMessage> = (0..10)
+            .map(|i| {
+                let text = i.to_string().repeat(3000);
+                if i % 2 == 0 {
+                    ChatMessage::assistant(text)
+                } else {
+                    ChatMessage::user(text)
+                }
+            })
+            .collect();
+        let body = test_client().build_chat_request(&history).unwrap();
+        let messages = body["messages"].as_array().unwrap();
+
+        assert_eq!(messages.len(), 7);
+        assert_eq!(messages[6]["text"], history[9].text);
+        assert_eq!(messages[0]["text"], history[3].text);
+        // Роли принадлежат сообщениям и при отбрасывании сохраняются.
+        assert_eq!(messages[0]["role"], "user");
+    }
+
+    #[test]
+    fn test_chat_sends_only_recent_messages() {
+        let history: Vec<ChatMessage> = (0..50)
+            .map(|i| {
+                if i % 2 == 0 {
+                    ChatMessage::user(format!("Вопрос {}", i))
+                } else {
+                    ChatMessage::assistant(format!("Ответ {}", i))
+                }
+            })
+            .collect();
+        let client = test_client()
+            .with_system_prompt("Отвечай кратко.")
+            .with_max_history_exchanges(3);
+
+        let body = client.build_chat_request(&history).unwrap();
+        let messages = body["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 7);
+        assert_eq!(messages[0]["role"], "system");
+        assert_eq!(messages[1]["text"], "Вопрос 44");
+        assert_eq!(messages[6]["text"], "Ответ 49");
+
+        // Окно начинается с вопроса, даже если диалог оканчивается вопросом без ответа.
+        let mut pending = history.clone();
+        pending.push(ChatMessage::user("Вопрос 50"));
+        let body = client.build_chat_request(&pending).unwrap();
+        let messages = body["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 6);
+        assert_eq!(messages[1]["text"], "Вопрос 46");
+
+        let body = client
+            .with_max_history_exchanges(0)
+            .build_chat_request(&history)
+            .unwrap();
+        assert_eq!(body["messages"].as_array().unwrap().len(), 51);
+        let body = test_client().build_chat_request(&history).unwrap();
+        assert_eq!(body["messages"].as_array().unwrap().len(), 20);
+    }
+
+    #[test]
+    fn test_last_message_is_kept_even_over_limit() {
+        let history = vec![ChatMessage::user("x".repeat(30_000))];
+        let body = test_client().build_chat_request(&history).unwrap();
+
+        assert_eq!(body["messages"].as_array().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_max_tokens_over_model_limit_is_config_error() {
+        let client = test_client()
+            .with_model("yandexgpt-lite/latest")
+            .with_max_tokens(10_000);
+        assert!(matches!(
+            client.build_ask_request("?"),
+            Err(GPTError::ConfigError { .. })
+        ));
+        assert!(matches!(
+            test_client()
+                .with_chat_max_tokens(0)
+                .build_chat_request(&[ChatMessage::user("?")]),
+            Err(GPTError::ConfigError { .. })
+        ));
+    }
+
+    #[test]
+    fn test_max_tokens_checked_against_final_model() {
+        // Лимит выше лимита lite, но допустим для yandexgpt: порядок вызовов не важен.
+        let lite_then_pro = test_client()
+            .with_model("yandexgpt-lite/latest")
+            .with_max_tokens(10_000)
+            .with_model("yandexgpt/latest");
+        assert!(lite_then_pro.build_ask_request("?").is_ok());
+
+        let pro_then_lite = test_client()
+            .with_model("yandexgpt/latest")
+            .with_max_tokens(10_000)
+            .with_model("yandexgpt-lite/latest");
+        assert!(matches!(
+            pro_then_lite.build_ask_request("?"),
+            Err(GPTError::ConfigError { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_max_tokens_fail_before_sending() {
+        let transport = FakeTransport::new(&[(200, OK_BODY)]);
+        let requests = transport.requests.clone();
+        let client = test_client().with_transport(transport).with_max_tokens(-1);
+        assert!(matches!(
+            client.ask_gpt("Привет").await,
+            Err(GPTError::ConfigError { .. })
+        ));
+        assert!(requests.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_alternatives_are_serialized_only_when_several() {
+        let body = test_client()
+            .with_alternatives(3)
+            .build_ask_request("?")
+            .unwrap();
+        assert_eq!(body["completion_options"]["num_alternatives"], 3);
+
+        let body = test_client().build_ask_request("?").unwrap();
+        assert!(body["completion_options"].get("num_alternatives").is_none());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_zero_alternatives_panics() {
+        GPTClient::new().with_alternatives(0);
+    }
+
+    #[test]
+    fn test_seed_is_serialized_only_when_set() {
+        let body = test_client().with_seed(42).build_ask_request("?").unwrap();
+        assert_eq!(body["completion_options"]["seed"], 42);
+
+        let body = test_client().build_ask_request("?").unwrap();
+        assert!(body["completion_options"].get("seed").is_none());
+    }
+
+    #[test]
+    fn test_zero_temperature_is_serialized() {
+        let client = GPTClient::new().with_temperature(0.0);
+        let body = client.build_ask_request("2 + 2?").unwrap();
+
+        assert_eq!(
+            body["completion_options"]["temperature"].as_f64(),
+            Some(0.0)
+        );
+    }
+
+    #[test]
+    fn test_deterministic_preset() {
+        let client = GPTClient::new().with_temperature(0.9).deterministic();
+        assert_eq!(client.gpt_options.temperature, 0.0);
+        assert_eq!(
+            client.gpt_options.max_tokens,
+            GPTOptions::default().max_tokens
+        );
+
+        let body = client.build_ask_request("2 + 2?").unwrap();
+        assert_eq!(
+            body["completion_options"]["temperature"].as_f64(),
+            Some(0.0)
+        );
+    }
+
+    #[test]
+    fn test_system_prompt_precedes_added_prompts() {
+        let client = GPTClient::new()
+            .add_system_prompt("Отвечай кратко.")
+            .with_system_prompt("Ты — редактор.");
+        let ask = client.build_ask_request("Проверь текст").unwrap();
+        let chat = client
+            .build_chat_request(&[ChatMessage::user("Проверь текст")])
+            .unwrap();
+
+        let expected = json!([
+            {"role": "system", "text": "Ты — редактор."},
+            {"role": "system", "text": "Отвечай кратко."},
+            {"role": "user", "text": "Проверь текст"},
+        ]);
+        assert_eq!(ask["messages"], expected);
+        assert_eq!(chat["messages"], expected);
+    }
+
+    #[test]
+    fn test_empty_system_prompt_clears_persona() {
+        let client = GPTClient::new()
+            .with_system_prompt("Ты — редактор.")
+            .with_system_prompt("");
+        let body = client.build_ask_request("Привет").unwrap();
+
+        assert_eq!(client.gpt_options.system_prompt, None);
+        assert_eq!(
+            body["messages"],
+            json!([{"role": "user", "text": "Привет"}])
+        );
+    }
+
+    #[test]
+    fn test_model_uri_is_templated_by_default() {
+        let body = test_client().build_ask_request("Привет").unwrap();
+        assert_eq!(body["model_uri"], "gpt://b1gcatalog/yandexgpt-lite/latest");
+    }
+
+    #[test]
+    fn test_model_uri_override_is_used_verbatim() {
+        let client = test_client()
+            .with_model_uri("ds://bt1custom")
+            .with_model("yandexgpt/rc");
+        let body = client.build_ask_request("Привет").unwrap();
+
+        assert_eq!(body["model_uri"], "ds://bt1custom");
+    }
+
+    #[test]
+    fn test_system_prompts_prepended_in_order() {
+        let client = GPTClient::new()
+            .add_system_prompt("Ты — редактор.")
+            .add_system_prompt("   ")
+            .add_system_prompt("Отвечай кратко.");
+        let body = client.build_ask_request("Проверь текст").unwrap();
+
+        assert_eq!(
+            body["messages"],
+            json!([
+                {"role": "system", "text": "Ты — редактор."},
+                {"role": "system", "text": "Отвечай кратко."},
+                {"role": "user", "text": "Проверь текст"},
+            ])
+        );
+    }
+
+    #[test]
+    fn test_from_config_matches_builders() {
+        let config = ClientConfig::try_new(
+            "yandexgpt-lite/latest",
+            0.5,
+            500,
+            Some("Отвечай кратко"),
+            Some(Duration::from_secs(5)),
+            3,
+        )
+        .unwrap();
+        let client = GPTClient::from_config(test_client().access, config.clone()).unwrap();
+        let built = test_client()
+            .with_system_prompt("Отвечай кратко")
+            .with_timeout(Duration::from_secs(5))
+            .unwrap()
+            .with_retry(3);
+
+        assert_eq!(
+            client.build_ask_request("Привет").unwrap(),
+            built.build_ask_request("Привет").unwrap()
+        );
+        assert_eq!(client.config(), config);
+        assert_eq!(built.config(), config);
+    }
+
+    #[test]
+    fn test_config_round_trip_covers_every_field() {
+        // Полное перечисление полей: новое поле `ClientConfig` не соберётся без этого теста.
+        let ClientConfig {
+            model,
+            temperature,
+            max_tokens,
+            system_prompt,
+            timeout,
+            retry,
+        } = ClientConfig::default();
+        let config = ClientConfig {
+            model: format!("{}-lite", model.trim_end_matches("/latest")),
+            temperature: temperature / 2.0,
+            max_tokens: max_tokens / 4,
+            system_prompt: system_prompt.or(Some("Отвечай кратко".to_string())),
+            timeout: timeout.or(Some(Duration::from_secs(7))),
+            retry: retry + 2,
+        };
+        assert_ne!(config, ClientConfig::default());
+
+        let client = GPTClient::from_config(test_client().access, config.clone()).unwrap();
+        assert_eq!(client.config(), config);
+        let rebuilt = GPTClient::from_config(test_client().access, client.config()).unwrap();
+        assert_eq!(rebuilt.config(), config);
+
+        // Настройки вне `ClientConfig` при пересборке не переносятся.
+        let custom = test_client()
+            .with_model_uri("ds://tuned")
+            .with_alternatives(2);
+        let rebuilt = GPTClient::from_config(test_client().access, custom.config()).unwrap();
+        assert_eq!(rebuilt.gpt_options.model_uri, None);
+        assert_eq!(rebuilt.gpt_options.alternatives, 1);
+    }
+
+    #[test]
+    fn test_from_config_validates() {
+        let config = ClientConfig {
+            temperature: 2.0,
+            ..ClientConfig::default()
+        };
+        assert!(matches!(
+            GPTClient::from_config(AccessData::default(), config),
+            Err(GPTError::ConfigError { .. })
+        ));
+        let config = ClientConfig {
+            retry: 0,
+            ..ClientConfig::default()
+        };
+        assert!(GPTClient::from_config(AccessData::default(), config).is_err());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_temperature_out_of_range_panics() {
+        let _ = GPTClient::new().with_temperature(1.5);
+    }
+}
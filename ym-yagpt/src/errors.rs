@@ -1,5 +1,9 @@
 //! Модуль собственных ошибок приложения.
 use std::fmt::{Display, Formatter};
+use std::time::Duration;
+
+/// Результат операций библиотеки с ошибкой [`GPTError`].
+pub type Result<T> = std::result::Result<T, GPTError>;
 
 /// Перечисление ошибок, персонализированных для взаимодействия с нейросетью.
 #[derive(Debug)]
@@ -13,6 +17,45 @@ pub enum GPTError {
     APIError { code: i32, description: String },
     /// Неправильная конфигурация для запроса к API.
     ConfigError { description: String },
+    /// Нет соединения с API: сеть недоступна, сервер не отвечает или истёк таймаут подключения.
+    Network { description: String },
+    /// Прочие ошибки HTTP-запроса (формирование запроса, редиректы, чтение тела ответа).
+    Request { description: String },
+    /// Ответ API не удалось разобрать.
+    ParseError { description: String },
+    /// Превышен лимит запросов (HTTP 429). `retry_after` — пауза из заголовка `Retry-After`.
+    RateLimited { retry_after: Option<Duration> },
+    /// Фильтр содержимого остановил генерацию раньше, чем модель что-либо ответила.
+    ContentFiltered,
+    /// Запрос вместе с историей не помещается в контекст модели (HTTP 400).
+    /// `max_tokens` — размер контекста из сообщения API, если он там указан,
+    /// `request_id` — идентификатор запроса (`x-request-id`) для обращения в поддержку.
+    ContextLengthExceeded {
+        max_tokens: Option<i64>,
+        request_id: Option<String>,
+    },
+}
+
+impl GPTError {
+    /// Имеет ли смысл повторить запрос, завершившийся этой ошибкой.
+    ///
+    /// Повторяются сбои на стороне сервера (5xx), потеря соединения (включая таймауты)
+    /// и превышение лимита запросов. Ошибки авторизации, прочие ответы 4xx (в том числе
+    /// переполнение контекста), ошибки конфигурации, разбора, пустой ответ и срабатывание
+    /// фильтра от повтора не исправятся.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            GPTError::APIError { code, .. } => *code >= 500,
+            GPTError::Network { .. } | GPTError::RateLimited { .. } => true,
+            GPTError::EmptyResponse
+            | GPTError::ContentFiltered
+            | GPTError::ContextLengthExceeded { .. }
+            | GPTError::InvalidCredential
+            | GPTError::ConfigError { .. }
+            | GPTError::Request { .. }
+            | GPTError::ParseError { .. } => false,
+        }
+    }
 }
 
 impl std::error::Error for GPTError {}
@@ -23,6 +66,9 @@ impl Display for GPTError {
             GPTError::EmptyResponse => {
                 write!(f, "Получен пустой ответ от API")
             }
+            GPTError::ContentFiltered => {
+                write!(f, "Ответ скрыт фильтром содержимого")
+            }
             GPTError::InvalidCredential => {
                 write!(f, "Данные для авторизации неверные или устарели")
             }
@@ -32,6 +78,327 @@ impl Display for GPTError {
             GPTError::ConfigError { description } => {
                 write!(f, "Некорректная конфигурация запроса GPT: {}", description)
             }
+            GPTError::Network { description } => {
+                write!(f, "Нет соединения с API: {}", description)
+            }
+            GPTError::Request { description } => {
+                write!(f, "Ошибка HTTP-запроса: {}", description)
+            }
+            GPTError::ParseError { description } => {
+                write!(f, "Не удалось разобрать ответ API: {}", description)
+            }
+            GPTError::ContextLengthExceeded {
+                max_tokens,
+                request_id,
+            } => {
+                write!(f, "Запрос вместе с историей превышает контекст модели")?;
+                if let Some(limit) = max_tokens {
+                    write!(f, " ({} токенов)", limit)?;
+                }
+                match request_id {
+                    Some(id) => write!(f, " [x-request-id: {}]", id),
+                    None => Ok(()),
+                }
+            }
+            GPTError::RateLimited { retry_after } => {
+                write!(f, "Превышен лимит запросов к API")?;
+                match retry_after {
+                    Some(delay) => write!(f, ", повтор возможен через {} с", delay.as_secs()),
+                    None => Ok(()),
+                }
+            }
         }
     }
 }
+
+/// Код gRPC `INVALID_ARGUMENT`: API отклонил параметр запроса.
+const GRPC_INVALID_ARGUMENT: i32 = 3;
+
+/// Пояснения к кодам gRPC (`grpcCode`) из тела ошибки API.
+const GRPC_HINTS: &[(i32, &str)] = &[
+    (5, "модель или каталог не найдены"),
+    (7, "нет прав на использование модели в этом каталоге"),
+    (8, "исчерпана квота каталога"),
+    (16, "данные авторизации не приняты"),
+];
+
+/// Параметры запроса, которые API называет в сообщении об ошибке, и их пояснения.
+///
+/// Запасной вариант, когда по коду gRPC причину не уточнить: имя параметра сравнивается
+/// с отдельными словами сообщения без учёта регистра, побеждает первое совпадение.
+const KNOWN_API_PARAMETERS: &[(&str, &str)] = &[
+    ("maxtokens", "значение max_tokens превышает лимит модели"),
+    ("max_tokens", "значение max_tokens превышает лимит модели"),
+    ("temperature", "недопустимое значение температуры"),
+    ("modeluri", "неизвестная модель или неверный ID каталога"),
+    ("model_uri", "неизвестная модель или неверный ID каталога"),
+    ("messages", "некорректный или пустой список сообщений"),
+];
+
+/// Сформировать человекочитаемое описание ошибки по телу ответа API.
+///
+/// Если тело — JSON в формате Yandex Cloud, возвращается сообщение из него с пояснением:
+/// сначала по коду gRPC, а если он не задан или означает неверный аргумент — по имени
+/// параметра в сообщении. Иначе — исходный текст без изменений.
+pub(crate) fn describe_api_error(body: &str) -> String {
+    let Ok(parsed) = serde_json::from_str::<crate::models::ApiErrorResponse>(body) else {
+        return body.to_string();
+    };
+
+    let message = parsed.error.message.trim();
+    if message.is_empty() {
+        return body.to_string();
+    }
+
+    match grpc_hint(parsed.error.grpc_code).or_else(|| parameter_hint(message)) {
+        Some(hint) => format!("{} ({})", hint, message),
+        None => message.to_string(),
+    }
+}
+
+/// Пояснение по коду gRPC из [`GRPC_HINTS`].
+fn grpc_hint(code: Option<i32>) -> Option<&'static str> {
+    let code = code?;
+    GRPC_HINTS
+        .iter()
+        .find(|(known, _)| *known == code)
+        .map(|(_, hint)| *hint)
+}
+
+/// Пояснение по имени параметра из [`KNOWN_API_PARAMETERS`], названного в `message`.
+fn parameter_hint(message: &str) -> Option<&'static str> {
+    let lowered = message.to_lowercase();
+    let words: Vec<&str> = lowered
+        .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .collect();
+    KNOWN_API_PARAMETERS
+        .iter()
+        .find(|(name, _)| words.contains(name))
+        .map(|(_, hint)| *hint)
+}
+
+/// Сообщения API о переполнении контекста модели (подстроки без учёта регистра).
+const CONTEXT_OVERFLOW_MESSAGES: &[&str] = &["number of input tokens", "total number of tokens"];
+
+/// Ошибка по HTTP-статусу `code` и телу ответа API.
+///
+/// Переполнение контекста — [`GPTError::ContextLengthExceeded`], остальное —
+/// [`GPTError::APIError`] с описанием из [`describe_api_error`].
+pub(crate) fn api_error(code: i32, body: &str) -> GPTError {
+    if code == 400
+        && let Some(max_tokens) = context_overflow_limit(body)
+    {
+        return GPTError::ContextLengthExceeded {
+            max_tokens,
+            request_id: None,
+        };
+    }
+    GPTError::APIError {
+        code,
+        description: describe_api_error(body),
+    }
+}
+
+/// Размер контекста из ошибки API о его переполнении: `Some(None)`, если это переполнение,
+/// но число в сообщении не найдено; `None` — ошибка другого рода.
+///
+/// API сообщает лимит после слова `than`: «Number of input tokens must be no more than 8192,
+/// got 9000».
+fn context_overflow_limit(body: &str) -> Option<Option<i64>> {
+    let parsed = serde_json::from_str::<crate::models::ApiErrorResponse>(body).ok()?;
+    if parsed
+        .error
+        .grpc_code
+        .is_some_and(|code| code != GRPC_INVALID_ARGUMENT)
+    {
+        return None;
+    }
+    let message = parsed.error.message.to_lowercase();
+    if !CONTEXT_OVERFLOW_MESSAGES
+        .iter()
+        .any(|pattern| message.contains(pattern))
+    {
+        return None;
+    }
+
+    let limit = message.split_once("than").and_then(|(_, rest)| {
+        let digits: String = rest
+            .trim_start()
+            .chars()
+            .take_while(char::is_ascii_digit)
+            .collect();
+        digits.parse().ok()
+    });
+    Some(limit)
+}
+
+/// Разобрать заголовок `Retry-After`, заданный числом секунд.
+///
+/// Форма с HTTP-датой не поддерживается и, как и некорректное значение, даёт `None`.
+pub(crate) fn parse_retry_after(value: &str) -> Option<Duration> {
+    value.trim().parse().ok().map(Duration::from_secs)
+}
+
+impl From<reqwest::Error> for GPTError {
+    fn from(err: reqwest::Error) -> Self {
+        let description = err.to_string();
+        if err.is_connect() || err.is_timeout() {
+            GPTError::Network { description }
+        } else if err.is_decode() {
+            GPTError::ParseError { description }
+        } else {
+            GPTError::Request { description }
+        }
+    }
+}
+
+impl From<serde_json::Error> for GPTError {
+    fn from(err: serde_json::Error) -> Self {
+        GPTError::ParseError {
+            description: err.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_describe_known_validation_error() {
+        let body = r#"{"error":{"grpcCode":3,"httpCode":400,"message":"maxTokens must be less than 8000","httpStatus":"Bad Request","details":[]}}"#;
+        assert_eq!(
+            describe_api_error(body),
+            "значение max_tokens превышает лимит модели (maxTokens must be less than 8000)"
+        );
+    }
+
+    #[test]
+    fn test_context_overflow_is_distinct_error() {
+        let body = r#"{"error":{"grpcCode":3,"httpCode":400,"message":"Number of input tokens must be no more than 8192, got 9342","httpStatus":"Bad Request","details":[]}}"#;
+        assert!(matches!(
+            api_error(400, body),
+            GPTError::ContextLengthExceeded {
+                max_tokens: Some(8192),
+                ..
+            }
+        ));
+
+        let body =
+            r#"{"error":{"httpCode":400,"message":"The total number of tokens is too large"}}"#;
+        assert!(matches!(
+            api_error(400, body),
+            GPTError::ContextLengthExceeded {
+                max_tokens: None,
+                ..
+            }
+        ));
+        assert!(matches!(
+            api_error(
+                400,
+                r#"{"error":{"message":"maxTokens must be less than 8000"}}"#
+            ),
+            GPTError::APIError { code: 400, .. }
+        ));
+    }
+
+    #[test]
+    fn test_grpc_code_takes_precedence_over_text() {
+        // Слово «messages» в тексте не делает ошибку прав ошибкой списка сообщений.
+        let body = r#"{"error":{"grpcCode":7,"httpCode":403,"message":"Permission to use messages API denied"}}"#;
+        assert_eq!(
+            describe_api_error(body),
+            "нет прав на использование модели в этом каталоге \
+             (Permission to use messages API denied)"
+        );
+
+        // Без кода — запасной разбор текста, но только по целым словам.
+        let body = r#"{"error":{"message":"temperature must be in [0, 1]"}}"#;
+        assert!(describe_api_error(body).starts_with("недопустимое значение температуры"));
+        let body = r#"{"error":{"grpcCode":3,"message":"maxtokensperday exceeded"}}"#;
+        assert_eq!(describe_api_error(body), "maxtokensperday exceeded");
+
+        // Переполнение контекста — только для неверного аргумента.
+        let body =
+            r#"{"error":{"grpcCode":8,"message":"Total number of tokens per hour exceeded"}}"#;
+        assert!(matches!(
+            api_error(400, body),
+            GPTError::APIError { code: 400, .. }
+        ));
+    }
+
+    #[test]
+    fn test_describe_unknown_error_keeps_message() {
+        let body = r#"{"error":{"httpCode":400,"message":"Something odd"}}"#;
+        assert_eq!(describe_api_error(body), "Something odd");
+    }
+
+    #[test]
+    fn test_describe_non_json_body_is_verbatim() {
+        assert_eq!(describe_api_error("Bad Gateway"), "Bad Gateway");
+    }
+
+    #[test]
+    fn test_retryable_errors() {
+        let server = GPTError::APIError {
+            code: 503,
+            description: String::new(),
+        };
+        let client = GPTError::APIError {
+            code: 400,
+            description: String::new(),
+        };
+        let network = GPTError::Network {
+            description: "timeout".to_string(),
+        };
+        let config = GPTError::ConfigError {
+            description: String::new(),
+        };
+
+        assert!(server.is_retryable());
+        assert!(network.is_retryable());
+        assert!(GPTError::RateLimited { retry_after: None }.is_retryable());
+        assert!(!client.is_retryable());
+        assert!(!config.is_retryable());
+        assert!(!GPTError::InvalidCredential.is_retryable());
+        assert!(!GPTError::EmptyResponse.is_retryable());
+        assert!(!GPTError::ContentFiltered.is_retryable());
+    }
+
+    #[test]
+    fn test_parse_retry_after_seconds() {
+        assert_eq!(parse_retry_after(" 7 "), Some(Duration::from_secs(7)));
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"), None);
+        assert_eq!(parse_retry_after("-1"), None);
+    }
+
+    #[test]
+    fn test_rate_limited_display_mentions_delay() {
+        let err = GPTError::RateLimited {
+            retry_after: Some(Duration::from_secs(3)),
+        };
+        assert_eq!(
+            err.to_string(),
+            "Превышен лимит запросов к API, повтор возможен через 3 с"
+        );
+    }
+
+    #[test]
+    fn test_serde_error_maps_to_parse_error() {
+        let err = serde_json::from_str::<serde_json::Value>("{").unwrap_err();
+        assert!(matches!(GPTError::from(err), GPTError::ParseError { .. }));
+    }
+
+    #[test]
+    fn test_question_mark_converts_into_crate_result() {
+        fn parse(text: &str) -> Result<serde_json::Value> {
+            Ok(serde_json::from_str(text)?)
+        }
+
+        assert!(parse("{\"ok\": true}").is_ok());
+        assert!(matches!(
+            parse("not json"),
+            Err(GPTError::ParseError { .. })
+        ));
+    }
+}
@@ -1,10 +1,20 @@
 pub mod client;
 pub mod errors;
 pub mod models;
+pub mod session;
+mod stream;
+pub mod transport;
 
 // Реэкспорт наиболее важных типов для удобства.
 pub use client::GPTClient;
-pub use models::{AccessData, ApiRequest, ChatMessage, CompletionOptions, GPTOptions, URL_API};
+pub use errors::{GPTError, Result};
+pub use models::{
+    AccessData, ApiRequest, AuthMethod, ChatMessage, ClientConfig, CompletionOptions, GPTAnswer,
+    GPTOptions, JsonOutput, ModelInfo, StreamEvent, URL_API, URL_BASE, URL_EMBEDDINGS,
+    URL_TOKENIZE, estimate_tokens, known_models, model_token_limit, recent_exchanges,
+};
+pub use session::ChatSession;
+pub use transport::{HttpTransport, Transport, TransportResponse};
 
 // Константы для часто используемых моделей
 pub const MODEL_YANDEXGPT_LATEST: &str = "yandexgpt/latest";
@@ -2,40 +2,368 @@
 
 //! Модели данных для работы с YandexGPT API.
 
+use crate::errors::GPTError;
+use crate::{MODEL_YANDEXGPT_LATEST, MODEL_YANDEXGPT_PRO};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::fmt::Display;
 use std::fs;
-use std::path::PathBuf;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Общая часть адресов API Foundation Models (см. [`GPTClient::with_base_url`]).
+///
+/// [`GPTClient::with_base_url`]: crate::GPTClient::with_base_url
+pub const URL_BASE: &str = "https://llm.api.cloud.yandex.net";
+
+/// Пути API генерации, эмбеддингов и токенизатора относительно [`URL_BASE`].
+pub(crate) const PATH_COMPLETION: &str = "/foundationModels/v1/completion";
+pub(crate) const PATH_EMBEDDINGS: &str = "/foundationModels/v1/textEmbedding";
+pub(crate) const PATH_TOKENIZE: &str = "/foundationModels/v1/tokenize";
 
 pub const URL_API: &str = "https://llm.api.cloud.yandex.net/foundationModels/v1/completion";
 
+/// Адрес API векторных представлений текста (эмбеддингов).
+pub const URL_EMBEDDINGS: &str =
+    "https://llm.api.cloud.yandex.net/foundationModels/v1/textEmbedding";
+
+/// Адрес API токенизатора (см. [`GPTClient::count_tokens`](crate::GPTClient::count_tokens)).
+pub const URL_TOKENIZE: &str = "https://llm.api.cloud.yandex.net/foundationModels/v1/tokenize";
+
+/// Модель эмбеддингов для поисковых запросов.
+pub const EMBEDDING_MODEL_QUERY: &str = "text-search-query/latest";
+
 /// Структура для опций по обработке запросов.
 #[derive(Debug, Clone)]
 pub struct GPTOptions {
     /// Название модели. Например, 'yandexgpt/latest'.
     pub model: String,
+    /// Полный URI модели, заменяющий шаблон `gpt://{каталог}/{model}`.
+    pub model_uri: Option<String>,
     /// "Температура" генерации ответа (условная креативность).
     pub temperature: f32,
     /// Максимальное количество токенов (символов) в ответе.
     pub max_tokens: i64,
+    /// Отдельный лимит токенов ответа для режима чата. Если не задан, действует `max_tokens`.
+    pub chat_max_tokens: Option<i64>,
+    /// Основная системная инструкция (персона). Отправляется первой, перед `system_prompts`.
+    pub system_prompt: Option<String>,
+    /// Системные инструкции, отправляемые перед диалогом в порядке добавления.
+    pub system_prompts: Vec<String>,
+    /// Зерно генерации. Если не задано, в запрос не попадает.
+    pub seed: Option<u64>,
+    /// Сколько вариантов ответа запрашивать. При `1` параметр в запрос не попадает.
+    pub alternatives: u32,
+    /// Формат ответа в JSON. Если не задан, модель отвечает обычным текстом.
+    pub json_output: Option<JsonOutput>,
+    /// Сколько последних обменов (вопрос пользователя и ответы на него) отправлять в режиме
+    /// чата; `0` — без ограничения (см. [`recent_exchanges`]). Системные инструкции
+    /// отправляются всегда и в это число не входят.
+    pub max_history_exchanges: usize,
+}
+
+/// Режим структурированного ответа (см. [`GPTClient::with_json_output`]).
+///
+/// [`GPTClient::with_json_output`]: crate::GPTClient::with_json_output
+#[derive(Debug, Clone, PartialEq)]
+pub enum JsonOutput {
+    /// Ответ — произвольный JSON-объект.
+    Object,
+    /// Ответ — JSON, соответствующий схеме (JSON Schema).
+    Schema(serde_json::Value),
 }
 
 impl Default for GPTOptions {
     fn default() -> Self {
         GPTOptions {
             model: "yandexgpt/latest".to_string(),
+            model_uri: None,
             temperature: 0.7,
             max_tokens: 2000,
+            chat_max_tokens: None,
+            system_prompt: None,
+            system_prompts: Vec::new(),
+            seed: None,
+            alternatives: 1,
+            json_output: None,
+            max_history_exchanges: 10,
+        }
+    }
+}
+
+impl GPTOptions {
+    /// Пресет для воспроизводимых ответов: температура `0.0`, остальное по умолчанию.
+    pub fn deterministic() -> Self {
+        GPTOptions {
+            temperature: 0.0,
+            ..Self::default()
+        }
+    }
+
+    /// Все системные инструкции в порядке отправки: персона, затем добавленные инструкции.
+    pub fn system_messages(&self) -> impl Iterator<Item = &String> {
+        self.system_prompt.iter().chain(&self.system_prompts)
+    }
+}
+
+/// Параметры клиента одной структурой, без цепочки построителей
+/// (см. [`GPTClient::from_config`](crate::GPTClient::from_config)).
+///
+/// Удобна, когда параметры вычисляются динамически, например из настроек интерфейса.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClientConfig {
+    /// Название модели. Например, 'yandexgpt/latest'.
+    pub model: String,
+    /// "Температура" генерации ответа, от `0.0` до `1.0`.
+    pub temperature: f32,
+    /// Максимальное количество токенов в ответе.
+    pub max_tokens: i64,
+    /// Основная системная инструкция (персона).
+    pub system_prompt: Option<String>,
+    /// Таймаут запроса целиком. Если не задан, действует поведение `reqwest` по умолчанию.
+    pub timeout: Option<Duration>,
+    /// Общее число попыток отправки запроса, включая первую.
+    pub retry: u32,
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        let options = GPTOptions::default();
+        Self {
+            model: options.model,
+            temperature: options.temperature,
+            max_tokens: options.max_tokens,
+            system_prompt: None,
+            timeout: None,
+            retry: 1,
+        }
+    }
+}
+
+impl ClientConfig {
+    /// Собрать и проверить параметры (см. [`ClientConfig::validate`]).
+    pub fn try_new(
+        model: &str,
+        temperature: f32,
+        max_tokens: i64,
+        system_prompt: Option<&str>,
+        timeout: Option<Duration>,
+        retry: u32,
+    ) -> Result<Self, GPTError> {
+        let config = Self {
+            model: model.to_string(),
+            temperature,
+            max_tokens,
+            system_prompt: system_prompt.map(str::to_string),
+            timeout,
+            retry,
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Проверить параметры: модель задана, температура от `0.0` до `1.0`, лимит токенов больше
+    /// нуля и не больше лимита модели, хотя бы одна попытка. Ошибка — `GPTError::ConfigError`.
+    pub fn validate(&self) -> Result<(), GPTError> {
+        if self.model.trim().is_empty() {
+            return Err(config_error("модель не задана"));
+        }
+        validate_temperature(self.temperature)?;
+        validate_max_tokens(&self.model, self.max_tokens)?;
+        if self.retry == 0 {
+            return Err(config_error("число попыток должно быть не меньше 1"));
+        }
+        Ok(())
+    }
+}
+
+/// Ошибка конфигурации с описанием `description`.
+fn config_error(description: impl Into<String>) -> GPTError {
+    GPTError::ConfigError {
+        description: description.into(),
+    }
+}
+
+/// Проверить температуру: от `0.0` до `1.0` включительно.
+pub(crate) fn validate_temperature(temperature: f32) -> Result<(), GPTError> {
+    if (0.0..=1.0).contains(&temperature) {
+        Ok(())
+    } else {
+        Err(config_error(format!(
+            "температура должна быть между 0 и 1, получено: {}",
+            temperature
+        )))
+    }
+}
+
+/// Проверить лимит токенов ответа: больше нуля и не больше лимита контекста модели `model`.
+pub(crate) fn validate_max_tokens(model: &str, max_tokens: i64) -> Result<(), GPTError> {
+    if max_tokens <= 0 {
+        return Err(config_error("количество токенов должно быть больше 0"));
+    }
+    match model_token_limit(model) {
+        Some(limit) if max_tokens > limit => Err(config_error(format!(
+            "количество токенов {} превышает лимит модели {} ({})",
+            max_tokens, model, limit
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Лимит контекста (запрос и ответ вместе) в токенах для известных семейств моделей.
+const MODEL_TOKEN_LIMITS: &[(&str, i64)] = &[
+    ("yandexgpt-lite", 8192),
+    ("yandexgpt", 32768),
+    ("yandexgpt-pro", 32768),
+    ("yandexgpt-32k", 32768),
+];
+
+/// Лимит контекста модели в токенах по её имени.
+///
+/// Имя может содержать версию (`yandexgpt-lite/latest`, `yandexgpt/rc`): учитывается только
+/// семейство до `/`. Для неизвестных моделей возвращает `None` — ограничения не проверяются.
+pub fn model_token_limit(model: &str) -> Option<i64> {
+    let family = model.split('/').next().unwrap_or(model);
+    MODEL_TOKEN_LIMITS
+        .iter()
+        .find(|(name, _)| *name == family)
+        .map(|(_, limit)| *limit)
+}
+
+/// Известные модели генерации текста: имя и описание.
+///
+/// Публичного метода API со списком моделей в Yandex Cloud нет, поэтому перечень ведётся вручную.
+const KNOWN_MODELS: &[(&str, &str)] = &[
+    (MODEL_YANDEXGPT_LATEST, "YandexGPT Pro, актуальная версия"),
+    ("yandexgpt/rc", "YandexGPT Pro, версия-кандидат"),
+    ("yandexgpt-lite/latest", "YandexGPT Lite: быстрее и дешевле"),
+    ("yandexgpt-32k/latest", "YandexGPT Pro, контекст 32k"),
+    (MODEL_YANDEXGPT_PRO, "YandexGPT Pro, короткое имя"),
+];
+
+/// Сведения о модели генерации текста.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModelInfo {
+    /// Имя модели для [`GPTClient::with_model`](crate::GPTClient::with_model).
+    pub name: String,
+    /// Краткое описание.
+    pub description: String,
+    /// Лимит контекста в токенах, если он известен (см. [`model_token_limit`]).
+    pub context_limit: Option<i64>,
+}
+
+impl ModelInfo {
+    pub fn new(name: &str, description: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            description: description.to_string(),
+            context_limit: model_token_limit(name),
+        }
+    }
+}
+
+/// Известные модели генерации текста в порядке рекомендации.
+pub fn known_models() -> Vec<ModelInfo> {
+    KNOWN_MODELS
+        .iter()
+        .map(|(name, description)| ModelInfo::new(name, description))
+        .collect()
+}
+
+/// Грубая оценка числа токенов в тексте без обращения к API.
+///
+/// Токенизатор YandexGPT в среднем укладывает в токен около трёх символов русского текста,
+/// поэтому оценка — число символов, делённое на три с округлением вверх.
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(3)
+}
+
+/// Последние `max_exchanges` обменов диалога `messages` — то, что клиент отправляет модели
+/// в режиме чата; `0` — весь диалог.
+///
+/// Обмен начинается с сообщения пользователя и включает следующие за ним ответы, поэтому
+/// обрезанный диалог всегда начинается с сообщения пользователя и ответ без своего вопроса
+/// не отправляется. Диалог, в котором обменов не больше `max_exchanges`, возвращается
+/// целиком, вместе с сообщениями перед первым вопросом (например, приветствием).
+pub fn recent_exchanges(messages: &[ChatMessage], max_exchanges: usize) -> &[ChatMessage] {
+    if max_exchanges == 0 {
+        return messages;
+    }
+    let start = messages
+        .iter()
+        .enumerate()
+        .rev()
+        .filter(|(_, message)| message.role == "user")
+        .nth(max_exchanges - 1);
+    match start {
+        Some((index, _)) if messages[..index].iter().any(|m| m.role == "user") => {
+            &messages[index..]
+        }
+        _ => messages,
+    }
+}
+
+/// Способ авторизации запросов к API Yandex Cloud.
+///
+/// `Debug` не выводит секрет.
+#[derive(Clone, PartialEq, Eq)]
+pub enum AuthMethod {
+    /// API-ключ сервисного аккаунта: `Authorization: Api-Key <key>`.
+    ApiKey(String),
+    /// IAM-токен: `Authorization: Bearer <token>`. Действует около 12 часов.
+    IamToken(String),
+}
+
+impl std::fmt::Debug for AuthMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AuthMethod::ApiKey(_) => f.write_str("ApiKey(****)"),
+            AuthMethod::IamToken(_) => f.write_str("IamToken(****)"),
+        }
+    }
+}
+
+impl AuthMethod {
+    /// Значение заголовка `Authorization`.
+    pub fn header_value(&self) -> String {
+        match self {
+            AuthMethod::ApiKey(key) => format!("Api-Key {}", key),
+            AuthMethod::IamToken(token) => format!("Bearer {}", token),
+        }
+    }
+
+    /// Задан ли секрет (ключ или токен).
+    pub fn is_set(&self) -> bool {
+        match self {
+            AuthMethod::ApiKey(secret) | AuthMethod::IamToken(secret) => !secret.trim().is_empty(),
         }
     }
 }
 
+/// Поля, которые ожидаются в файле данных авторизации.
+const ACCESS_FIELDS: &[&str] = &["id_catalog", "api_key", "folder_id"];
+
 /// Структура для хранения данных авторизации.
-#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+///
+/// `Debug`, как и `Display`, маскирует ключ и токен: клиент с этими данными попадает в логи.
+#[derive(Default, Clone, Deserialize, Serialize)]
 pub struct AccessData {
     pub id_catalog: String,
     pub api_key: String,
+    /// Каталог Yandex Cloud, если он отличается от `id_catalog`.
+    ///
+    /// Когда поле задано, каталог подставляется в `model_uri` (`gpt://{folder_id}/{model}`)
+    /// и передаётся в заголовке `x-folder-id`. Без него используется `id_catalog`,
+    /// а заголовок не отправляется.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub folder_id: Option<String>,
+    /// IAM-токен. Если задан, используется вместо `api_key`.
+    ///
+    /// Токен живёт около 12 часов, поэтому в файл не сохраняется; обновлять его —
+    /// задача вызывающей стороны.
+    #[serde(skip)]
+    pub iam_token: Option<String>,
 }
 
 impl Display for AccessData {
@@ -49,35 +377,148 @@ impl Display for AccessData {
     }
 }
 
+impl std::fmt::Debug for AccessData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AccessData")
+            .field("id_catalog", &self.mask_key(&self.id_catalog))
+            .field("api_key", &self.mask_key(&self.api_key))
+            .field("folder_id", &self.folder_id)
+            .field(
+                "iam_token",
+                &self.iam_token.as_deref().map(|token| self.mask_key(token)),
+            )
+            .finish()
+    }
+}
+
 impl AccessData {
     pub fn new(id_catalog: String, api_key: String) -> Self {
         Self {
             id_catalog,
             api_key,
+            folder_id: None,
+            iam_token: None,
         }
     }
 
+    /// Авторизоваться IAM-токеном вместо API-ключа.
+    pub fn with_iam_token(mut self, token: impl Into<String>) -> Self {
+        self.iam_token = Some(token.into());
+        self
+    }
+
+    /// Способ авторизации: IAM-токен, если он задан, иначе API-ключ.
+    pub fn auth_method(&self) -> AuthMethod {
+        match &self.iam_token {
+            Some(token) => AuthMethod::IamToken(token.clone()),
+            None => AuthMethod::ApiKey(self.api_key.clone()),
+        }
+    }
+
+    /// Указать каталог Yandex Cloud отдельно от `id_catalog`.
+    pub fn with_folder_id(mut self, folder_id: impl Into<String>) -> Self {
+        self.folder_id = Some(folder_id.into());
+        self
+    }
+
+    /// Каталог, в котором выполняются запросы: `folder_id`, а если он не задан — `id_catalog`.
+    pub fn folder(&self) -> &str {
+        self.folder_id.as_deref().unwrap_or(&self.id_catalog)
+    }
+
     pub fn has_data(&self) -> bool {
-        !self.id_catalog.trim().is_empty() && !self.api_key.trim().is_empty()
+        !self.id_catalog.trim().is_empty() && self.auth_method().is_set()
     }
 
     /// Сохранить информацию из созданного экземпляра в файл с параметрами.
-    pub fn save_me(&self, access_file: PathBuf) -> bool {
-        let json = json!({
+    ///
+    /// Данные пишутся во временный файл в том же каталоге, который затем переименовывается
+    /// в `access_file`: прерванная запись не оставляет усечённый файл. В Unix файл доступен
+    /// только владельцу (`0o600`), так как содержит ключ API.
+    ///
+    /// При ошибке записи возвращает `GPTError::ConfigError` с путём к файлу и причиной
+    /// (нет прав, нет места, отсутствует каталог и т.п.).
+    pub fn save_me(&self, access_file: PathBuf) -> Result<(), GPTError> {
+        let mut json = json!({
             "id_catalog": self.id_catalog,
             "api_key": self.api_key,
         });
+        if let Some(folder_id) = &self.folder_id {
+            json["folder_id"] = json!(folder_id);
+        }
+
+        write_private_atomically(&access_file, json.to_string().as_bytes()).map_err(|err| {
+            GPTError::ConfigError {
+                description: format!("не удалось записать {}: {}", access_file.display(), err),
+            }
+        })
+    }
 
-        fs::write(&access_file, json.to_string()).is_ok()
+    /// Загрузить информацию из файла параметров и создать на их основе экземпляр.
+    ///
+    /// Недоступный файл и некорректное содержимое возвращаются как `GPTError::ConfigError`
+    /// с разными описаниями. Предупреждения о неизвестных полях отбрасываются; чтобы их
+    /// получить, используйте [`AccessData::load_checked`].
+    pub fn load_it(access_file: PathBuf) -> Result<Self, GPTError> {
+        Self::load_checked(&access_file).map(|(access, _)| access)
     }
 
-    /// Загрузить информацию из файла параметров (при наличии) и создать на их основе экземпляр.
-    pub fn load_it(access_file: PathBuf) -> Self {
-        let contents = fs::read_to_string(&access_file)
-            .unwrap_or_else(|_| panic!("Файл {} недоступен", access_file.display()));
+    /// Загрузить и проверить файл параметров.
+    ///
+    /// Возвращает данные и список предупреждений (см. [`AccessData::from_json`]).
+    pub fn load_checked(access_file: &Path) -> Result<(Self, Vec<String>), GPTError> {
+        let contents = fs::read_to_string(access_file).map_err(|err| GPTError::ConfigError {
+            description: format!("файл {} недоступен: {}", access_file.display(), err),
+        })?;
+
+        Self::from_json(&contents).map_err(|err| match err {
+            GPTError::ConfigError { description } => GPTError::ConfigError {
+                description: format!("{}: {}", access_file.display(), description),
+            },
+            other => other,
+        })
+    }
+
+    /// Разобрать содержимое файла параметров со строгой проверкой.
+    ///
+    /// Ошибкой считаются некорректный JSON, повторяющиеся ключи и пустые обязательные поля.
+    /// Неизвестные поля не мешают загрузке, но попадают в список предупреждений: чаще всего это
+    /// опечатка в имени ключа.
+    pub fn from_json(contents: &str) -> Result<(Self, Vec<String>), GPTError> {
+        // Типизированный разбор отвергает повторяющиеся ключи, а не берёт последний молча.
+        let access: AccessData =
+            serde_json::from_str(contents).map_err(|err| GPTError::ConfigError {
+                description: format!("ошибка разбора: {}", err),
+            })?;
+
+        if !Self::validator_id_catalog(access.id_catalog.trim()) {
+            return Err(GPTError::ConfigError {
+                description: "поле `id_catalog` не заполнено".to_string(),
+            });
+        }
+        if !Self::validator_api_key(access.api_key.trim()) {
+            return Err(GPTError::ConfigError {
+                description: "поле `api_key` не заполнено".to_string(),
+            });
+        }
+        if access
+            .folder_id
+            .as_deref()
+            .is_some_and(|folder_id| folder_id.trim().is_empty())
+        {
+            return Err(GPTError::ConfigError {
+                description: "поле `folder_id` задано, но пустое".to_string(),
+            });
+        }
+
+        let fields: serde_json::Map<String, serde_json::Value> = serde_json::from_str(contents)?;
+        let warnings = fields
+            .keys()
+            .filter(|key| !ACCESS_FIELDS.contains(&key.as_str()))
+            .map(|key| format!("Неизвестное поле `{}` в файле доступа игнорируется", key))
+            .collect();
 
-        serde_json::from_str(&contents)
-            .unwrap_or_else(|_| panic!("Ошибка парсинга файла {}", access_file.display()))
+        Ok((access, warnings))
     }
 
     /// Проверить корректность предоставленного id_catalog.
@@ -90,13 +531,51 @@ impl AccessData {
         !input.is_empty()
     }
 
+    /// Замаскировать секрет для вывода: видны не более `VISIBLE_CHARS` последних символов.
+    ///
+    /// Короткие значения, в которых открытый хвост составил бы заметную часть секрета,
+    /// маскируются целиком. Длина секрета по маске не угадывается.
     fn mask_key(&self, key: &str) -> String {
-        const VISIBLE_CHARS: usize = 5;
-        if key.len() <= VISIBLE_CHARS {
-            return key.to_string();
+        const MASK: &str = "****";
+        const VISIBLE_CHARS: usize = 4;
+        const MIN_CHARS_TO_REVEAL: usize = 12;
+
+        let chars = key.chars().count();
+        if chars < MIN_CHARS_TO_REVEAL {
+            return MASK.to_string();
+        }
+        let tail: String = key.chars().skip(chars - VISIBLE_CHARS).collect();
+        format!("{}{}", MASK, tail)
+    }
+}
+
+/// Записать `contents` в `path` через временный файл рядом с ним и атомарное переименование.
+///
+/// В Unix права временного файла ограничиваются владельцем до записи данных.
+fn write_private_atomically(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "путь без имени файла"))?;
+    let mut temp_name = file_name.to_os_string();
+    temp_name.push(format!(".tmp-{}", std::process::id()));
+    let temp_path = path.with_file_name(temp_name);
+
+    let result = (|| {
+        let mut file = fs::File::create(&temp_path)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            file.set_permissions(fs::Permissions::from_mode(0o600))?;
         }
-        format!("{}*****", &key[..VISIBLE_CHARS])
+        file.write_all(contents)?;
+        file.sync_all()?;
+        fs::rename(&temp_path, path)
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
     }
+    result
 }
 
 // Структуры для ответов API.
@@ -108,11 +587,124 @@ pub struct ApiResponse {
 #[derive(Deserialize)]
 pub struct ResultField {
     pub alternatives: Vec<Alternative>,
+    /// Расход токенов на запрос. Если API его не прислал, все значения нулевые.
+    #[serde(default)]
+    pub usage: Usage,
+}
+
+/// Расход токенов на запрос и ответ.
+///
+/// API присылает числа строками и в camelCase (`"inputTextTokens": "19"`); принимаются также
+/// числа и snake_case.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct Usage {
+    #[serde(default, alias = "inputTextTokens", deserialize_with = "token_count")]
+    pub input_text_tokens: u32,
+    #[serde(default, alias = "completionTokens", deserialize_with = "token_count")]
+    pub completion_tokens: u32,
+    #[serde(default, alias = "totalTokens", deserialize_with = "token_count")]
+    pub total_tokens: u32,
+}
+
+/// Число токенов из строки или числа JSON.
+fn token_count<'de, D>(deserializer: D) -> Result<u32, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    use serde::de::Error;
+
+    match serde_json::Value::deserialize(deserializer)? {
+        serde_json::Value::String(text) => text.parse().map_err(D::Error::custom),
+        serde_json::Value::Number(number) => number
+            .as_u64()
+            .and_then(|count| u32::try_from(count).ok())
+            .ok_or_else(|| D::Error::custom(format!("недопустимое число токенов: {}", number))),
+        other => Err(D::Error::custom(format!(
+            "ожидалось число токенов, получено: {}",
+            other
+        ))),
+    }
 }
 
+/// Статус альтернативы, когда генерация завершена полностью.
+pub const STATUS_FINAL: &str = "ALTERNATIVE_STATUS_FINAL";
+
+/// Статус альтернативы, когда генерация остановлена лимитом токенов.
+pub const STATUS_TRUNCATED: &str = "ALTERNATIVE_STATUS_TRUNCATED_FINAL";
+
+/// Статус альтернативы, когда ответ прерван фильтром содержимого.
+pub const STATUS_CONTENT_FILTER: &str = "ALTERNATIVE_STATUS_CONTENT_FILTER";
+
 #[derive(Deserialize)]
 pub struct Alternative {
     pub message: Message,
+    /// Статус завершения генерации, например `ALTERNATIVE_STATUS_FINAL`.
+    #[serde(default)]
+    pub status: String,
+}
+
+impl Alternative {
+    /// Проверить, что в варианте есть текст.
+    ///
+    /// Пустой или пробельный текст — ошибка: [`GPTError::ContentFiltered`], если генерацию
+    /// остановил фильтр содержимого, иначе [`GPTError::EmptyResponse`].
+    pub fn non_empty(self) -> Result<Self, GPTError> {
+        if !self.message.text.trim().is_empty() {
+            Ok(self)
+        } else if self.status == STATUS_CONTENT_FILTER {
+            Err(GPTError::ContentFiltered)
+        } else {
+            Err(GPTError::EmptyResponse)
+        }
+    }
+}
+
+/// Ответ модели: текст, статус завершения генерации и расход токенов.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GPTAnswer {
+    pub text: String,
+    /// Ответ обрезан по лимиту `max_tokens` и может быть продолжен.
+    pub truncated: bool,
+    /// Статус завершения генерации из ответа API (см. [`STATUS_FINAL`]).
+    pub status: String,
+    /// Токены запроса, включая системные инструкции и историю.
+    pub input_tokens: u32,
+    /// Токены сгенерированного ответа. Расход общий для запроса: у всех вариантов одного
+    /// ответа значения совпадают.
+    pub completion_tokens: u32,
+}
+
+impl From<Alternative> for GPTAnswer {
+    fn from(alternative: Alternative) -> Self {
+        Self {
+            truncated: alternative.status == STATUS_TRUNCATED,
+            text: alternative.message.text,
+            status: alternative.status,
+            input_tokens: 0,
+            completion_tokens: 0,
+        }
+    }
+}
+
+impl GPTAnswer {
+    /// Добавить к ответу расход токенов на запрос.
+    pub fn with_usage(mut self, usage: Usage) -> Self {
+        self.input_tokens = usage.input_text_tokens;
+        self.completion_tokens = usage.completion_tokens;
+        self
+    }
+}
+
+/// Элемент потокового ответа (см. [`GPTClient::ask_gpt_stream`]).
+///
+/// [`GPTClient::ask_gpt_stream`]: crate::client::GPTClient::ask_gpt_stream
+#[derive(Debug, Clone, PartialEq)]
+pub enum StreamEvent {
+    /// Новый фрагмент текста ответа.
+    Delta(String),
+    /// Генерация завершена: весь текст ответа, статус и расход токенов. Последний элемент
+    /// потока.
+    Done(GPTAnswer),
 }
 
 #[derive(Deserialize)]
@@ -120,23 +712,465 @@ pub struct Message {
     pub text: String,
 }
 
+/// Тело ответа API с ошибкой.
+///
+/// Формат: `{"error": {"grpcCode": 3, "httpCode": 400, "message": "...", "httpStatus": "Bad Request"}}`.
+#[derive(Deserialize)]
+pub struct ApiErrorResponse {
+    pub error: ApiErrorBody,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiErrorBody {
+    #[serde(default)]
+    pub message: String,
+    #[serde(default)]
+    pub http_code: Option<u16>,
+    #[serde(default)]
+    pub grpc_code: Option<i32>,
+}
+
 // Структура для запросов
 #[derive(Serialize)]
 pub struct CompletionOptions {
     pub stream: bool,
     pub temperature: f32,
     pub max_tokens: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    #[serde(skip_serializing_if = "is_single_alternative")]
+    pub num_alternatives: u32,
 }
 
-#[derive(Serialize)]
+/// Один вариант ответа — поведение API по умолчанию, параметр не передаётся.
+fn is_single_alternative(count: &u32) -> bool {
+    *count <= 1
+}
+
+/// Сообщение диалога с явной ролью автора: `user`, `assistant` или `system`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct ChatMessage {
     pub role: String,
     pub text: String,
 }
 
+impl ChatMessage {
+    /// Сообщение пользователя.
+    pub fn user(text: impl Into<String>) -> Self {
+        Self {
+            role: "user".to_string(),
+            text: text.into(),
+        }
+    }
+
+    /// Ответ модели.
+    pub fn assistant(text: impl Into<String>) -> Self {
+        Self {
+            role: "assistant".to_string(),
+            text: text.into(),
+        }
+    }
+}
+
 #[derive(Serialize)]
 pub struct ApiRequest {
     pub model_uri: String,
     pub completion_options: CompletionOptions,
     pub messages: Vec<ChatMessage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub json_object: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub json_schema: Option<JsonSchema>,
+}
+
+/// Схема ответа в запросе к API.
+#[derive(Serialize)]
+pub struct JsonSchema {
+    pub schema: serde_json::Value,
+}
+
+/// Тело запроса к API эмбеддингов.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmbeddingRequest {
+    pub model_uri: String,
+    pub text: String,
+}
+
+/// Ответ API эмбеддингов.
+#[derive(Deserialize)]
+pub struct EmbeddingResponse {
+    pub embedding: Vec<f32>,
+}
+
+/// Тело запроса к API токенизатора.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenizeRequest {
+    pub model_uri: String,
+    pub text: String,
+}
+
+/// Ответ API токенизатора. Нужно только число токенов, поэтому их поля не разбираются.
+#[derive(Deserialize)]
+pub struct TokenizeResponse {
+    #[serde(default)]
+    pub tokens: Vec<serde::de::IgnoredAny>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_client_config_validation() {
+        let config = ClientConfig::try_new("yandexgpt-lite/latest", 0.3, 1000, None, None, 2);
+        assert_eq!(config.unwrap().retry, 2);
+
+        let invalid = [
+            ClientConfig::try_new("", 0.3, 1000, None, None, 1),
+            ClientConfig::try_new("yandexgpt/latest", 1.5, 1000, None, None, 1),
+            ClientConfig::try_new("yandexgpt/latest", 0.3, 0, None, None, 1),
+            ClientConfig::try_new("yandexgpt-lite/latest", 0.3, 10_000, None, None, 1),
+            ClientConfig::try_new("yandexgpt/latest", 0.3, 1000, None, None, 0),
+        ];
+        for result in invalid {
+            assert!(matches!(result, Err(GPTError::ConfigError { .. })));
+        }
+        assert!(ClientConfig::default().validate().is_ok());
+    }
+
+    #[test]
+    fn test_known_models_include_fallbacks() {
+        let models = known_models();
+        let names: Vec<&str> = models.iter().map(|model| model.name.as_str()).collect();
+
+        assert_eq!(names[0], MODEL_YANDEXGPT_LATEST);
+        assert!(names.contains(&MODEL_YANDEXGPT_PRO));
+        assert_eq!(models[0].context_limit, Some(32768));
+        assert!(models.iter().all(|model| !model.description.is_empty()));
+    }
+
+    #[test]
+    fn test_model_token_limit_known_models() {
+        assert_eq!(model_token_limit("yandexgpt-lite/latest"), Some(8192));
+        assert_eq!(model_token_limit("yandexgpt-lite"), Some(8192));
+        assert_eq!(model_token_limit("yandexgpt/latest"), Some(32768));
+        assert_eq!(model_token_limit("yandexgpt-pro"), Some(32768));
+        assert_eq!(model_token_limit("yandexgpt-32k/rc"), Some(32768));
+    }
+
+    #[test]
+    fn test_model_token_limit_unknown_model() {
+        assert_eq!(model_token_limit("llama/latest"), None);
+        assert_eq!(model_token_limit(""), None);
+    }
+
+    #[test]
+    fn test_estimate_tokens_counts_chars_not_bytes() {
+        assert_eq!(estimate_tokens(""), 0);
+        assert_eq!(estimate_tokens("abc"), 1);
+        assert_eq!(estimate_tokens("abcd"), 2);
+        assert_eq!(estimate_tokens("Привет"), 2);
+    }
+
+    #[test]
+    fn test_recent_exchanges_start_at_user_message() {
+        let dialog = [
+            ChatMessage::assistant("Здравствуйте"),
+            ChatMessage::user("1"),
+            ChatMessage::assistant("один"),
+            ChatMessage::user("2"),
+            ChatMessage::assistant("два"),
+            ChatMessage::assistant("дополнение"),
+            ChatMessage::user("3"),
+        ];
+
+        assert_eq!(recent_exchanges(&dialog, 1), &dialog[6..]);
+        // Ответ «один» лимитом отрезан от вопроса и не отправляется без него.
+        assert_eq!(recent_exchanges(&dialog, 2), &dialog[3..]);
+        // Диалог целиком помещается в лимит: приветствие остаётся.
+        assert_eq!(recent_exchanges(&dialog, 3), &dialog[..]);
+        assert_eq!(recent_exchanges(&dialog, 10), &dialog[..]);
+        assert_eq!(recent_exchanges(&dialog, 0), &dialog[..]);
+        assert_eq!(recent_exchanges(&dialog[..1], 3), &dialog[..1]);
+        assert!(recent_exchanges(&[], 3).is_empty());
+    }
+
+    #[test]
+    fn test_answer_truncated_status() {
+        let response: ApiResponse = serde_json::from_str(
+            r#"{"result": {"alternatives": [{"message": {"role": "assistant", "text": "Нача"},
+                "status": "ALTERNATIVE_STATUS_TRUNCATED_FINAL"}]}}"#,
+        )
+        .unwrap();
+        let answer = GPTAnswer::from(response.result.alternatives.into_iter().next().unwrap());
+
+        assert_eq!(answer.text, "Нача");
+        assert!(answer.truncated);
+    }
+
+    #[test]
+    fn test_answer_without_status_is_complete() {
+        let response: ApiResponse =
+            serde_json::from_str(r#"{"result": {"alternatives": [{"message": {"text": "Ок"}}]}}"#)
+                .unwrap();
+        let answer = GPTAnswer::from(response.result.alternatives.into_iter().next().unwrap());
+
+        assert!(!answer.truncated);
+    }
+
+    #[test]
+    fn test_empty_text_alternative_is_an_error() {
+        let response: ApiResponse = serde_json::from_str(
+            r#"{"result": {"alternatives": [
+                {"message": {"role": "assistant", "text": ""},
+                 "status": "ALTERNATIVE_STATUS_CONTENT_FILTER"},
+                {"message": {"role": "assistant", "text": "  \n"},
+                 "status": "ALTERNATIVE_STATUS_FINAL"},
+                {"message": {"role": "assistant", "text": "Ответ"},
+                 "status": "ALTERNATIVE_STATUS_CONTENT_FILTER"}]}}"#,
+        )
+        .unwrap();
+        let mut alternatives = response.result.alternatives.into_iter();
+
+        assert!(matches!(
+            alternatives.next().unwrap().non_empty(),
+            Err(GPTError::ContentFiltered)
+        ));
+        assert!(matches!(
+            alternatives.next().unwrap().non_empty(),
+            Err(GPTError::EmptyResponse)
+        ));
+        // Частичный ответ до срабатывания фильтра сохраняется вместе со статусом.
+        let answer = GPTAnswer::from(alternatives.next().unwrap().non_empty().unwrap());
+        assert_eq!(answer.text, "Ответ");
+        assert_eq!(answer.status, STATUS_CONTENT_FILTER);
+        assert!(!answer.truncated);
+    }
+
+    #[test]
+    fn test_from_json_valid() {
+        let (access, warnings) =
+            AccessData::from_json(r#"{"id_catalog": "b1g", "api_key": "key"}"#).unwrap();
+        assert_eq!(access.id_catalog, "b1g");
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn test_from_json_warns_on_unknown_field() {
+        let (_, warnings) =
+            AccessData::from_json(r#"{"id_catalog": "b1g", "api_key": "key", "apikey": "x"}"#)
+                .unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("apikey"));
+    }
+
+    #[test]
+    fn test_from_json_optional_folder_id() {
+        let (access, warnings) = AccessData::from_json(
+            r#"{"id_catalog": "b1g", "api_key": "key", "folder_id": "b1gfolder"}"#,
+        )
+        .unwrap();
+        assert_eq!(access.folder(), "b1gfolder");
+        assert!(warnings.is_empty());
+
+        let (access, _) =
+            AccessData::from_json(r#"{"id_catalog": "b1g", "api_key": "key"}"#).unwrap();
+        assert_eq!(access.folder_id, None);
+        assert_eq!(access.folder(), "b1g");
+
+        let res =
+            AccessData::from_json(r#"{"id_catalog": "b1g", "api_key": "key", "folder_id": ""}"#);
+        assert!(matches!(res, Err(GPTError::ConfigError { .. })));
+    }
+
+    #[test]
+    fn test_from_json_rejects_duplicate_key() {
+        let res =
+            AccessData::from_json(r#"{"id_catalog": "a", "id_catalog": "b", "api_key": "k"}"#);
+        assert!(matches!(res, Err(GPTError::ConfigError { .. })));
+    }
+
+    #[test]
+    fn test_from_json_rejects_empty_field() {
+        match AccessData::from_json(r#"{"id_catalog": "b1g", "api_key": "  "}"#) {
+            Err(GPTError::ConfigError { description }) => assert!(description.contains("api_key")),
+            other => panic!("Ожидалась ConfigError, получено: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_save_me_reports_unwritable_path() {
+        let access_file = std::env::temp_dir()
+            .join("ym-no-such-dir")
+            .join("nested")
+            .join("access.json");
+        let access = AccessData::new("catalog".to_string(), "key".to_string());
+
+        match access.save_me(access_file) {
+            Err(GPTError::ConfigError { description }) => {
+                assert!(description.contains("access.json"), "{}", description);
+            }
+            other => panic!("Ожидалась ConfigError, получено: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_mask_key_reveals_only_tail() {
+        let access = AccessData::default();
+        assert_eq!(access.mask_key("AQVN1234567890abcd"), "****abcd");
+        assert_eq!(access.mask_key("секретныйключапи"), "****чапи");
+    }
+
+    #[test]
+    fn test_mask_key_hides_short_keys_entirely() {
+        let access = AccessData::default();
+        assert_eq!(access.mask_key("abc"), "****");
+        assert_eq!(access.mask_key("ключ"), "****");
+        assert_eq!(access.mask_key(""), "****");
+    }
+
+    #[test]
+    fn test_display_never_prints_secrets() {
+        let access = AccessData::new(
+            "b1gcatalog0123456".to_string(),
+            "AQVNsecretkey-абвгд".to_string(),
+        );
+        let shown = access.to_string();
+        assert!(!shown.contains("b1gcatalog"), "{}", shown);
+        assert!(!shown.contains("AQVNsecret"), "{}", shown);
+        assert!(shown.contains("****3456"), "{}", shown);
+        assert!(shown.contains("****бвгд"), "{}", shown);
+    }
+
+    #[test]
+    fn test_debug_never_prints_secrets() {
+        let access = AccessData::new(
+            "b1gcatalog0123456".to_string(),
+            "AQVNsecretkey-абвгд".to_string(),
+        )
+        .with_iam_token("t1.iam-token-secret");
+        let shown = format!("{:?} {:?}", access, access.auth_method());
+        for secret in ["b1gcatalog", "AQVNsecret", "t1.iam-token"] {
+            assert!(!shown.contains(secret), "{}", shown);
+        }
+        assert!(shown.contains("IamToken(****)"), "{}", shown);
+    }
+
+    #[test]
+    fn test_save_me_replaces_file_atomically() {
+        let dir = std::env::temp_dir().join(format!("ym-save-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let access_file = dir.join("access.json");
+        fs::write(&access_file, "old").unwrap();
+
+        let access = AccessData::new("catalog".to_string(), "key".to_string());
+        access.save_me(access_file.clone()).unwrap();
+
+        let loaded = AccessData::load_it(access_file.clone()).unwrap();
+        let leftovers = fs::read_dir(&dir).unwrap().count();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(loaded.api_key, "key");
+        assert_eq!(leftovers, 1, "временный файл не удалён");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_save_me_restricts_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let access_file =
+            std::env::temp_dir().join(format!("ym-access-mode-{}.json", std::process::id()));
+        let access = AccessData::new("catalog".to_string(), "key".to_string());
+        access.save_me(access_file.clone()).unwrap();
+
+        let mode = fs::metadata(&access_file).unwrap().permissions().mode();
+        fs::remove_file(&access_file).unwrap();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[test]
+    fn test_auth_method_prefers_iam_token() {
+        let access = AccessData::new("b1g".to_string(), "key".to_string());
+        assert_eq!(access.auth_method().header_value(), "Api-Key key");
+
+        let access = access.with_iam_token("t1.token");
+        assert_eq!(
+            access.auth_method(),
+            AuthMethod::IamToken("t1.token".to_string())
+        );
+        assert_eq!(access.auth_method().header_value(), "Bearer t1.token");
+    }
+
+    #[test]
+    fn test_has_data_accepts_either_method() {
+        let no_key = AccessData::new("b1g".to_string(), String::new());
+        assert!(!no_key.has_data());
+        assert!(no_key.clone().with_iam_token("t1.token").has_data());
+        assert!(!no_key.with_iam_token(" ").has_data());
+    }
+
+    #[test]
+    fn test_save_me_skips_iam_token() {
+        let access_file = std::env::temp_dir().join(format!("ym-iam-{}.json", std::process::id()));
+        AccessData::new("b1g".to_string(), "key".to_string())
+            .with_iam_token("t1.token")
+            .save_me(access_file.clone())
+            .unwrap();
+
+        let saved = fs::read_to_string(&access_file).unwrap();
+        fs::remove_file(&access_file).unwrap();
+        assert!(!saved.contains("t1.token"), "{}", saved);
+    }
+
+    #[test]
+    fn test_load_it_distinguishes_missing_and_corrupt_files() {
+        let dir = std::env::temp_dir();
+        let missing = dir.join(format!("ym-missing-{}.json", std::process::id()));
+        let corrupt = dir.join(format!("ym-corrupt-{}.json", std::process::id()));
+        fs::write(&corrupt, "{not json").unwrap();
+
+        let missing_err = AccessData::load_it(missing).unwrap_err().to_string();
+        let corrupt_err = AccessData::load_it(corrupt.clone())
+            .unwrap_err()
+            .to_string();
+        fs::remove_file(&corrupt).unwrap();
+
+        assert!(missing_err.contains("недоступен"), "{}", missing_err);
+        assert!(corrupt_err.contains("ошибка разбора"), "{}", corrupt_err);
+    }
+
+    #[test]
+    fn test_usage_is_parsed_from_strings() {
+        let parsed: ApiResponse = serde_json::from_str(
+            r#"{"result":{"alternatives":[{"message":{"role":"assistant","text":"Привет"},
+                "status":"ALTERNATIVE_STATUS_FINAL"}],
+                "usage":{"inputTextTokens":"19","completionTokens":"4","totalTokens":"23"},
+                "modelVersion":"23.10.2024"}}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            parsed.result.usage,
+            Usage {
+                input_text_tokens: 19,
+                completion_tokens: 4,
+                total_tokens: 23,
+            }
+        );
+    }
+
+    #[test]
+    fn test_missing_usage_defaults_to_zero() {
+        let parsed: ApiResponse = serde_json::from_str(
+            r#"{"result":{"alternatives":[{"message":{"role":"assistant","text":"ok"}}],
+                "usage":{"input_text_tokens":7}}}"#,
+        )
+        .unwrap();
+
+        assert_eq!(parsed.result.usage.input_text_tokens, 7);
+        assert_eq!(parsed.result.usage.completion_tokens, 0);
+    }
 }
@@ -0,0 +1,137 @@
+//! Диалог с моделью, который сам ведёт историю сообщений.
+//!
+//! [`ChatSession`] хранит реплики с явными ролями: вопрос добавляется как `user`,
+//! ответ модели — как `assistant`, и вызывающему коду не нужно собирать историю вручную.
+
+use crate::client::GPTClient;
+use crate::errors::{GPTError, Result};
+use crate::models::ChatMessage;
+
+/// Диалог с моделью: клиент и упорядоченная история сообщений.
+///
+/// **Пример**
+///
+/// ```rust,no_run
+/// # use ym_yagpt::{ChatSession, GPTClient};
+/// # async fn example(client: GPTClient) -> ym_yagpt::Result<()> {
+/// let mut session = ChatSession::new(client);
+/// session.push_user("Как зовут кота Шрёдингера?");
+/// let answer = session.send().await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct ChatSession {
+    client: GPTClient,
+    messages: Vec<ChatMessage>,
+}
+
+impl ChatSession {
+    /// Новый диалог без сообщений.
+    pub fn new(client: GPTClient) -> Self {
+        Self {
+            client,
+            messages: Vec::new(),
+        }
+    }
+
+    /// Добавить сообщение пользователя. Отправляется при следующем [`ChatSession::send`].
+    pub fn push_user(&mut self, text: impl Into<String>) {
+        self.messages.push(ChatMessage::user(text));
+    }
+
+    /// Отправить историю модели и добавить её ответ в конец.
+    ///
+    /// Последним в истории должно быть сообщение пользователя, иначе — [`GPTError::ConfigError`].
+    /// При ошибке запроса история не меняется: отправку можно повторить.
+    pub async fn send(&mut self) -> Result<&str> {
+        if self.messages.last().is_none_or(|last| last.role != "user") {
+            return Err(GPTError::ConfigError {
+                description: "в диалоге нет нового сообщения пользователя".to_string(),
+            });
+        }
+
+        let answer = self.client.chat_with_gpt(&self.messages).await?;
+        self.messages.push(ChatMessage::assistant(answer));
+        Ok(&self.messages[self.messages.len() - 1].text)
+    }
+
+    /// Очистить историю. Настройки клиента сохраняются.
+    pub fn reset(&mut self) {
+        self.messages.clear();
+    }
+
+    /// Сообщения диалога по порядку.
+    pub fn history(&self) -> &[ChatMessage] {
+        &self.messages
+    }
+
+    /// Клиент, через который идут запросы.
+    pub fn client(&self) -> &GPTClient {
+        &self.client
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::testing::{FakeTransport, RecordedRequests};
+
+    fn session(answers: &[&str]) -> (ChatSession, RecordedRequests) {
+        let transport = FakeTransport::answering(answers);
+        let requests = transport.requests.clone();
+        let client = GPTClient::new()
+            .set_auth("b1gcatalog".to_string(), "secret".to_string())
+            .with_transport(transport);
+        (ChatSession::new(client), requests)
+    }
+
+    #[tokio::test]
+    async fn test_multi_turn_exchange_keeps_roles() {
+        let (mut session, requests) = session(&["Привет!", "Мурзик."]);
+
+        session.push_user("Здравствуй");
+        assert_eq!(session.send().await.unwrap(), "Привет!");
+        session.push_user("Как зовут кота?");
+        assert_eq!(session.send().await.unwrap(), "Мурзик.");
+
+        let roles: Vec<&str> = session.history().iter().map(|m| m.role.as_str()).collect();
+        assert_eq!(roles, ["user", "assistant", "user", "assistant"]);
+
+        let requests = requests.lock().unwrap();
+        let sent = requests[1].body["messages"].as_array().unwrap();
+        assert_eq!(sent.len(), 3);
+        assert_eq!(sent[1]["role"], "assistant");
+        assert_eq!(sent[1]["text"], "Привет!");
+        assert_eq!(sent[2]["text"], "Как зовут кота?");
+    }
+
+    #[tokio::test]
+    async fn test_send_requires_user_message() {
+        let (mut session, requests) = session(&["Ответ"]);
+
+        assert!(matches!(
+            session.send().await,
+            Err(GPTError::ConfigError { .. })
+        ));
+        session.push_user("Вопрос");
+        session.send().await.unwrap();
+        assert!(matches!(
+            session.send().await,
+            Err(GPTError::ConfigError { .. })
+        ));
+        assert_eq!(requests.lock().unwrap().len(), 1);
+
+        session.reset();
+        assert!(session.history().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_failed_send_keeps_history() {
+        let (mut session, _) = session(&[]);
+
+        session.push_user("Вопрос");
+        assert!(session.send().await.is_err());
+        assert_eq!(session.history(), [ChatMessage::user("Вопрос")]);
+    }
+}
@@ -0,0 +1,246 @@
+//! Разбор потокового ответа YandexGPT API.
+//!
+//! При `stream: true` API присылает ответ построчно: каждая строка — JSON того же вида,
+//! что и обычный ответ, с накопленным на текущий момент текстом. Границы сетевых фрагментов
+//! не совпадают с границами строк (и даже символов UTF-8), поэтому декодер копит байты
+//! до перевода строки.
+
+use crate::errors::{GPTError, Result};
+use crate::models::{
+    ApiResponse, GPTAnswer, STATUS_CONTENT_FILTER, STATUS_FINAL, STATUS_TRUNCATED, StreamEvent,
+};
+
+/// Завершает ли статус альтернативы генерацию.
+fn is_final_status(status: &str) -> bool {
+    matches!(
+        status,
+        STATUS_FINAL | STATUS_TRUNCATED | STATUS_CONTENT_FILTER
+    )
+}
+
+/// Превращает фрагменты потокового ответа в приращения текста и итоговый ответ.
+#[derive(Debug, Default)]
+pub(crate) struct StreamDecoder {
+    /// Байты незавершённой строки.
+    buffer: Vec<u8>,
+    /// Текст, уже отданный потребителю.
+    received: String,
+    /// Получена завершающая строка или ошибка: дальнейшие данные игнорируются.
+    finished: bool,
+}
+
+impl StreamDecoder {
+    /// Поток завершён: пришёл финальный статус или ошибка.
+    pub(crate) fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Принять очередной фрагмент и вернуть события из завершённых в нём строк.
+    pub(crate) fn feed(&mut self, chunk: &[u8]) -> Vec<Result<StreamEvent>> {
+        let mut events = Vec::new();
+        if self.finished {
+            return events;
+        }
+        self.buffer.extend_from_slice(chunk);
+
+        while !self.finished
+            && let Some(newline) = self.buffer.iter().position(|&byte| byte == b'\n')
+        {
+            let line: Vec<u8> = self.buffer.drain(..=newline).collect();
+            self.decode_line(&line, &mut events);
+        }
+        events
+    }
+
+    /// Разобрать остаток буфера после закрытия соединения (последняя строка без `\n`).
+    ///
+    /// Если финального статуса так и не пришло, ответ оборван: последним событием идёт
+    /// [`GPTError::Network`].
+    pub(crate) fn finish(&mut self) -> Vec<Result<StreamEvent>> {
+        self.finish_with(GPTError::Network {
+            description: "соединение закрыто до завершения ответа".to_string(),
+        })
+    }
+
+    /// Разобрать остаток буфера, а если ответ так и не завершился — закончить поток ошибкой
+    /// `err`.
+    pub(crate) fn finish_with(&mut self, err: GPTError) -> Vec<Result<StreamEvent>> {
+        let mut events = Vec::new();
+        if self.finished {
+            return events;
+        }
+        let rest = std::mem::take(&mut self.buffer);
+        self.decode_line(&rest, &mut events);
+        if !self.finished {
+            events.push(Err(self.fail_with(err)));
+        }
+        events
+    }
+
+    /// Разобрать одну строку. Пустые строки и строки без нового текста ничего не дают,
+    /// строка с финальным статусом даёт ещё и [`StreamEvent::Done`].
+    fn decode_line(&mut self, line: &[u8], events: &mut Vec<Result<StreamEvent>>) {
+        let line = match std::str::from_utf8(line) {
+            Ok(line) => line.trim(),
+            Err(err) => {
+                let err = self.fail(format!("строка потока не в UTF-8: {}", err));
+                return events.push(Err(err));
+            }
+        };
+        if line.is_empty() {
+            return;
+        }
+
+        let parsed: ApiResponse = match serde_json::from_str(line) {
+            Ok(parsed) => parsed,
+            Err(err) => return events.push(Err(self.fail(err.to_string()))),
+        };
+        let usage = parsed.result.usage;
+        let Some(alternative) = parsed.result.alternatives.into_iter().next() else {
+            return events.push(Err(self.fail_with(GPTError::EmptyResponse)));
+        };
+
+        // API присылает текст целиком, потребителю нужна только новая часть.
+        let text = &alternative.message.text;
+        let delta = text.strip_prefix(self.received.as_str()).unwrap_or(text);
+        if !delta.is_empty() {
+            events.push(Ok(StreamEvent::Delta(delta.to_string())));
+        }
+        self.received.clone_from(text);
+
+        if is_final_status(&alternative.status) {
+            self.finished = true;
+            events.push(alternative.non_empty().map(|alternative| {
+                StreamEvent::Done(GPTAnswer::from(alternative).with_usage(usage))
+            }));
+        }
+    }
+
+    /// Завершить поток ошибкой разбора.
+    fn fail(&mut self, description: String) -> GPTError {
+        self.fail_with(GPTError::ParseError { description })
+    }
+
+    /// Завершить поток ошибкой.
+    fn fail_with(&mut self, err: GPTError) -> GPTError {
+        self.finished = true;
+        err
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(text: &str, status: &str) -> String {
+        format!(
+            "{{\"result\":{{\"alternatives\":[{{\"message\":{{\"role\":\"assistant\",\"text\":\"{}\"}},\"status\":\"{}\"}}]}}}}\n",
+            text, status
+        )
+    }
+
+    /// Приращения текста из событий.
+    fn texts(events: &[Result<StreamEvent>]) -> Vec<String> {
+        events
+            .iter()
+            .filter_map(|event| match event {
+                Ok(StreamEvent::Delta(delta)) => Some(delta.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Итоговый ответ из событий.
+    fn done(events: &[Result<StreamEvent>]) -> Option<&GPTAnswer> {
+        events.iter().find_map(|event| match event {
+            Ok(StreamEvent::Done(answer)) => Some(answer),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn test_cumulative_text_becomes_deltas() {
+        let mut decoder = StreamDecoder::default();
+        let chunk = line("При", "ALTERNATIVE_STATUS_PARTIAL")
+            + &line("Привет", "ALTERNATIVE_STATUS_PARTIAL")
+            + &line("Привет!", STATUS_FINAL);
+
+        let events = decoder.feed(chunk.as_bytes());
+        assert_eq!(texts(&events), ["При", "вет", "!"]);
+        let answer = done(&events).unwrap();
+        assert_eq!((answer.text.as_str(), answer.truncated), ("Привет!", false));
+        assert!(decoder.is_finished());
+    }
+
+    #[test]
+    fn test_final_line_reports_status_and_usage() {
+        let mut decoder = StreamDecoder::default();
+        let chunk = r#"{"result":{"alternatives":[{"message":{"text":"Обрезано"},"status":"ALTERNATIVE_STATUS_TRUNCATED_FINAL"}],"usage":{"inputTextTokens":"12","completionTokens":"3","totalTokens":"15"}}}"#;
+
+        let events = decoder.feed(format!("{chunk}\n").as_bytes());
+        let answer = done(&events).unwrap();
+        assert!(answer.truncated);
+        assert_eq!((answer.input_tokens, answer.completion_tokens), (12, 3));
+    }
+
+    #[test]
+    fn test_line_split_across_chunks_and_inside_utf8() {
+        let mut decoder = StreamDecoder::default();
+        let data = line("Привет", "ALTERNATIVE_STATUS_PARTIAL");
+        let bytes = data.as_bytes();
+        // Разрез посреди двухбайтовой «р».
+        let cut = data.find("р").unwrap() + 1;
+
+        assert!(decoder.feed(&bytes[..cut]).is_empty());
+        assert_eq!(texts(&decoder.feed(&bytes[cut..])), ["Привет"]);
+        assert!(!decoder.is_finished());
+    }
+
+    #[test]
+    fn test_data_after_final_status_is_ignored() {
+        let mut decoder = StreamDecoder::default();
+        let chunk = line("Готово", STATUS_TRUNCATED) + &line("Готово и ещё", STATUS_FINAL);
+
+        let events = decoder.feed(chunk.as_bytes());
+        assert_eq!(texts(&events), ["Готово"]);
+        assert!(done(&events).unwrap().truncated);
+        assert!(decoder.feed(b"{}\n").is_empty());
+        assert!(decoder.finish().is_empty());
+    }
+
+    #[test]
+    fn test_last_line_without_newline_is_decoded_on_finish() {
+        let mut decoder = StreamDecoder::default();
+        let data = line("Хвост", STATUS_FINAL);
+
+        assert!(decoder.feed(data.trim_end().as_bytes()).is_empty());
+        let events = decoder.finish();
+        assert_eq!(texts(&events), ["Хвост"]);
+        assert!(done(&events).is_some());
+    }
+
+    #[test]
+    fn test_stream_closed_before_final_status_is_network_error() {
+        let mut decoder = StreamDecoder::default();
+        let events = decoder.feed(line("Нача", "ALTERNATIVE_STATUS_PARTIAL").as_bytes());
+        assert_eq!(texts(&events), ["Нача"]);
+
+        assert!(matches!(
+            decoder.finish().as_slice(),
+            [Err(GPTError::Network { .. })]
+        ));
+        assert!(decoder.is_finished());
+    }
+
+    #[test]
+    fn test_invalid_line_ends_stream_with_error() {
+        let mut decoder = StreamDecoder::default();
+        let deltas = decoder.feed(b"not json\n");
+
+        assert!(matches!(
+            deltas.as_slice(),
+            [Err(GPTError::ParseError { .. })]
+        ));
+        assert!(decoder.is_finished());
+    }
+}
@@ -0,0 +1,408 @@
+//! Транспорт HTTP-запросов клиента.
+//!
+//! [`GPTClient`](crate::client::GPTClient) отправляет запросы через трейт [`Transport`].
+//! По умолчанию это [`HttpTransport`] на `reqwest`; в тестах его можно заменить подделкой,
+//! которая возвращает заготовленные ответы без сети (см. [`GPTClient::with_transport`]).
+//!
+//! [`GPTClient::with_transport`]: crate::client::GPTClient::with_transport
+
+use std::fmt::{Debug, Formatter};
+use std::pin::Pin;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures::{Stream, StreamExt, TryStreamExt};
+use reqwest::Client;
+use serde::de::DeserializeOwned;
+
+use crate::errors::{GPTError, Result};
+
+/// Тело ответа: фрагменты в порядке получения.
+pub type BodyStream = Pin<Box<dyn Stream<Item = Result<Vec<u8>>> + Send>>;
+
+/// Ответ на HTTP-запрос: статус, заголовки и тело, которое читается по мере поступления.
+pub struct TransportResponse {
+    /// HTTP-статус ответа.
+    pub status: u16,
+    /// Заголовки ответа: имя и значение. Значения не в ASCII отбрасываются.
+    pub headers: Vec<(String, String)>,
+    /// Тело ответа.
+    pub body: BodyStream,
+}
+
+impl TransportResponse {
+    /// Ответ с заранее известным телом.
+    pub fn new(status: u16, body: impl Into<Vec<u8>>) -> Self {
+        let body = body.into();
+        Self {
+            status,
+            headers: Vec::new(),
+            body: Box::pin(futures::stream::once(async move { Ok(body) })),
+        }
+    }
+
+    /// Добавить заголовок ответа.
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Значение заголовка `name` (без учёта регистра имени).
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Статус из диапазона `2xx`.
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    /// Прочитать тело целиком.
+    pub async fn bytes(self) -> Result<Vec<u8>> {
+        self.body.try_concat().await
+    }
+
+    /// Прочитать тело как текст. Некорректные последовательности UTF-8 заменяются.
+    pub async fn text(self) -> Result<String> {
+        Ok(String::from_utf8_lossy(&self.bytes().await?).into_owned())
+    }
+
+    /// Прочитать тело и разобрать его как JSON.
+    pub async fn json<T: DeserializeOwned>(self) -> Result<T> {
+        Ok(serde_json::from_slice(&self.bytes().await?)?)
+    }
+}
+
+impl Debug for TransportResponse {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TransportResponse")
+            .field("status", &self.status)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Способ доставки запросов к API.
+#[async_trait]
+pub trait Transport: Debug + Send + Sync {
+    /// Отправить POST-запрос с JSON-телом `body` и заголовками `headers`.
+    ///
+    /// Сбой соединения возвращается ошибкой, а ответ с любым HTTP-статусом — как `Ok`:
+    /// статус разбирает клиент.
+    async fn post_json(
+        &self,
+        url: &str,
+        headers: &[(&str, String)],
+        body: &serde_json::Value,
+    ) -> Result<TransportResponse>;
+}
+
+/// Транспорт по умолчанию: HTTP-клиент `reqwest`.
+///
+/// Пул соединений и TLS-сессии переиспользуются между запросами и клонами транспорта.
+#[derive(Debug, Clone, Default)]
+pub struct HttpTransport {
+    client: Client,
+}
+
+impl HttpTransport {
+    /// Транспорт без собственного таймаута.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Транспорт с ограничением времени запроса целиком.
+    ///
+    /// Если HTTP-клиент собрать не удалось, возвращается [`GPTError::ConfigError`].
+    pub fn with_timeout(timeout: Duration) -> Result<Self> {
+        Self::with_options(Some(timeout), None)
+    }
+
+    /// Транспорт с необязательными таймаутом и прокси.
+    ///
+    /// Без явного `proxy` действуют переменные окружения `HTTPS_PROXY`, `HTTP_PROXY`
+    /// и `ALL_PROXY` (с учётом `NO_PROXY`). Некорректный адрес прокси — [`GPTError::ConfigError`].
+    pub fn with_options(timeout: Option<Duration>, proxy: Option<&str>) -> Result<Self> {
+        let mut builder = Client::builder();
+        if let Some(timeout) = timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(url) = proxy {
+            let proxy = reqwest::Proxy::all(url).map_err(|err| GPTError::ConfigError {
+                description: format!("не удалось настроить прокси: {err}"),
+            })?;
+            builder = builder.proxy(proxy);
+        }
+        let client = builder.build().map_err(|err| GPTError::ConfigError {
+            description: format!("не удалось создать HTTP-клиент: {err}"),
+        })?;
+        Ok(Self { client })
+    }
+}
+
+#[async_trait]
+impl Transport for HttpTransport {
+    async fn post_json(
+        &self,
+        url: &str,
+        headers: &[(&str, String)],
+        body: &serde_json::Value,
+    ) -> Result<TransportResponse> {
+        let mut request = self.client.post(url).json(body);
+        for (name, value) in headers {
+            request = request.header(*name, value);
+        }
+
+        let response = request.send().await?;
+        let status = response.status().as_u16();
+        let headers = response
+            .headers()
+            .iter()
+            .filter_map(|(name, value)| {
+                let value = value.to_str().ok()?;
+                Some((name.as_str().to_string(), value.to_string()))
+            })
+            .collect();
+        let body = response
+            .bytes_stream()
+            .map(|chunk| chunk.map(|bytes| bytes.to_vec()).map_err(GPTError::from));
+
+        Ok(TransportResponse {
+            status,
+            headers,
+            body: Box::pin(body),
+        })
+    }
+}
+
+/// Поддельный транспорт для тестов клиента и сессии.
+#[cfg(test)]
+pub(crate) mod testing {
+    use std::collections::VecDeque;
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    /// Запрос, полученный поддельным транспортом.
+    #[derive(Debug, Clone)]
+    pub(crate) struct RecordedRequest {
+        pub url: String,
+        pub headers: Vec<(String, String)>,
+        pub body: serde_json::Value,
+    }
+
+    /// Запросы, полученные поддельным транспортом, по порядку.
+    pub(crate) type RecordedRequests = Arc<Mutex<Vec<RecordedRequest>>>;
+
+    /// Поддельный транспорт: отдаёт заготовленные ответы по очереди и запоминает запросы.
+    ///
+    /// Когда ответы кончились, запрос завершается ошибкой сети.
+    #[derive(Debug, Default)]
+    pub(crate) struct FakeTransport {
+        responses: Mutex<VecDeque<TransportResponse>>,
+        pub requests: RecordedRequests,
+    }
+
+    impl FakeTransport {
+        /// Ответы с заданными статусом и телом.
+        pub(crate) fn new(responses: &[(u16, &'static str)]) -> Self {
+            Self::with_responses(
+                responses
+                    .iter()
+                    .map(|&(status, body)| TransportResponse::new(status, body))
+                    .collect(),
+            )
+        }
+
+        /// Успешные ответы модели с текстами `answers`.
+        pub(crate) fn answering(answers: &[&str]) -> Self {
+            Self::with_responses(
+                answers
+                    .iter()
+                    .map(|text| {
+                        let body = serde_json::json!({"result": {"alternatives": [{
+                            "message": {"role": "assistant", "text": text},
+                            "status": "ALTERNATIVE_STATUS_FINAL"
+                        }]}});
+                        TransportResponse::new(200, body.to_string())
+                    })
+                    .collect(),
+            )
+        }
+
+        /// Заготовленные ответы целиком.
+        pub(crate) fn with_responses(responses: Vec<TransportResponse>) -> Self {
+            Self {
+                responses: Mutex::new(responses.into()),
+                requests: Arc::default(),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl Transport for FakeTransport {
+        async fn post_json(
+            &self,
+            url: &str,
+            headers: &[(&str, String)],
+            body: &serde_json::Value,
+        ) -> Result<TransportResponse> {
+            self.requests.lock().unwrap().push(RecordedRequest {
+                url: url.to_string(),
+                headers: headers
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), value.clone()))
+                    .collect(),
+                body: body.clone(),
+            });
+
+            match self.responses.lock().unwrap().pop_front() {
+                Some(response) => Ok(response),
+                None => Err(GPTError::Network {
+                    description: "нет заготовленного ответа".to_string(),
+                }),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_response_reads_chunked_body() {
+        let chunks = futures::stream::iter([Ok(b"{\"a\":".to_vec()), Ok(b"1}".to_vec())]);
+        let response = TransportResponse {
+            status: 200,
+            headers: Vec::new(),
+            body: Box::pin(chunks),
+        };
+
+        let value: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(value["a"], 1);
+    }
+
+    #[test]
+    fn test_header_lookup_ignores_case() {
+        let response = TransportResponse::new(429, "").with_header("Retry-After", "5");
+        assert_eq!(response.header("retry-after"), Some("5"));
+        assert_eq!(response.header("x-request-id"), None);
+    }
+
+    #[test]
+    fn test_invalid_proxy_is_config_error() {
+        let proxy = HttpTransport::with_options(None, Some("http://[::1:3128"));
+        assert!(matches!(proxy, Err(GPTError::ConfigError { .. })));
+        assert!(HttpTransport::with_options(None, Some("http://127.0.0.1:3128")).is_ok());
+        assert!(HttpTransport::with_timeout(Duration::from_secs(5)).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_requests_reuse_connection() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // Сервер с keep-alive считает принятые соединения. Если клиент ушёл на новое
+        // соединение, текущее простаивает: его не ждём дольше секунды.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (mut served, mut connections) = (0, 0);
+            while served < 3 {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                connections += 1;
+                let mut request = [0u8; 4096];
+                while served < 3 {
+                    let wait = Duration::from_secs(1);
+                    match tokio::time::timeout(wait, socket.read(&mut request)).await {
+                        Ok(Ok(read)) if read > 0 => {}
+                        _ => break,
+                    }
+                    socket
+                        .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 7\r\n\r\n{\"a\":1}")
+                        .await
+                        .unwrap();
+                    served += 1;
+                }
+            }
+            connections
+        });
+
+        let transport = HttpTransport::new();
+        for _ in 0..3 {
+            let response = transport
+                .post_json(&url, &[], &serde_json::json!({}))
+                .await
+                .unwrap();
+            let value: serde_json::Value = response.json().await.unwrap();
+            assert_eq!(value["a"], 1);
+        }
+        assert_eq!(server.await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_socks_proxy_is_used() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // Прокси SOCKS5 сам отвечает на HTTP-запрос, который должен был переслать: так видно,
+        // что запрос к недоступному напрямую адресу прошёл через него.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy = format!("socks5h://{}", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut greeting = [0u8; 3];
+            socket.read_exact(&mut greeting).await.unwrap();
+            assert_eq!(greeting, [5, 1, 0]);
+            socket.write_all(&[5, 0]).await.unwrap();
+
+            // CONNECT по доменному имени: VER CMD RSV ATYP LEN имя PORT.
+            let mut head = [0u8; 5];
+            socket.read_exact(&mut head).await.unwrap();
+            assert_eq!(head[..4], [5, 1, 0, 3]);
+            let mut host = vec![0u8; head[4] as usize + 2];
+            socket.read_exact(&mut host).await.unwrap();
+            socket
+                .write_all(&[5, 0, 0, 1, 127, 0, 0, 1, 0, 80])
+                .await
+                .unwrap();
+
+            let mut request = vec![0u8; 4096];
+            let read = socket.read(&mut request).await.unwrap();
+            let request = String::from_utf8_lossy(&request[..read]).to_string();
+            socket
+                .write_all(
+                    b"HTTP/1.1 200 OK\r\ncontent-length: 7\r\nconnection: close\r\n\r\n{\"a\":1}",
+                )
+                .await
+                .unwrap();
+            (
+                String::from_utf8_lossy(&host[..host.len() - 2]).to_string(),
+                request,
+            )
+        });
+
+        let transport = HttpTransport::with_options(None, Some(&proxy)).unwrap();
+        let response = transport
+            .post_json("http://llm.example.invalid/v1", &[], &serde_json::json!({}))
+            .await
+            .unwrap();
+        let value: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(value["a"], 1);
+
+        let (host, request) = server.await.unwrap();
+        assert_eq!(host, "llm.example.invalid");
+        assert!(request.starts_with("POST /v1 HTTP/1.1"), "{}", request);
+    }
+
+    #[tokio::test]
+    async fn test_response_invalid_json_is_parse_error() {
+        let response = TransportResponse::new(200, "not json");
+        assert!(!TransportResponse::new(503, "").is_success());
+        assert!(matches!(
+            response.json::<serde_json::Value>().await,
+            Err(GPTError::ParseError { .. })
+        ));
+    }
+}